@@ -1,120 +1,819 @@
 // asmgen is responsible for generating assembly code from bytecode instructions
 
-use crate::codegen::Instruction;
+use crate::ast::Type;
+use crate::codegen::{Function, Instruction, LabelId, Module};
+use crate::error::{CompilerError, Result};
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Write;
+use std::str::FromStr;
+
+/// C runtime support library linked into every compiled program
+pub const RUNTIME_SOURCE: &str = include_str!("runtime.c");
+
+// Every value on the operand stack and in a variable slot takes 16 bytes:
+// the type tag (low 32 bits, the high 32 bits are spare) followed by the
+// payload. A value on top of the stack has its tag at [rsp] and its payload
+// at [rsp + 8]. Null values always carry a zero payload.
+//
+// Type tags used inline, must match the OX_* constants in runtime.c
+const TYPE_NULL: u64 = 0;
+const TYPE_BOOL: u64 = 1;
+const TYPE_INT: u64 = 2;
+const TYPE_FLOAT: u64 = 3;
+const TYPE_STRING: u64 = 4;
+const TYPE_ARRAY: u64 = 5;
+const TYPE_CLOSURE: u64 = 6;
+const TYPE_OBJECT: u64 = 7;
+
+/// Values with this tag or a higher one are reference-counted, with the count
+/// in the first word of what their payload points to, or of the string header
+const FIRST_COUNTED_TYPE: u64 = TYPE_STRING;
+
+/// Size of the header in front of every string buffer: reference count, capacity and bytes in use
+const STRING_HEADER_SIZE: usize = 24;
+
+/// Size of one value slot in bytes
+const VALUE_SIZE: usize = 16;
+
+/// Offset of the captured values in an `ox_closure`, after its reference count, code and counts
+const CLOSURE_CAPTURES: usize = 24;
+
+/// Built-in functions and the runtime helpers that implement them
+const BUILTINS: [(&str, &str); 2] = [("strlen", "ox_strlen"), ("substr", "ox_substr")];
+
+/// Calling convention the generated assembly follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Windows x64: arguments in rcx, rdx, r8, r9 and 32 bytes of shadow space for the callee
+    WindowsX64,
+    /// System V AMD64 (Linux, BSD): arguments in rdi, rsi, rdx, rcx and no shadow space
+    SysvX64,
+}
+
+impl Target {
+    /// The convention of the machine the compiler runs on
+    pub fn host() -> Self {
+        if cfg!(windows) {
+            Target::WindowsX64
+        } else {
+            Target::SysvX64
+        }
+    }
+
+    /// Name used to select the target on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::WindowsX64 => "windows-x64",
+            Target::SysvX64 => "sysv-x64",
+        }
+    }
+
+    /// Registers used for the first four integer and pointer arguments
+    fn arg_regs(self) -> [&'static str; 4] {
+        match self {
+            Target::WindowsX64 => ["rcx", "rdx", "r8", "r9"],
+            Target::SysvX64 => ["rdi", "rsi", "rdx", "rcx"],
+        }
+    }
+
+    /// Bytes the caller reserves below the return address for the callee
+    fn shadow_space(self) -> usize {
+        match self {
+            Target::WindowsX64 => 32,
+            Target::SysvX64 => 0,
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "windows-x64" => Ok(Target::WindowsX64),
+            "sysv-x64" => Ok(Target::SysvX64),
+            _ => Err(format!("Unknown target '{}', expected windows-x64 or sysv-x64", s)),
+        }
+    }
+}
 
 /// Assembly code generator
 pub struct AsmGenerator {
+    target: Target,
     asm_code: String,
-    string_literals: Vec<String>,
+    string_literals: Vec<String>, // The module's constant pool, emitted as str_<index>
     label_counter: usize,
-    variables: std::collections::HashMap<String, usize>,
+    variables: HashMap<String, usize>,
     var_counter: usize,
+    exit_label: String, // Where Return jumps to in the current function
+    functions: HashMap<String, usize>, // Parameter count of every user function
+    property_counts: HashMap<String, usize>, // Number of properties of every class
+    members: Vec<String>, // Property and method names, sorted; the runtime refers to them by index
+    externs: BTreeSet<String>, // Runtime helpers referenced by the generated code
+}
+
+impl Default for AsmGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AsmGenerator {
-    /// Create a new assembly code generator
+    /// Create a new assembly code generator for the host's calling convention
     pub fn new() -> Self {
+        Self::with_target(Target::host())
+    }
+
+    /// Create a new assembly code generator for the given calling convention
+    pub fn with_target(target: Target) -> Self {
         Self {
+            target,
             asm_code: String::new(),
             string_literals: Vec::new(),
             label_counter: 0,
-            variables: std::collections::HashMap::new(),
+            variables: HashMap::new(),
             var_counter: 0,
+            exit_label: String::new(),
+            functions: HashMap::new(),
+            property_counts: HashMap::new(),
+            members: Vec::new(),
+            externs: BTreeSet::new(),
         }
     }
 
-    /// Generate assembly code from bytecode instructions
-    pub fn generate(&mut self, instructions: &[Instruction]) -> String {
+    /// Generate assembly code for a module
+    pub fn generate(&mut self, module: &Module) -> Result<String> {
         // Clear previous state
         self.asm_code.clear();
-        self.string_literals.clear();
+        self.string_literals = module.constants.strings().to_vec();
         self.label_counter = 0;
-        self.variables.clear();
-        self.var_counter = 0;
+        self.functions = module
+            .functions
+            .values()
+            .map(|function| (function.name.clone(), function.param_count))
+            .collect();
+        self.property_counts = module
+            .classes
+            .values()
+            .map(|class| (class.name.clone(), class.properties.len()))
+            .collect();
+        self.externs.clear();
 
-        // Add assembly header
-        self.add_header();
+        // Members are numbered across the module, including ones that no class declares
+        let mut members: BTreeSet<String> = BTreeSet::new();
+        for class in module.classes.values() {
+            members.extend(class.properties.iter().cloned());
+            members.extend(class.methods.iter().cloned());
+        }
+        let bodies = std::iter::once(&module.main).chain(module.functions.values().map(|f| &f.instructions));
+        for instruction in bodies.flatten() {
+            // Calls to functions that are neither declared nor built in cannot be linked
+            if let Instruction::Call(name, _) = instruction {
+                if !module.functions.contains_key(name) && Self::builtin(name).is_none() {
+                    return Err(CompilerError::CodeGenError {
+                        message: format!("Call to undefined function {}()", name),
+                    });
+                }
+            }
+            if let Instruction::LoadProperty(name)
+            | Instruction::LoadPropertyQuiet(name)
+            | Instruction::LoadPropertyElement(name, _)
+            | Instruction::StorePropertyElement(name, _)
+            | Instruction::CallMethod(name, _) = instruction
+            {
+                members.insert(name.clone());
+            }
+        }
+        self.members = members.into_iter().collect();
 
-        // Process instructions
-        for instruction in instructions {
+        // Top-level code becomes main
+        self.begin_frame("main", &[], &module.main);
+        for instruction in &module.main {
             self.process_instruction(instruction);
         }
 
+        // Add assembly footer
+        self.add_footer();
+
+        // User functions, in a stable order
+        let mut names: Vec<&String> = module.functions.keys().collect();
+        names.sort();
+        for name in names {
+            self.add_function(&module.functions[name]);
+        }
+
         // Add string literals section
         self.add_string_literals();
+        self.add_classes(module);
 
-        // Add assembly footer
-        self.add_footer();
+        // The header lists the runtime helpers, so it is added last
+        let body = std::mem::take(&mut self.asm_code);
+        self.add_header();
+        self.asm_code.push_str(&body);
 
-        self.asm_code.clone()
+        Ok(self.asm_code.clone())
     }
 
     /// Add assembly header
     fn add_header(&mut self) {
-        // Windows x64 calling convention
+        writeln!(self.asm_code, "# Target: {}", self.target.name()).unwrap();
         writeln!(self.asm_code, ".intel_syntax noprefix").unwrap();
+        if self.target == Target::SysvX64 {
+            // The generated code never needs an executable stack
+            writeln!(self.asm_code, ".section .note.GNU-stack,\"\",@progbits").unwrap();
+        }
         writeln!(self.asm_code, ".text").unwrap();
 
         // External functions
-        writeln!(self.asm_code, ".extern printf").unwrap();
-        writeln!(self.asm_code, ".extern putchar").unwrap();
-        writeln!(self.asm_code, ".extern sprintf").unwrap();
+        for name in &self.externs {
+            writeln!(self.asm_code, ".extern {}", name).unwrap();
+        }
 
         // Main function
         writeln!(self.asm_code, ".global main").unwrap();
-        writeln!(self.asm_code, "main:").unwrap();
-        writeln!(self.asm_code, "    push rbp").unwrap();
-        writeln!(self.asm_code, "    mov rbp, rsp").unwrap();
-        writeln!(self.asm_code, "    sub rsp, 256  # Reserve stack space for variables").unwrap();
-        // Windows x64 requires 32 bytes of shadow space
-        writeln!(self.asm_code, "    sub rsp, 32   # Shadow space for Windows x64").unwrap();
-        writeln!(self.asm_code).unwrap();
     }
 
     /// Add assembly footer
     fn add_footer(&mut self) {
         writeln!(self.asm_code, "    # Program exit").unwrap();
-        writeln!(self.asm_code, "    add rsp, 32   # Restore shadow space").unwrap();
+        writeln!(self.asm_code, "{}:", self.exit_label).unwrap();
+        self.emit_release_frame(0);
         writeln!(self.asm_code, "    mov rax, 0  # Return 0").unwrap();
         writeln!(self.asm_code, "    leave").unwrap();
         writeln!(self.asm_code, "    ret").unwrap();
+        writeln!(self.asm_code).unwrap();
+    }
+
+    /// Add a user-defined function
+    ///
+    /// Calling convention: the caller pushes the arguments in reverse order, so the
+    /// first argument sits just above the return address at [rbp + 16]. The callee
+    /// returns the tag of its value in rax and the payload in rdx, and the caller
+    /// removes and releases the arguments; the callee releases its variables.
+    ///
+    /// A closure is called with its `ox_closure` in r10, whose captured values
+    /// start at [r10 + 24] and are bound before the parameters.
+    fn add_function(&mut self, function: &Function) {
+        let label = Self::function_label(&function.name);
+        let variables: Vec<String> = function.captures.iter().chain(&function.params).cloned().collect();
+        self.begin_frame(&label, &variables, &function.instructions);
+
+        // Copy the captured values into their local slots
+        for (i, capture) in function.captures.iter().enumerate() {
+            let offset = self.get_var_offset(capture);
+            let captured = CLOSURE_CAPTURES + i * VALUE_SIZE;
+            writeln!(self.asm_code, "    # Bind captured ${}", capture).unwrap();
+            writeln!(self.asm_code, "    mov rax, [r10 + {}]", captured).unwrap();
+            writeln!(self.asm_code, "    mov [rbp - {}], rax", offset).unwrap();
+            writeln!(self.asm_code, "    mov rax, [r10 + {}]", captured + 8).unwrap();
+            writeln!(self.asm_code, "    mov [rbp - {}], rax", offset - 8).unwrap();
+            self.emit_add_ref(&format!("rbp - {}", offset));
+        }
+
+        // Copy the arguments into their local slots
+        for (i, param) in function.params.iter().enumerate() {
+            let offset = self.get_var_offset(param);
+            let arg = 16 + i * VALUE_SIZE;
+            writeln!(self.asm_code, "    # Bind parameter ${}", param).unwrap();
+            writeln!(self.asm_code, "    mov rax, [rbp + {}]", arg).unwrap();
+            writeln!(self.asm_code, "    mov [rbp - {}], rax", offset).unwrap();
+            writeln!(self.asm_code, "    mov rax, [rbp + {}]", arg + 8).unwrap();
+            writeln!(self.asm_code, "    mov [rbp - {}], rax", offset - 8).unwrap();
+            self.emit_add_ref(&format!("rbp - {}", offset));
+        }
+
+        for instruction in &function.instructions {
+            self.process_instruction(instruction);
+        }
+
+        // Codegen always ends a function with Return, but keep the epilogue reachable anyway
+        writeln!(self.asm_code, "{}:", self.exit_label).unwrap();
+        writeln!(self.asm_code, "    push rdx  # Keep the return value").unwrap();
+        writeln!(self.asm_code, "    push rax").unwrap();
+        self.emit_release_frame(VALUE_SIZE);
+        writeln!(self.asm_code, "    pop rax").unwrap();
+        writeln!(self.asm_code, "    pop rdx").unwrap();
+        writeln!(self.asm_code, "    leave").unwrap();
+        writeln!(self.asm_code, "    ret").unwrap();
+        writeln!(self.asm_code).unwrap();
+    }
+
+    /// Release the variables, and whatever a return inside a loop left on the
+    /// operand stack, from [rsp + offset] up to the frame pointer
+    fn emit_release_frame(&mut self, offset: usize) {
+        let regs = self.target.arg_regs();
+        writeln!(self.asm_code, "    lea {}, [rsp + {}]", regs[0], offset).unwrap();
+        writeln!(self.asm_code, "    mov {}, rbp", regs[1]).unwrap();
+        writeln!(self.asm_code, "    sub {}, {}", regs[1], regs[0]).unwrap();
+        writeln!(self.asm_code, "    shr {}, 4  # Values up to rbp", regs[1]).unwrap();
+        self.emit_call("ox_release_values");
+    }
+
+    /// Emit a function label and prologue, reserving a stack slot for every variable it uses
+    fn begin_frame(&mut self, label: &str, params: &[String], instructions: &[Instruction]) {
+        self.variables.clear();
+        self.var_counter = 0;
+        self.exit_label = format!(".{}_exit", label);
+
+        // Allocate the variable slots up front so the frame size is known
+        for param in params {
+            self.get_var_offset(param);
+        }
+        for instruction in instructions {
+            if let Instruction::LoadVar(name)
+            | Instruction::StoreVar(name)
+            | Instruction::LoadElement(name, _)
+            | Instruction::StoreElement(name, _) = instruction
+            {
+                self.get_var_offset(name);
+            }
+        }
+        // Slots are 16 bytes, so the stack stays 16-byte aligned
+        let frame_size = self.var_counter * VALUE_SIZE;
+
+        writeln!(self.asm_code, "{}:", label).unwrap();
+        writeln!(self.asm_code, "    push rbp").unwrap();
+        writeln!(self.asm_code, "    mov rbp, rsp").unwrap();
+        writeln!(self.asm_code, "    sub rsp, {}  # Reserve stack space for variables", frame_size).unwrap();
+
+        // Variables start out as null
+        for slot in 1..=self.var_counter {
+            writeln!(self.asm_code, "    mov qword ptr [rbp - {}], {}", slot * VALUE_SIZE, TYPE_NULL).unwrap();
+            writeln!(self.asm_code, "    mov qword ptr [rbp - {}], 0", slot * VALUE_SIZE - 8).unwrap();
+        }
+        writeln!(self.asm_code).unwrap();
+    }
+
+    /// Symbol used for a user-defined function; methods are named `Class::method`, which is not a valid symbol
+    fn function_label(name: &str) -> String {
+        format!("php_fn_{}", name.replace("::", "."))
+    }
+
+    /// Runtime helper that implements a built-in function
+    fn builtin(name: &str) -> Option<&'static str> {
+        BUILTINS.iter().find(|(builtin, _)| *builtin == name).map(|(_, runtime)| *runtime)
+    }
+
+    /// Symbol of the `ox_class` of a class
+    fn class_label(name: &str) -> String {
+        format!("php_class_{}", name)
+    }
+
+    /// Number of a property or method name, as the runtime refers to it
+    fn member(&self, name: &str) -> usize {
+        self.members.binary_search_by(|member| member.as_str().cmp(name)).expect("member was collected")
+    }
+
+    /// Assembly label for a bytecode label, which is unique within the module
+    fn jump_label(label: LabelId) -> String {
+        format!(".label_{}", label.0)
+    }
+
+    /// Generate a new internal label
+    fn new_label(&mut self, kind: &str) -> String {
+        let label = format!(".{}_{}", kind, self.label_counter);
+        self.label_counter += 1;
+        label
+    }
+
+    /// Call a runtime helper, passing the addresses of operand stack slots as arguments
+    ///
+    /// Values are 16 bytes and frames are 16-byte aligned, so rsp is always
+    /// aligned between instructions and no realignment is needed here.
+    fn emit_runtime_call(&mut self, name: &str, slot_offsets: &[usize]) {
+        for (reg, offset) in self.target.arg_regs().iter().zip(slot_offsets) {
+            writeln!(self.asm_code, "    lea {}, [rsp + {}]", reg, offset).unwrap();
+        }
+        self.emit_call(name);
+    }
+
+    /// Call a runtime helper whose arguments are already in the argument registers
+    fn emit_call(&mut self, name: &str) {
+        // Windows x64 requires shadow space, a multiple of 16 so rsp stays aligned
+        let shadow = self.target.shadow_space();
+        if shadow > 0 {
+            writeln!(self.asm_code, "    sub rsp, {}   # Shadow space for Windows x64", shadow).unwrap();
+        }
+        writeln!(self.asm_code, "    call {}", name).unwrap();
+        if shadow > 0 {
+            writeln!(self.asm_code, "    add rsp, {}   # Restore shadow space", shadow).unwrap();
+        }
+        self.externs.insert(name.to_string());
+    }
+
+    /// Count one more holder of the value at [slot], clobbering only rax
+    ///
+    /// Every variable, element, property and operand stack slot holds its
+    /// value. Arrays are copied on write: one with more than one holder is
+    /// copied before it is modified, so every variable behaves as if it had
+    /// its own.
+    fn emit_add_ref(&mut self, slot: &str) {
+        let count = self.new_label("count");
+        let done = self.new_label("counted");
+        writeln!(self.asm_code, "    cmp dword ptr [{}], {}  # Counted?", slot, FIRST_COUNTED_TYPE).unwrap();
+        writeln!(self.asm_code, "    jb {}", done).unwrap();
+        writeln!(self.asm_code, "    mov rax, [{} + 8]", slot).unwrap();
+        writeln!(self.asm_code, "    ja {}  # Not a string", count).unwrap();
+        writeln!(self.asm_code, "    sub rax, {}  # String header", STRING_HEADER_SIZE).unwrap();
+        writeln!(self.asm_code, "{}:", count).unwrap();
+        writeln!(self.asm_code, "    inc qword ptr [rax]  # Reference count").unwrap();
+        writeln!(self.asm_code, "{}:", done).unwrap();
+    }
+
+    /// Let go of the value at [slot], which the runtime frees when nothing else holds it
+    fn emit_release(&mut self, slot: &str) {
+        let done = self.new_label("released");
+        writeln!(self.asm_code, "    cmp dword ptr [{}], {}  # Counted?", slot, FIRST_COUNTED_TYPE).unwrap();
+        writeln!(self.asm_code, "    jb {}", done).unwrap();
+        writeln!(self.asm_code, "    lea {}, [{}]", self.target.arg_regs()[0], slot).unwrap();
+        self.emit_call("ox_release");
+        writeln!(self.asm_code, "{}:", done).unwrap();
+    }
+
+    /// Let go of the `count` values from [rsp + offset] up
+    fn emit_release_values(&mut self, offset: usize, count: usize) {
+        let regs = self.target.arg_regs();
+        writeln!(self.asm_code, "    lea {}, [rsp + {}]", regs[0], offset).unwrap();
+        writeln!(self.asm_code, "    mov {}, {}", regs[1], count).unwrap();
+        self.emit_call("ox_release_values");
+    }
+
+    /// Let go of the `count` values from [rsp + offset] up without clobbering rax
+    fn emit_release_keeping_rax(&mut self, offset: usize, count: usize) {
+        writeln!(self.asm_code, "    sub rsp, 16").unwrap();
+        writeln!(self.asm_code, "    mov [rsp], rax").unwrap();
+        if count == 1 {
+            self.emit_release(&format!("rsp + {}", offset + VALUE_SIZE));
+        } else {
+            self.emit_release_values(offset + VALUE_SIZE, count);
+        }
+        writeln!(self.asm_code, "    mov rax, [rsp]").unwrap();
+        writeln!(self.asm_code, "    add rsp, 16").unwrap();
+    }
+
+    /// Pop and let go of the `count` values below the one on top of the stack
+    fn emit_drop_below(&mut self, count: usize) {
+        if count == 0 {
+            return;
+        }
+        let size = count * VALUE_SIZE;
+        self.emit_release_values(VALUE_SIZE, count);
+        writeln!(self.asm_code, "    mov rax, [rsp]").unwrap();
+        writeln!(self.asm_code, "    mov [rsp + {}], rax", size).unwrap();
+        writeln!(self.asm_code, "    mov rax, [rsp + 8]").unwrap();
+        writeln!(self.asm_code, "    mov [rsp + {}], rax", size + 8).unwrap();
+        writeln!(self.asm_code, "    add rsp, {}", size).unwrap();
+    }
+
+    /// Call a runtime helper that computes a value from the `count` operands
+    /// on top of the stack, which then takes their place
+    ///
+    /// The helper borrows the operands, the first one deepest, and writes the
+    /// value to a new slot above them.
+    fn emit_operation(&mut self, name: &str, count: usize) {
+        writeln!(self.asm_code, "    sub rsp, 16  # Result").unwrap();
+        let offsets: Vec<usize> = std::iter::once(0).chain((1..=count).rev().map(|i| i * VALUE_SIZE)).collect();
+        self.emit_runtime_call(name, &offsets);
+        self.emit_drop_below(count);
+    }
+
+    /// Set up the arguments of an array element access: the variable, the
+    /// stack slots above which the keys lie, and which dimensions have keys
+    fn emit_element_args(&mut self, name: &str, dims: &[bool]) {
+        let offset = self.get_var_offset(name);
+        let regs = self.target.arg_regs();
+        writeln!(self.asm_code, "    lea {}, [rbp - {}]  # Variable", regs[0], offset).unwrap();
+        self.emit_dimension_args(dims);
+    }
+
+    /// Set up the arguments of an array element access after the first one,
+    /// which is left to the caller
+    fn emit_dimension_args(&mut self, dims: &[bool]) {
+        let mut keyed = 0u64;
+        for (i, &has_key) in dims.iter().enumerate() {
+            if has_key {
+                keyed |= 1 << i;
+            }
+        }
+        let regs = self.target.arg_regs();
+        writeln!(self.asm_code, "    lea {}, [rsp]  # Stack slots", regs[1]).unwrap();
+        writeln!(self.asm_code, "    mov {}, {}  # Dimensions", regs[2], dims.len()).unwrap();
+        writeln!(self.asm_code, "    mov {}, {}  # Dimensions with a key", regs[3], keyed).unwrap();
+    }
+
+    /// Push a value with the given tag and payload
+    fn emit_push_value(&mut self, tag: u64, payload: &str) {
+        writeln!(self.asm_code, "    mov rax, {}", payload).unwrap();
+        writeln!(self.asm_code, "    push rax  # Payload").unwrap();
+        writeln!(self.asm_code, "    push {}  # Type tag", tag).unwrap();
+    }
+
+    /// Jump to `label` unless both operands on top of the stack are integers
+    fn emit_require_ints(&mut self, label: &str) {
+        writeln!(self.asm_code, "    cmp qword ptr [rsp + 16], {}  # First operand is an int?", TYPE_INT).unwrap();
+        writeln!(self.asm_code, "    jne {}", label).unwrap();
+        writeln!(self.asm_code, "    cmp qword ptr [rsp], {}  # Second operand is an int?", TYPE_INT).unwrap();
+        writeln!(self.asm_code, "    jne {}", label).unwrap();
+    }
+
+    /// Load the int or float at [rsp + offset] into `xmm` as a float, or jump to `fallback`
+    fn emit_load_float(&mut self, offset: usize, xmm: &str, fallback: &str) {
+        let is_float = self.new_label("load_float");
+        let loaded = self.new_label("float_loaded");
+
+        writeln!(self.asm_code, "    cmp qword ptr [rsp + {}], {}  # Float?", offset, TYPE_FLOAT).unwrap();
+        writeln!(self.asm_code, "    je {}", is_float).unwrap();
+        writeln!(self.asm_code, "    cmp qword ptr [rsp + {}], {}  # Int?", offset, TYPE_INT).unwrap();
+        writeln!(self.asm_code, "    jne {}", fallback).unwrap();
+        writeln!(self.asm_code, "    cvtsi2sd {}, qword ptr [rsp + {}]", xmm, offset + 8).unwrap();
+        writeln!(self.asm_code, "    jmp {}", loaded).unwrap();
+        writeln!(self.asm_code, "{}:", is_float).unwrap();
+        writeln!(self.asm_code, "    movsd {}, qword ptr [rsp + {}]", xmm, offset + 8).unwrap();
+        writeln!(self.asm_code, "{}:", loaded).unwrap();
+    }
+
+    /// Emit a binary arithmetic operation
+    ///
+    /// Two integers are handled inline with `int_op`. Ints and floats that are
+    /// not both integers, or whose integer result overflows, are computed as
+    /// floats with the SSE instruction `float_op`. Anything else, such as
+    /// strings or a division by zero, is left to the runtime helper.
+    fn emit_arithmetic(&mut self, int_op: Option<&str>, float_op: Option<&str>, runtime_fn: &str) {
+        let float = self.new_label("arith_float");
+        let slow = self.new_label("arith_slow");
+        let done = self.new_label("arith_done");
+        let end = self.new_label("arith_end");
+
+        match int_op {
+            Some(int_op) => {
+                self.emit_require_ints(&float);
+                writeln!(self.asm_code, "    mov rax, [rsp + 24]  # First operand").unwrap();
+                writeln!(self.asm_code, "    {} rax, [rsp + 8]  # Second operand", int_op).unwrap();
+                writeln!(self.asm_code, "    jo {}  # Overflow turns into a float", float).unwrap();
+                writeln!(self.asm_code, "    mov [rsp + 24], rax").unwrap();
+                writeln!(self.asm_code, "    jmp {}", done).unwrap();
+            }
+            None => {
+                // Without an inline integer operation two ints go to the runtime
+                writeln!(self.asm_code, "    cmp qword ptr [rsp + 16], {}", TYPE_INT).unwrap();
+                writeln!(self.asm_code, "    jne {}", float).unwrap();
+                writeln!(self.asm_code, "    cmp qword ptr [rsp], {}", TYPE_INT).unwrap();
+                writeln!(self.asm_code, "    je {}", slow).unwrap();
+            }
+        }
+        writeln!(self.asm_code, "{}:", float).unwrap();
+        if let Some(float_op) = float_op {
+            self.emit_load_float(16, "xmm0", &slow);
+            self.emit_load_float(0, "xmm1", &slow);
+            if float_op == "divsd" {
+                // Division by zero throws, which the runtime takes care of
+                writeln!(self.asm_code, "    xorpd xmm2, xmm2").unwrap();
+                writeln!(self.asm_code, "    ucomisd xmm1, xmm2").unwrap();
+                writeln!(self.asm_code, "    je {}", slow).unwrap();
+            }
+            writeln!(self.asm_code, "    {} xmm0, xmm1", float_op).unwrap();
+            writeln!(self.asm_code, "    movsd qword ptr [rsp + 24], xmm0").unwrap();
+            writeln!(self.asm_code, "    mov qword ptr [rsp + 16], {}", TYPE_FLOAT).unwrap();
+            writeln!(self.asm_code, "    jmp {}", done).unwrap();
+        }
+        writeln!(self.asm_code, "{}:", slow).unwrap();
+        self.emit_operation(runtime_fn, 2);
+        writeln!(self.asm_code, "    jmp {}", end).unwrap();
+        writeln!(self.asm_code, "{}:", done).unwrap();
+        writeln!(self.asm_code, "    add rsp, 16  # Result replaces the first operand").unwrap();
+        writeln!(self.asm_code, "{}:", end).unwrap();
+    }
+
+    /// Emit a shift, inline when an int is shifted by 0 to 63 bits
+    ///
+    /// Negative shift amounts throw and larger ones shift every bit out,
+    /// which the runtime helper takes care of, as it does other types.
+    fn emit_shift(&mut self, shift_op: &str, runtime_fn: &str) {
+        let slow = self.new_label("shift_slow");
+        let done = self.new_label("shift_done");
+        let end = self.new_label("shift_end");
+
+        self.emit_require_ints(&slow);
+        writeln!(self.asm_code, "    mov rcx, [rsp + 8]  # Shift amount").unwrap();
+        writeln!(self.asm_code, "    cmp rcx, 63").unwrap();
+        writeln!(self.asm_code, "    ja {}  # Negative or 64 and up", slow).unwrap();
+        writeln!(self.asm_code, "    {} qword ptr [rsp + 24], cl", shift_op).unwrap();
+        writeln!(self.asm_code, "    jmp {}", done).unwrap();
+        writeln!(self.asm_code, "{}:", slow).unwrap();
+        self.emit_operation(runtime_fn, 2);
+        writeln!(self.asm_code, "    jmp {}", end).unwrap();
+        writeln!(self.asm_code, "{}:", done).unwrap();
+        writeln!(self.asm_code, "    add rsp, 16  # Result replaces the first operand").unwrap();
+        writeln!(self.asm_code, "{}:", end).unwrap();
+    }
+
+    /// Emit a comparison producing a bool
+    ///
+    /// `int_setcc` compares two integers inline, and `float_test` compares the
+    /// operands as floats in xmm0 and xmm1, leaving the result in al. Otherwise
+    /// the runtime's loose comparison returns -1, 0, 1 (or 2 when unordered) in
+    /// rax, which is tested against `cmp_value` with `result_setcc`.
+    fn emit_comparison(&mut self, int_setcc: &str, float_test: &[&str], cmp_value: i64, result_setcc: &str) {
+        let float = self.new_label("cmp_float");
+        let slow = self.new_label("cmp_slow");
+        let done = self.new_label("cmp_done");
+
+        self.emit_require_ints(&float);
+        writeln!(self.asm_code, "    mov rax, [rsp + 24]  # First operand").unwrap();
+        writeln!(self.asm_code, "    cmp rax, [rsp + 8]  # Second operand").unwrap();
+        writeln!(self.asm_code, "    {} al", int_setcc).unwrap();
+        writeln!(self.asm_code, "    jmp {}", done).unwrap();
+        writeln!(self.asm_code, "{}:", float).unwrap();
+        self.emit_load_float(16, "xmm0", &slow);
+        self.emit_load_float(0, "xmm1", &slow);
+        for line in float_test {
+            writeln!(self.asm_code, "    {}", line).unwrap();
+        }
+        writeln!(self.asm_code, "    jmp {}", done).unwrap();
+        writeln!(self.asm_code, "{}:", slow).unwrap();
+        self.emit_runtime_call("ox_compare", &[16, 0]);
+        self.emit_release_keeping_rax(0, 2);
+        writeln!(self.asm_code, "    cmp rax, {}", cmp_value).unwrap();
+        writeln!(self.asm_code, "    {} al", result_setcc).unwrap();
+        writeln!(self.asm_code, "{}:", done).unwrap();
+        writeln!(self.asm_code, "    movzx eax, al").unwrap();
+        writeln!(self.asm_code, "    mov [rsp + 24], rax").unwrap();
+        writeln!(self.asm_code, "    mov qword ptr [rsp + 16], {}", TYPE_BOOL).unwrap();
+        writeln!(self.asm_code, "    add rsp, 16  # Result replaces the first operand").unwrap();
+    }
+
+    /// Compare the two operands on top of the stack by type and value, replacing them with a bool
+    fn emit_identity(&mut self, negate: bool) {
+        let slow = self.new_label("identical_slow");
+        let done = self.new_label("identical_done");
+
+        self.emit_require_ints(&slow);
+        writeln!(self.asm_code, "    mov rax, [rsp + 24]  # First operand").unwrap();
+        writeln!(self.asm_code, "    cmp rax, [rsp + 8]  # Second operand").unwrap();
+        writeln!(self.asm_code, "    sete al").unwrap();
+        writeln!(self.asm_code, "    movzx eax, al").unwrap();
+        writeln!(self.asm_code, "    jmp {}", done).unwrap();
+        writeln!(self.asm_code, "{}:", slow).unwrap();
+        self.emit_runtime_call("ox_identical", &[16, 0]);
+        self.emit_release_keeping_rax(0, 2);
+        writeln!(self.asm_code, "{}:", done).unwrap();
+        if negate {
+            writeln!(self.asm_code, "    xor rax, 1").unwrap();
+        }
+        writeln!(self.asm_code, "    mov [rsp + 24], rax").unwrap();
+        writeln!(self.asm_code, "    mov qword ptr [rsp + 16], {}", TYPE_BOOL).unwrap();
+        writeln!(self.asm_code, "    add rsp, 16  # Result replaces the first operand").unwrap();
+    }
+
+    /// Compute the truthiness of the stack slot at [rsp + offset] into rax (0 or 1),
+    /// letting go of the value, which the caller overwrites or pops
+    fn emit_truthy(&mut self, offset: usize) {
+        let slow = self.new_label("truthy_slow");
+        let done = self.new_label("truthy_done");
+
+        // Null, bool and int are truthy exactly when their payload is non-zero
+        writeln!(self.asm_code, "    cmp qword ptr [rsp + {}], {}", offset, TYPE_INT).unwrap();
+        writeln!(self.asm_code, "    ja {}", slow).unwrap();
+        writeln!(self.asm_code, "    cmp qword ptr [rsp + {}], 0", offset + 8).unwrap();
+        writeln!(self.asm_code, "    setne al").unwrap();
+        writeln!(self.asm_code, "    movzx eax, al").unwrap();
+        writeln!(self.asm_code, "    jmp {}", done).unwrap();
+        writeln!(self.asm_code, "{}:", slow).unwrap();
+        self.emit_runtime_call("ox_truthy", &[offset]);
+        self.emit_release_keeping_rax(offset, 1);
+        writeln!(self.asm_code, "{}:", done).unwrap();
     }
 
     /// Add string literals section
     fn add_string_literals(&mut self) {
         writeln!(self.asm_code, ".data").unwrap();
 
-        // Format string for printf
-        writeln!(self.asm_code, "fmt_str:").unwrap();
-        writeln!(self.asm_code, "    .string \"%s\"").unwrap();
-        writeln!(self.asm_code, "fmt_int:").unwrap();
-        writeln!(self.asm_code, "    .string \"%d\"").unwrap();
-        writeln!(self.asm_code, "fmt_float:").unwrap();
-        writeln!(self.asm_code, "    .string \"%f\"").unwrap();
-
-        // Add string literals
+        // Add string literals, each preceded by the runtime's string header: a
+        // reference count, a capacity of 0 that marks them read-only and never
+        // freed, and their length
         for (i, s) in self.string_literals.iter().enumerate() {
-            let escaped = s.replace("\"", "\\\"");
+            writeln!(self.asm_code, "    .balign 8").unwrap();
+            writeln!(self.asm_code, "    .quad 0, 0, {}", s.len()).unwrap();
             writeln!(self.asm_code, "str_{}:", i).unwrap();
-            writeln!(self.asm_code, "    .string \"{}\"", escaped).unwrap();
+            writeln!(self.asm_code, "    .ascii \"{}\"", Self::escape_string(s)).unwrap();
         }
     }
 
-    /// Generate a new label
-    fn new_label(&mut self) -> String {
-        let label = format!("label_{}", self.label_counter);
-        self.label_counter += 1;
-        label
+    /// Add the `ox_class` of every class and the names of all members
+    ///
+    /// A class points to the member numbers of its properties and to a table
+    /// with an `ox_method` for every member number that is one of its methods.
+    fn add_classes(&mut self, module: &Module) {
+        let mut names: Vec<&String> = module.classes.keys().collect();
+        names.sort();
+        for name in names {
+            let class = &module.classes[name];
+            let label = Self::class_label(name);
+            writeln!(self.asm_code, "    .balign 8").unwrap();
+            writeln!(self.asm_code, "{}:", label).unwrap();
+            writeln!(
+                self.asm_code,
+                "    .quad {0}_name, {1}, {0}_properties, {0}_property_types, {0}_methods",
+                label,
+                class.properties.len()
+            )
+            .unwrap();
+            writeln!(self.asm_code, "{}_properties:", label).unwrap();
+            for property in &class.properties {
+                writeln!(self.asm_code, "    .quad {}  # {}", self.member(property), property).unwrap();
+            }
+            // Each type is a tag, a name, which is 0 for properties that take any value,
+            // whether the property starts out uninitialized and whether it may hold null
+            writeln!(self.asm_code, "{}_property_types:", label).unwrap();
+            for (i, declared) in class.property_types.iter().enumerate() {
+                let (type_, nullable) = match declared {
+                    Some(Type::Nullable(inner)) => (Some(&**inner), 1),
+                    type_ => (type_.as_ref(), 0),
+                };
+                let tag = match type_ {
+                    Some(Type::Integer) => TYPE_INT,
+                    Some(Type::Float) => TYPE_FLOAT,
+                    Some(Type::String) => TYPE_STRING,
+                    Some(Type::Boolean) => TYPE_BOOL,
+                    Some(Type::Array) => TYPE_ARRAY,
+                    Some(Type::Closure) => TYPE_CLOSURE,
+                    Some(Type::Object(_)) => TYPE_OBJECT,
+                    Some(Type::Null | Type::Nullable(_) | Type::Mixed) | None => TYPE_NULL,
+                };
+                let uninitialized = class.starts_uninitialized(i) as u8;
+                match type_ {
+                    Some(Type::Mixed) | None => {
+                        writeln!(self.asm_code, "    .quad {}, 0, {}, {}", tag, uninitialized, nullable).unwrap()
+                    }
+                    Some(_) => writeln!(
+                        self.asm_code,
+                        "    .quad {}, {}_type_{}, {}, {}",
+                        tag, label, i, uninitialized, nullable
+                    )
+                    .unwrap(),
+                }
+            }
+            writeln!(self.asm_code, "{}_methods:", label).unwrap();
+            for member in &self.members {
+                if class.methods.contains(member) {
+                    writeln!(self.asm_code, "    .quad {}_method_{}", label, member).unwrap();
+                } else {
+                    writeln!(self.asm_code, "    .quad 0").unwrap();
+                }
+            }
+            for method in &class.methods {
+                let function = class.function_name(method);
+                // The object is passed as an extra first argument
+                let param_count = self.functions.get(&function).map_or(1, |&count| count) - 1;
+                writeln!(self.asm_code, "{}_method_{}:", label, method).unwrap();
+                writeln!(self.asm_code, "    .quad {}, {}", Self::function_label(&function), param_count).unwrap();
+            }
+            writeln!(self.asm_code, "{}_name:", label).unwrap();
+            writeln!(self.asm_code, "    .asciz \"{}\"", Self::escape_string(name)).unwrap();
+            for (i, type_) in class.property_types.iter().enumerate() {
+                if let Some(type_) = type_.as_ref().filter(|type_| **type_ != Type::Mixed) {
+                    writeln!(self.asm_code, "{}_type_{}:", label, i).unwrap();
+                    writeln!(self.asm_code, "    .asciz \"{}\"", Self::escape_string(&type_.name())).unwrap();
+                }
+            }
+        }
+
+        // Member names are strings with a header, like the literals, so the runtime can use them as array keys
+        writeln!(self.asm_code, "    .balign 8").unwrap();
+        writeln!(self.asm_code, ".global ox_member_names").unwrap();
+        writeln!(self.asm_code, "ox_member_names:").unwrap();
+        for i in 0..self.members.len() {
+            writeln!(self.asm_code, "    .quad member_{}", i).unwrap();
+        }
+        for (i, member) in self.members.iter().enumerate() {
+            writeln!(self.asm_code, "    .balign 8").unwrap();
+            writeln!(self.asm_code, "    .quad 0, 0, {}", member.len()).unwrap();
+            writeln!(self.asm_code, "member_{}:", i).unwrap();
+            writeln!(self.asm_code, "    .asciz \"{}\"", Self::escape_string(member)).unwrap();
+        }
+    }
+
+    /// Escape a string for use in an `.ascii` directive
+    fn escape_string(s: &str) -> String {
+        let mut escaped = String::new();
+        for byte in s.bytes() {
+            match byte {
+                b'"' => escaped.push_str("\\\""),
+                b'\\' => escaped.push_str("\\\\"),
+                0x20..=0x7e => escaped.push(byte as char),
+                _ => write!(escaped, "\\{:03o}", byte).unwrap(),
+            }
+        }
+        escaped
     }
 
     /// Get the stack offset for a variable
     fn get_var_offset(&mut self, name: &str) -> usize {
         // If the variable doesn't exist, allocate a new offset
         if !self.variables.contains_key(name) {
-            // Each variable takes 8 bytes (64-bit)
-            // Start at offset 8 to account for saved rbp
-            let offset = 8 + (self.var_counter * 8);
+            // Each variable takes one 16-byte slot below the saved rbp; the
+            // offset points at the tag, the payload follows 8 bytes higher
+            let offset = (self.var_counter + 1) * VALUE_SIZE;
             self.variables.insert(name.to_string(), offset);
             self.var_counter += 1;
         }
@@ -128,340 +827,483 @@ impl AsmGenerator {
         match instruction {
             Instruction::PushInt(value) => {
                 writeln!(self.asm_code, "    # PushInt({})", value).unwrap();
-                writeln!(self.asm_code, "    mov rax, {}", value).unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_push_value(TYPE_INT, &value.to_string());
             }
             Instruction::PushFloat(value) => {
                 writeln!(self.asm_code, "    # PushFloat({})", value).unwrap();
-                // For simplicity, we'll just convert to int
-                writeln!(self.asm_code, "    mov rax, {}", *value as i64).unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                // The payload is the IEEE 754 bit pattern
+                self.emit_push_value(TYPE_FLOAT, &format!("0x{:016x}", value.to_bits()));
             }
-            Instruction::PushString(value) => {
-                writeln!(self.asm_code, "    # PushString(\"{}\")", value).unwrap();
-                let str_index = self.string_literals.len();
-                self.string_literals.push(value.clone());
-                writeln!(self.asm_code, "    lea rax, [rip + str_{}]", str_index).unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+            Instruction::PushString(index) => {
+                // Every constant in the pool is emitted as str_<index>
+                let value = &self.string_literals[*index];
+                writeln!(self.asm_code, "    # PushString(\"{}\")", value.escape_debug()).unwrap();
+                writeln!(self.asm_code, "    lea rax, [rip + str_{}]", index).unwrap();
+                writeln!(self.asm_code, "    push rax  # Payload").unwrap();
+                // The length goes in the upper half of the tag word
+                let tag = TYPE_STRING | ((value.len() as u64) << 32);
+                writeln!(self.asm_code, "    mov rax, 0x{:x}", tag).unwrap();
+                writeln!(self.asm_code, "    push rax  # Type tag and length").unwrap();
             }
             Instruction::PushBool(value) => {
                 writeln!(self.asm_code, "    # PushBool({})", value).unwrap();
-                writeln!(self.asm_code, "    mov rax, {}", if *value { 1 } else { 0 }).unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_push_value(TYPE_BOOL, if *value { "1" } else { "0" });
             }
             Instruction::PushNull => {
                 writeln!(self.asm_code, "    # PushNull").unwrap();
-                writeln!(self.asm_code, "    mov rax, 0").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_push_value(TYPE_NULL, "0");
             }
             Instruction::Pop => {
                 writeln!(self.asm_code, "    # Pop").unwrap();
-                writeln!(self.asm_code, "    add rsp, 8").unwrap();
+                self.emit_release("rsp");
+                writeln!(self.asm_code, "    add rsp, 16").unwrap();
+            }
+            Instruction::Dup => {
+                writeln!(self.asm_code, "    # Dup").unwrap();
+                writeln!(self.asm_code, "    push qword ptr [rsp + 8]  # Payload").unwrap();
+                writeln!(self.asm_code, "    push qword ptr [rsp + 8]  # Type tag").unwrap();
+                self.emit_add_ref("rsp");
             }
             Instruction::CreateArray => {
                 writeln!(self.asm_code, "    # CreateArray").unwrap();
-                // For simplicity, we'll just allocate a fixed-size array on the stack
-                // In a real implementation, we would need to allocate memory on the heap
-                writeln!(self.asm_code, "    sub rsp, 64  # Allocate space for array").unwrap();
-                writeln!(self.asm_code, "    mov rax, rsp  # Store array pointer").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                // Arrays live on the heap, the value only holds a pointer
+                self.emit_push_value(TYPE_NULL, "0");
+                self.emit_runtime_call("ox_array_new", &[0]);
             }
             Instruction::ArrayPush => {
                 writeln!(self.asm_code, "    # ArrayPush").unwrap();
-                // For simplicity, we'll just store the value at a fixed offset
-                // In a real implementation, we would need to track the array size
-                writeln!(self.asm_code, "    pop rax  # Value to push").unwrap();
-                writeln!(self.asm_code, "    pop rdx  # Array pointer").unwrap();
-                writeln!(self.asm_code, "    mov [rdx], rax  # Store value in array").unwrap();
-                writeln!(self.asm_code, "    push rdx  # Push array pointer back").unwrap();
+                // Stack: array, value
+                self.emit_runtime_call("ox_array_push", &[16, 0]);
+                self.emit_release("rsp");
+                writeln!(self.asm_code, "    add rsp, 16  # Leave the array on the stack").unwrap();
             }
             Instruction::ArraySet => {
                 writeln!(self.asm_code, "    # ArraySet").unwrap();
-                // For simplicity, we'll just store the value at the key offset
-                // In a real implementation, we would need to handle string keys
-                writeln!(self.asm_code, "    pop rdx  # Key").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Value").unwrap();
-                writeln!(self.asm_code, "    pop rcx  # Array pointer").unwrap();
-                writeln!(self.asm_code, "    mov [rcx + rdx * 8], rax  # Store value at key offset").unwrap();
-                writeln!(self.asm_code, "    push rcx  # Push array pointer back").unwrap();
+                // Stack: array, value, key
+                self.emit_runtime_call("ox_array_set", &[32, 0, 16]);
+                self.emit_release_values(0, 2);
+                writeln!(self.asm_code, "    add rsp, 32  # Leave the array on the stack").unwrap();
             }
             Instruction::ArrayGet => {
                 writeln!(self.asm_code, "    # ArrayGet").unwrap();
-                // For simplicity, we'll just load the value at the key offset
-                // In a real implementation, we would need to handle string keys
-                writeln!(self.asm_code, "    pop rdx  # Key").unwrap();
-                writeln!(self.asm_code, "    pop rcx  # Array pointer").unwrap();
-                writeln!(self.asm_code, "    mov rax, [rcx + rdx * 8]  # Load value at key offset").unwrap();
-                writeln!(self.asm_code, "    push rax  # Push value").unwrap();
+                // Stack: array, key
+                self.emit_operation("ox_array_get", 2);
+            }
+            Instruction::ArrayGetQuiet => {
+                writeln!(self.asm_code, "    # ArrayGetQuiet").unwrap();
+                // Stack: array, key
+                self.emit_operation("ox_array_get_quiet", 2);
+            }
+            Instruction::IterInit => {
+                writeln!(self.asm_code, "    # IterInit").unwrap();
+                // The iterator stays on the stack for the whole loop
+                self.emit_runtime_call("ox_iter_init", &[0]);
+            }
+            Instruction::IterNext(label) => {
+                writeln!(self.asm_code, "    # IterNext({})", label).unwrap();
+                let next = self.new_label("iter_next");
+                writeln!(self.asm_code, "    sub rsp, 32  # Room for the value and the key").unwrap();
+                self.emit_runtime_call("ox_iter_next", &[32, 16, 0]);
+                writeln!(self.asm_code, "    test rax, rax").unwrap();
+                writeln!(self.asm_code, "    jnz {}", next).unwrap();
+                writeln!(self.asm_code, "    add rsp, 48  # Done, pop the iterator the runtime released").unwrap();
+                writeln!(self.asm_code, "    jmp {}", Self::jump_label(*label)).unwrap();
+                writeln!(self.asm_code, "{}:", next).unwrap();
+            }
+            Instruction::LoadElement(name, dims) => {
+                writeln!(self.asm_code, "    # LoadElement(\"{}\", {:?})", name, dims).unwrap();
+                // The element is read into a new slot on top of the keys
+                self.emit_push_value(TYPE_NULL, "0");
+                self.emit_element_args(name, dims);
+                self.emit_call("ox_load_element");
+            }
+            Instruction::StoreElement(name, dims) => {
+                writeln!(self.asm_code, "    # StoreElement(\"{}\", {:?})", name, dims).unwrap();
+                self.emit_element_args(name, dims);
+                self.emit_call("ox_store_element");
+                // The value stays on the stack, above the keys
+                self.emit_drop_below(dims.iter().filter(|&&has_key| has_key).count());
             }
             Instruction::Add => {
                 writeln!(self.asm_code, "    # Add").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    add rax, rbx").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_arithmetic(Some("add"), Some("addsd"), "ox_add");
             }
             Instruction::Subtract => {
                 writeln!(self.asm_code, "    # Subtract").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    sub rbx, rax").unwrap();
-                writeln!(self.asm_code, "    push rbx").unwrap();
+                self.emit_arithmetic(Some("sub"), Some("subsd"), "ox_subtract");
             }
             Instruction::Multiply => {
                 writeln!(self.asm_code, "    # Multiply").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    imul rbx").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_arithmetic(Some("imul"), Some("mulsd"), "ox_multiply");
             }
             Instruction::Divide => {
                 writeln!(self.asm_code, "    # Divide").unwrap();
-                writeln!(self.asm_code, "    pop rcx  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rax  # First operand").unwrap();
-                writeln!(self.asm_code, "    cqo  # Sign-extend RAX into RDX:RAX").unwrap();
-                writeln!(self.asm_code, "    idiv rcx").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                // Dividing two ints may or may not produce an int, so that is left to the runtime
+                self.emit_arithmetic(None, Some("divsd"), "ox_divide");
             }
             Instruction::Modulo => {
                 writeln!(self.asm_code, "    # Modulo").unwrap();
-                writeln!(self.asm_code, "    pop rcx  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rax  # First operand").unwrap();
-                writeln!(self.asm_code, "    cqo  # Sign-extend RAX into RDX:RAX").unwrap();
-                writeln!(self.asm_code, "    idiv rcx").unwrap();
-                writeln!(self.asm_code, "    push rdx  # Remainder is in RDX").unwrap();
+                // Modulo works on integers, converting floats first
+                self.emit_arithmetic(None, None, "ox_modulo");
+            }
+            Instruction::Power => {
+                writeln!(self.asm_code, "    # Power").unwrap();
+                // Exponentiation is left to the runtime for every type
+                self.emit_arithmetic(None, None, "ox_power");
             }
             Instruction::Negate => {
                 writeln!(self.asm_code, "    # Negate").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Operand").unwrap();
-                writeln!(self.asm_code, "    neg rax").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                let not_int = self.new_label("negate_not_int");
+                let slow = self.new_label("negate_slow");
+                let done = self.new_label("negate_done");
+                writeln!(self.asm_code, "    cmp qword ptr [rsp], {}  # Operand is an int?", TYPE_INT).unwrap();
+                writeln!(self.asm_code, "    jne {}", not_int).unwrap();
+                writeln!(self.asm_code, "    neg qword ptr [rsp + 8]").unwrap();
+                writeln!(self.asm_code, "    jno {}", done).unwrap();
+                // Negating the smallest integer overflows; undo and let the runtime make a float
+                writeln!(self.asm_code, "    neg qword ptr [rsp + 8]").unwrap();
+                writeln!(self.asm_code, "    jmp {}", slow).unwrap();
+                writeln!(self.asm_code, "{}:", not_int).unwrap();
+                // A float is negated by flipping its sign bit
+                writeln!(self.asm_code, "    cmp qword ptr [rsp], {}  # Operand is a float?", TYPE_FLOAT).unwrap();
+                writeln!(self.asm_code, "    jne {}", slow).unwrap();
+                writeln!(self.asm_code, "    btc qword ptr [rsp + 8], 63").unwrap();
+                writeln!(self.asm_code, "    jmp {}", done).unwrap();
+                writeln!(self.asm_code, "{}:", slow).unwrap();
+                self.emit_operation("ox_negate", 1);
+                writeln!(self.asm_code, "{}:", done).unwrap();
+            }
+            Instruction::BitwiseAnd => {
+                writeln!(self.asm_code, "    # BitwiseAnd").unwrap();
+                // Logical instructions never overflow, so only two ints stay inline
+                self.emit_arithmetic(Some("and"), None, "ox_bitwise_and");
+            }
+            Instruction::BitwiseOr => {
+                writeln!(self.asm_code, "    # BitwiseOr").unwrap();
+                self.emit_arithmetic(Some("or"), None, "ox_bitwise_or");
+            }
+            Instruction::BitwiseXor => {
+                writeln!(self.asm_code, "    # BitwiseXor").unwrap();
+                self.emit_arithmetic(Some("xor"), None, "ox_bitwise_xor");
+            }
+            Instruction::ShiftLeft => {
+                writeln!(self.asm_code, "    # ShiftLeft").unwrap();
+                self.emit_shift("shl", "ox_shift_left");
+            }
+            Instruction::ShiftRight => {
+                writeln!(self.asm_code, "    # ShiftRight").unwrap();
+                self.emit_shift("sar", "ox_shift_right");
+            }
+            Instruction::BitwiseNot => {
+                writeln!(self.asm_code, "    # BitwiseNot").unwrap();
+                let slow = self.new_label("not_slow");
+                let done = self.new_label("not_done");
+                writeln!(self.asm_code, "    cmp qword ptr [rsp], {}  # Operand is an int?", TYPE_INT).unwrap();
+                writeln!(self.asm_code, "    jne {}", slow).unwrap();
+                writeln!(self.asm_code, "    not qword ptr [rsp + 8]").unwrap();
+                writeln!(self.asm_code, "    jmp {}", done).unwrap();
+                writeln!(self.asm_code, "{}:", slow).unwrap();
+                self.emit_operation("ox_bitwise_not", 1);
+                writeln!(self.asm_code, "{}:", done).unwrap();
+            }
+            Instruction::Increment | Instruction::Decrement => {
+                writeln!(self.asm_code, "    # {:?}", instruction).unwrap();
+                let (op, runtime) = if *instruction == Instruction::Increment {
+                    ("add", "ox_increment")
+                } else {
+                    ("sub", "ox_decrement")
+                };
+                let slow = self.new_label("step_slow");
+                let done = self.new_label("step_done");
+                writeln!(self.asm_code, "    cmp qword ptr [rsp], {}  # Operand is an int?", TYPE_INT).unwrap();
+                writeln!(self.asm_code, "    jne {}", slow).unwrap();
+                writeln!(self.asm_code, "    {} qword ptr [rsp + 8], 1", op).unwrap();
+                writeln!(self.asm_code, "    jno {}", done).unwrap();
+                // The largest or smallest integer overflows; undo and let the runtime make a float
+                let undo = if op == "add" { "sub" } else { "add" };
+                writeln!(self.asm_code, "    {} qword ptr [rsp + 8], 1", undo).unwrap();
+                writeln!(self.asm_code, "{}:", slow).unwrap();
+                self.emit_runtime_call(runtime, &[0]);
+                writeln!(self.asm_code, "{}:", done).unwrap();
             }
             Instruction::Echo => {
                 writeln!(self.asm_code, "    # Echo").unwrap();
-                // Windows x64 calling convention: rcx, rdx, r8, r9
-                writeln!(self.asm_code, "    pop rdx  # Value to print (second arg)").unwrap();
-
-                // Check if it's a string or an integer
-                writeln!(self.asm_code, "    # Check if it's a string or an integer").unwrap();
-                writeln!(self.asm_code, "    cmp rdx, 100000  # Assume values < 100000 are integers").unwrap();
-                writeln!(self.asm_code, "    jge .print_string_{}", self.label_counter).unwrap();
-
-                // Print as integer
-                writeln!(self.asm_code, "    # Print as integer").unwrap();
-                writeln!(self.asm_code, "    lea rcx, [rip + fmt_int]  # Format string (first arg)").unwrap();
-                writeln!(self.asm_code, "    mov rax, 0").unwrap();
-                writeln!(self.asm_code, "    call printf").unwrap();
-                writeln!(self.asm_code, "    jmp .echo_done_{}", self.label_counter).unwrap();
-
-                // Print as string
-                writeln!(self.asm_code, ".print_string_{}:", self.label_counter).unwrap();
-                writeln!(self.asm_code, "    lea rcx, [rip + fmt_str]  # Format string (first arg)").unwrap();
-                writeln!(self.asm_code, "    mov rax, 0").unwrap();
-                writeln!(self.asm_code, "    call printf").unwrap();
-
-                writeln!(self.asm_code, ".echo_done_{}:", self.label_counter).unwrap();
-                self.label_counter += 1;
+                // The runtime prints the value according to its type
+                self.emit_runtime_call("ox_echo", &[0]);
+                self.emit_release("rsp");
+                writeln!(self.asm_code, "    add rsp, 16").unwrap();
 
                 // We're not adding a newline by default to allow for string concatenation
             }
             Instruction::EchoLine => {
                 writeln!(self.asm_code, "    # EchoLine").unwrap();
-                // Windows x64 calling convention: rcx, rdx, r8, r9
-                writeln!(self.asm_code, "    pop rdx  # Value to print (second arg)").unwrap();
-
-                // Check if it's a string or an integer
-                writeln!(self.asm_code, "    # Check if it's a string or an integer").unwrap();
-                writeln!(self.asm_code, "    cmp rdx, 100000  # Assume values < 100000 are integers").unwrap();
-                writeln!(self.asm_code, "    jge .print_string_line_{}", self.label_counter).unwrap();
-
-                // Print as integer
-                writeln!(self.asm_code, "    # Print as integer").unwrap();
-                writeln!(self.asm_code, "    lea rcx, [rip + fmt_int]  # Format string (first arg)").unwrap();
-                writeln!(self.asm_code, "    mov rax, 0").unwrap();
-                writeln!(self.asm_code, "    call printf").unwrap();
-                writeln!(self.asm_code, "    jmp .echo_line_done_{}", self.label_counter).unwrap();
-
-                // Print as string
-                writeln!(self.asm_code, ".print_string_line_{}:", self.label_counter).unwrap();
-                writeln!(self.asm_code, "    lea rcx, [rip + fmt_str]  # Format string (first arg)").unwrap();
-                writeln!(self.asm_code, "    mov rax, 0").unwrap();
-                writeln!(self.asm_code, "    call printf").unwrap();
-
-                writeln!(self.asm_code, ".echo_line_done_{}:", self.label_counter).unwrap();
-                self.label_counter += 1;
-
-                // Add newline
-                writeln!(self.asm_code, "    mov rcx, 10  # '\\n' (first arg)").unwrap();
-                writeln!(self.asm_code, "    call putchar").unwrap();
+                self.emit_runtime_call("ox_echo_line", &[0]);
+                self.emit_release("rsp");
+                writeln!(self.asm_code, "    add rsp, 16").unwrap();
             }
             Instruction::Concat => {
                 writeln!(self.asm_code, "    # Concat").unwrap();
-                // We'll use the strcat function from the C standard library
-                writeln!(self.asm_code, "    pop rdx  # Second operand (string to append)").unwrap();
-                writeln!(self.asm_code, "    pop rcx  # First operand (destination string)").unwrap();
-
-                // Allocate space for the concatenated string
-                writeln!(self.asm_code, "    sub rsp, 512  # Reserve space for concatenated string").unwrap();
-                writeln!(self.asm_code, "    mov r8, rsp  # Store buffer address").unwrap();
-
-                // Copy the first string to the buffer
-                writeln!(self.asm_code, "    mov r9, rcx  # Source (first string)").unwrap();
-                writeln!(self.asm_code, "    mov r10, r8  # Destination (buffer)").unwrap();
-                writeln!(self.asm_code, ".copy_first_{}:", self.label_counter).unwrap();
-                writeln!(self.asm_code, "    mov al, [r9]  # Load character").unwrap();
-                writeln!(self.asm_code, "    mov [r10], al  # Store character").unwrap();
-                writeln!(self.asm_code, "    inc r9  # Next source character").unwrap();
-                writeln!(self.asm_code, "    inc r10  # Next destination character").unwrap();
-                writeln!(self.asm_code, "    test al, al  # Check for null terminator").unwrap();
-                writeln!(self.asm_code, "    jnz .copy_first_{}", self.label_counter).unwrap();
-
-                // Adjust destination pointer to overwrite the null terminator
-                writeln!(self.asm_code, "    dec r10  # Back up to null terminator").unwrap();
-
-                // Copy the second string to the buffer (append)
-                writeln!(self.asm_code, "    mov r9, rdx  # Source (second string)").unwrap();
-                writeln!(self.asm_code, ".copy_second_{}:", self.label_counter).unwrap();
-                writeln!(self.asm_code, "    mov al, [r9]  # Load character").unwrap();
-                writeln!(self.asm_code, "    mov [r10], al  # Store character").unwrap();
-                writeln!(self.asm_code, "    inc r9  # Next source character").unwrap();
-                writeln!(self.asm_code, "    inc r10  # Next destination character").unwrap();
-                writeln!(self.asm_code, "    test al, al  # Check for null terminator").unwrap();
-                writeln!(self.asm_code, "    jnz .copy_second_{}", self.label_counter).unwrap();
-
-                // Add the concatenated string to string literals
-                let str_index = self.string_literals.len();
-                self.string_literals.push("<concatenated string>".to_string()); // Add a placeholder
-
-                // Create a new string literal for the concatenated string
-                writeln!(self.asm_code, "    # Create a new string literal for the concatenated string").unwrap();
-                writeln!(self.asm_code, "    lea rax, [rip + str_{}]  # Get address of string literal", str_index).unwrap();
-                writeln!(self.asm_code, "    add rsp, 512  # Restore stack").unwrap();
-                writeln!(self.asm_code, "    push rax  # Push result").unwrap();
-
-                self.label_counter += 1;
+                self.emit_operation("ox_concat", 2);
             }
             Instruction::LoadVar(name) => {
                 writeln!(self.asm_code, "    # LoadVar(\"{}\")", name).unwrap();
                 // Get the variable offset
                 let offset = self.get_var_offset(name);
-                writeln!(self.asm_code, "    mov rax, [rbp - {}]  # Load variable", offset).unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                writeln!(self.asm_code, "    push qword ptr [rbp - {}]  # Payload", offset - 8).unwrap();
+                writeln!(self.asm_code, "    push qword ptr [rbp - {}]  # Type tag", offset).unwrap();
+                self.emit_add_ref("rsp");
             }
             Instruction::StoreVar(name) => {
                 writeln!(self.asm_code, "    # StoreVar(\"{}\")", name).unwrap();
                 // Get the variable offset
                 let offset = self.get_var_offset(name);
-                writeln!(self.asm_code, "    pop rax  # Value to store").unwrap();
-                writeln!(self.asm_code, "    mov [rbp - {}], rax  # Store variable", offset).unwrap();
-                // Push the value back on the stack (PHP assignment is an expression)
-                writeln!(self.asm_code, "    push rax").unwrap();
+                // The variable lets go of its old value and takes over the one on the stack
+                self.emit_release(&format!("rbp - {}", offset));
+                writeln!(self.asm_code, "    pop qword ptr [rbp - {}]  # Type tag", offset).unwrap();
+                writeln!(self.asm_code, "    pop qword ptr [rbp - {}]  # Payload", offset - 8).unwrap();
             }
             Instruction::Greater => {
                 writeln!(self.asm_code, "    # Greater").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    cmp rbx, rax").unwrap();
-                writeln!(self.asm_code, "    setg al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_comparison("setg", &["ucomisd xmm0, xmm1", "seta al"], 1, "sete");
             }
             Instruction::Less => {
                 writeln!(self.asm_code, "    # Less").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    cmp rbx, rax").unwrap();
-                writeln!(self.asm_code, "    setl al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_comparison("setl", &["ucomisd xmm1, xmm0", "seta al"], -1, "sete");
             }
             Instruction::LessEqual => {
                 writeln!(self.asm_code, "    # LessEqual").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    cmp rbx, rax").unwrap();
-                writeln!(self.asm_code, "    setle al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                // -1 or 0, but not 1 or unordered
+                self.emit_comparison("setle", &["ucomisd xmm1, xmm0", "setae al"], 0, "setle");
             }
             Instruction::Equal => {
                 writeln!(self.asm_code, "    # Equal").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    cmp rbx, rax").unwrap();
-                writeln!(self.asm_code, "    sete al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                // NAN is unordered, which sets the parity flag
+                self.emit_comparison("sete", &["ucomisd xmm0, xmm1", "sete al", "setnp cl", "and al, cl"], 0, "sete");
+            }
+            Instruction::Spaceship => {
+                writeln!(self.asm_code, "    # Spaceship").unwrap();
+                let slow = self.new_label("spaceship_slow");
+                let done = self.new_label("spaceship_done");
+                let end = self.new_label("spaceship_end");
+                self.emit_require_ints(&slow);
+                writeln!(self.asm_code, "    mov rax, [rsp + 24]  # First operand").unwrap();
+                writeln!(self.asm_code, "    cmp rax, [rsp + 8]  # Second operand").unwrap();
+                writeln!(self.asm_code, "    setg al").unwrap();
+                writeln!(self.asm_code, "    setl cl").unwrap();
+                writeln!(self.asm_code, "    sub al, cl").unwrap();
+                writeln!(self.asm_code, "    movsx rax, al").unwrap();
+                writeln!(self.asm_code, "    mov [rsp + 24], rax").unwrap();
+                writeln!(self.asm_code, "    jmp {}", done).unwrap();
+                writeln!(self.asm_code, "{}:", slow).unwrap();
+                self.emit_operation("ox_spaceship", 2);
+                writeln!(self.asm_code, "    jmp {}", end).unwrap();
+                writeln!(self.asm_code, "{}:", done).unwrap();
+                writeln!(self.asm_code, "    add rsp, 16  # Result replaces the first operand").unwrap();
+                writeln!(self.asm_code, "{}:", end).unwrap();
+            }
+            Instruction::Identical | Instruction::NotIdentical => {
+                writeln!(self.asm_code, "    # {:?}", instruction).unwrap();
+                self.emit_identity(*instruction == Instruction::NotIdentical);
             }
             Instruction::NotEqual => {
                 writeln!(self.asm_code, "    # NotEqual").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    cmp rbx, rax").unwrap();
-                writeln!(self.asm_code, "    setne al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                self.emit_comparison("setne", &["ucomisd xmm0, xmm1", "setne al", "setp cl", "or al, cl"], 0, "setne");
             }
             Instruction::GreaterEqual => {
                 writeln!(self.asm_code, "    # GreaterEqual").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    cmp rbx, rax").unwrap();
-                writeln!(self.asm_code, "    setge al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
-                writeln!(self.asm_code, "    push rax").unwrap();
+                // 0 or 1 as an unsigned comparison excludes -1 and unordered
+                self.emit_comparison("setge", &["ucomisd xmm0, xmm1", "setae al"], 1, "setbe");
             }
-            Instruction::LogicalAnd => {
-                writeln!(self.asm_code, "    # LogicalAnd").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    and rax, rbx").unwrap();
-                writeln!(self.asm_code, "    cmp rax, 0").unwrap();
-                writeln!(self.asm_code, "    setne al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
+            Instruction::LogicalNot => {
+                writeln!(self.asm_code, "    # LogicalNot").unwrap();
+                self.emit_truthy(0);
+                writeln!(self.asm_code, "    xor rax, 1").unwrap();
+                writeln!(self.asm_code, "    mov [rsp + 8], rax").unwrap();
+                writeln!(self.asm_code, "    mov qword ptr [rsp], {}", TYPE_BOOL).unwrap();
+            }
+            Instruction::JumpIfFalse(label) => {
+                writeln!(self.asm_code, "    # JumpIfFalse({})", label).unwrap();
+                self.emit_truthy(0);
+                writeln!(self.asm_code, "    add rsp, 16  # Pop the condition").unwrap();
+                writeln!(self.asm_code, "    test rax, rax").unwrap();
+                writeln!(self.asm_code, "    jz {}", Self::jump_label(*label)).unwrap();
+            }
+            Instruction::Jump(label) => {
+                writeln!(self.asm_code, "    # Jump({})", label).unwrap();
+                writeln!(self.asm_code, "    jmp {}", Self::jump_label(*label)).unwrap();
+            }
+            Instruction::JumpIfTrue(label) => {
+                writeln!(self.asm_code, "    # JumpIfTrue({})", label).unwrap();
+                self.emit_truthy(0);
+                writeln!(self.asm_code, "    add rsp, 16  # Pop the condition").unwrap();
+                writeln!(self.asm_code, "    test rax, rax").unwrap();
+                writeln!(self.asm_code, "    jnz {}", Self::jump_label(*label)).unwrap();
+            }
+            Instruction::UnhandledMatch => {
+                writeln!(self.asm_code, "    # UnhandledMatch").unwrap();
+                // Does not return
+                self.emit_runtime_call("ox_unhandled_match", &[0]);
+            }
+            Instruction::JumpTable(first, targets, otherwise) => {
+                writeln!(self.asm_code, "    # JumpTable({}, {} targets, {})", first, targets.len(), otherwise).unwrap();
+                let table = self.new_label("jump_table");
+                let fallback = Self::jump_label(*otherwise);
+                writeln!(self.asm_code, "    cmp qword ptr [rsp], {}  # Int?", TYPE_INT).unwrap();
+                writeln!(self.asm_code, "    jne {}", fallback).unwrap();
+                writeln!(self.asm_code, "    mov rax, [rsp + 8]").unwrap();
+                writeln!(self.asm_code, "    mov rcx, {}", first).unwrap();
+                writeln!(self.asm_code, "    sub rax, rcx  # Offset from the first value").unwrap();
+                // Values below the first one wrap around to large unsigned offsets
+                writeln!(self.asm_code, "    cmp rax, {}", targets.len()).unwrap();
+                writeln!(self.asm_code, "    jae {}", fallback).unwrap();
+                writeln!(self.asm_code, "    lea rcx, [rip + {}]", table).unwrap();
+                writeln!(self.asm_code, "    movsxd rax, dword ptr [rcx + rax * 4]").unwrap();
+                writeln!(self.asm_code, "    add rax, rcx").unwrap();
+                writeln!(self.asm_code, "    jmp rax").unwrap();
+                // Entries are relative to the table, so it needs no relocations
+                writeln!(self.asm_code, "{}:", table).unwrap();
+                for target in targets {
+                    writeln!(self.asm_code, "    .long {} - {}", Self::jump_label(*target), table).unwrap();
+                }
+            }
+            // Add labels for jump targets
+            Instruction::Label(label) => {
+                writeln!(self.asm_code, "{}:", Self::jump_label(*label)).unwrap();
+            }
+            Instruction::Call(name, arg_count) => {
+                writeln!(self.asm_code, "    # Call(\"{}\", {})", name, arg_count).unwrap();
+                if self.functions.contains_key(name) {
+                    writeln!(self.asm_code, "    call {}", Self::function_label(name)).unwrap();
+                    writeln!(self.asm_code, "    push rdx  # Push return value").unwrap();
+                    writeln!(self.asm_code, "    push rax").unwrap();
+                } else if let Some(runtime) = Self::builtin(name) {
+                    // The runtime writes the result to a new slot above the arguments
+                    let regs = self.target.arg_regs();
+                    writeln!(self.asm_code, "    sub rsp, 16  # Result").unwrap();
+                    writeln!(self.asm_code, "    lea {}, [rsp]", regs[0]).unwrap();
+                    writeln!(self.asm_code, "    lea {}, [rsp + 16]  # Arguments", regs[1]).unwrap();
+                    writeln!(self.asm_code, "    mov {}, {}", regs[2], arg_count).unwrap();
+                    self.emit_call(runtime);
+                } else {
+                    writeln!(self.asm_code, "    push rdx").unwrap();
+                    writeln!(self.asm_code, "    push rax").unwrap();
+                }
+                // Remove the arguments from under the return value
+                self.emit_drop_below(*arg_count);
+            }
+            Instruction::CallIndirect(arg_count) => {
+                writeln!(self.asm_code, "    # CallIndirect({})", arg_count).unwrap();
+                let regs = self.target.arg_regs();
+                writeln!(self.asm_code, "    lea {}, [rsp + {}]  # Callee", regs[0], arg_count * VALUE_SIZE).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Argument count", regs[1], arg_count).unwrap();
+                self.emit_call("ox_callable");
+                // The closure stays in r10, where its code finds the captured values
+                writeln!(self.asm_code, "    mov r10, rax").unwrap();
+                writeln!(self.asm_code, "    call qword ptr [r10 + 8]  # Code").unwrap();
+                writeln!(self.asm_code, "    push rdx  # Push return value").unwrap();
                 writeln!(self.asm_code, "    push rax").unwrap();
+                // Remove the arguments and the callee from under the return value
+                self.emit_drop_below(arg_count + 1);
             }
-            Instruction::LogicalOr => {
-                writeln!(self.asm_code, "    # LogicalOr").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Second operand").unwrap();
-                writeln!(self.asm_code, "    pop rbx  # First operand").unwrap();
-                writeln!(self.asm_code, "    or rax, rbx").unwrap();
-                writeln!(self.asm_code, "    cmp rax, 0").unwrap();
-                writeln!(self.asm_code, "    setne al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
+            Instruction::MakeClosure(name, capture_count) => {
+                writeln!(self.asm_code, "    # MakeClosure(\"{}\", {})", name, capture_count).unwrap();
+                let regs = self.target.arg_regs();
+                let param_count = self.functions.get(name).copied().unwrap_or(0);
+                writeln!(self.asm_code, "    lea {}, [rip + {}]", regs[0], Self::function_label(name)).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Parameter count", regs[1], param_count).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Captured values", regs[2], capture_count).unwrap();
+                writeln!(self.asm_code, "    lea {}, [rsp]", regs[3]).unwrap();
+                self.emit_call("ox_make_closure");
                 writeln!(self.asm_code, "    push rax").unwrap();
+                writeln!(self.asm_code, "    push {}", TYPE_CLOSURE).unwrap();
+                // Remove the captured values, which the closure holds now
+                self.emit_drop_below(*capture_count);
             }
-            Instruction::LogicalNot => {
-                writeln!(self.asm_code, "    # LogicalNot").unwrap();
-                writeln!(self.asm_code, "    pop rax  # Operand").unwrap();
-                writeln!(self.asm_code, "    cmp rax, 0").unwrap();
-                writeln!(self.asm_code, "    sete al").unwrap();
-                writeln!(self.asm_code, "    movzx rax, al").unwrap();
+            Instruction::NewObject(class) => {
+                writeln!(self.asm_code, "    # NewObject(\"{}\")", class).unwrap();
+                let regs = self.target.arg_regs();
+                let property_count = self.property_counts.get(class).copied().unwrap_or(0);
+                writeln!(self.asm_code, "    lea {}, [rip + {}]", regs[0], Self::class_label(class)).unwrap();
+                writeln!(self.asm_code, "    lea {}, [rsp]  # Default values", regs[1]).unwrap();
+                self.emit_call("ox_new_object");
                 writeln!(self.asm_code, "    push rax").unwrap();
+                writeln!(self.asm_code, "    push {}", TYPE_OBJECT).unwrap();
+                // Remove the default values, which the object holds now
+                self.emit_drop_below(property_count);
             }
-            Instruction::JumpIfFalse(addr) => {
-                writeln!(self.asm_code, "    # JumpIfFalse({})", addr).unwrap();
-                writeln!(self.asm_code, "    pop rax  # Condition").unwrap();
-                writeln!(self.asm_code, "    cmp rax, 0").unwrap();
-                writeln!(self.asm_code, "    je .label_{}", addr).unwrap();
+            Instruction::LoadProperty(name) | Instruction::LoadPropertyQuiet(name) => {
+                writeln!(self.asm_code, "    # {:?}", instruction).unwrap();
+                let runtime = if let Instruction::LoadProperty(_) = instruction {
+                    "ox_load_property"
+                } else {
+                    "ox_load_property_quiet"
+                };
+                let regs = self.target.arg_regs();
+                // The property is read into a new slot, which then replaces the object
+                self.emit_push_value(TYPE_NULL, "0");
+                writeln!(self.asm_code, "    lea {}, [rsp]", regs[0]).unwrap();
+                writeln!(self.asm_code, "    lea {}, [rsp + 16]  # Object", regs[1]).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Member", regs[2], self.member(name)).unwrap();
+                self.emit_call(runtime);
+                self.emit_drop_below(1);
             }
-            Instruction::Jump(addr) => {
-                writeln!(self.asm_code, "    # Jump({})", addr).unwrap();
-                writeln!(self.asm_code, "    jmp .label_{}", addr).unwrap();
+            Instruction::LoadPropertyElement(name, dims) => {
+                writeln!(self.asm_code, "    # LoadPropertyElement(\"{}\", {:?})", name, dims).unwrap();
+                let regs = self.target.arg_regs();
+                let keys_size = dims.iter().filter(|&&has_key| has_key).count() * VALUE_SIZE;
+                // The property is read into a new slot on top of the keys, and the element over it
+                self.emit_push_value(TYPE_NULL, "0");
+                writeln!(self.asm_code, "    lea {}, [rsp]", regs[0]).unwrap();
+                writeln!(self.asm_code, "    lea {}, [rsp + {}]  # Object", regs[1], keys_size + VALUE_SIZE).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Member", regs[2], self.member(name)).unwrap();
+                self.emit_call("ox_load_property");
+                writeln!(self.asm_code, "    lea {}, [rsp]  # Property", regs[0]).unwrap();
+                self.emit_dimension_args(dims);
+                self.emit_call("ox_load_element");
             }
-            Instruction::JumpIfTrue(addr) => {
-                writeln!(self.asm_code, "    # JumpIfTrue({})", addr).unwrap();
-                writeln!(self.asm_code, "    pop rax  # Condition").unwrap();
-                writeln!(self.asm_code, "    cmp rax, 0").unwrap();
-                writeln!(self.asm_code, "    jne .label_{}", addr).unwrap();
+            Instruction::StorePropertyElement(name, dims) => {
+                writeln!(self.asm_code, "    # StorePropertyElement(\"{}\", {:?})", name, dims).unwrap();
+                let regs = self.target.arg_regs();
+                let keys_size = dims.iter().filter(|&&has_key| has_key).count() * VALUE_SIZE;
+                writeln!(self.asm_code, "    lea {}, [rsp + {}]  # Object", regs[0], keys_size + VALUE_SIZE).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Member", regs[1], self.member(name)).unwrap();
+                if dims.is_empty() {
+                    // The runtime converts the value to the property's type where it lies
+                    writeln!(self.asm_code, "    lea {}, [rsp]  # Value", regs[2]).unwrap();
+                    self.emit_call("ox_store_property");
+                } else {
+                    self.emit_call("ox_property_slot");
+                    writeln!(self.asm_code, "    mov {}, rax  # Property", regs[0]).unwrap();
+                    self.emit_dimension_args(dims);
+                    self.emit_call("ox_store_element");
+                }
+                // The value stays on the stack, above the keys and the object
+                self.emit_drop_below(keys_size / VALUE_SIZE + 1);
             }
-            // Add labels for jump targets
-            Instruction::Label(addr) => {
-                writeln!(self.asm_code, ".label_{}:", addr).unwrap();
+            Instruction::CallMethod(name, arg_count) => {
+                writeln!(self.asm_code, "    # CallMethod(\"{}\", {})", name, arg_count).unwrap();
+                let regs = self.target.arg_regs();
+                let object = arg_count * VALUE_SIZE;
+                writeln!(self.asm_code, "    lea {}, [rsp + {}]  # Object", regs[0], object).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Member", regs[1], self.member(name)).unwrap();
+                writeln!(self.asm_code, "    mov {}, {}  # Argument count", regs[2], arg_count).unwrap();
+                self.emit_call("ox_method_code");
+                writeln!(self.asm_code, "    mov r11, rax  # Code").unwrap();
+                // The method gets the object as its first argument, so a copy goes on top of the others
+                writeln!(self.asm_code, "    push qword ptr [rsp + {}]  # Payload", object + 8).unwrap();
+                writeln!(self.asm_code, "    push qword ptr [rsp + {}]  # Type tag", object + 8).unwrap();
+                self.emit_add_ref("rsp");
+                writeln!(self.asm_code, "    call r11").unwrap();
+                writeln!(self.asm_code, "    push rdx  # Push return value").unwrap();
+                writeln!(self.asm_code, "    push rax").unwrap();
+                // Remove the arguments and both copies of the object from under the return value
+                self.emit_drop_below(arg_count + 2);
             }
-            // Simplified implementation for other instructions
-            _ => {
-                writeln!(self.asm_code, "    # Unimplemented: {:?}", instruction).unwrap();
+            Instruction::Return => {
+                writeln!(self.asm_code, "    # Return").unwrap();
+                writeln!(self.asm_code, "    pop rax  # Return value (tag)").unwrap();
+                writeln!(self.asm_code, "    pop rdx  # Return value (payload)").unwrap();
+                writeln!(self.asm_code, "    jmp {}", self.exit_label).unwrap();
             }
         }
     }
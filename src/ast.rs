@@ -21,9 +21,49 @@ pub enum Type {
     Boolean,
     Array,
     Null,
+    Closure, // Anonymous and arrow functions
+    Object(String), // Instance of the named class
+    Nullable(Box<Type>), // ?T, the type or null
     Mixed, // For variables that could be any type (PHP is dynamically typed)
 }
 
+impl Type {
+    /// The type as PHP writes it in declarations and error messages
+    pub fn name(&self) -> String {
+        match self {
+            Type::Integer => "int".to_string(),
+            Type::Float => "float".to_string(),
+            Type::String => "string".to_string(),
+            Type::Boolean => "bool".to_string(),
+            Type::Array => "array".to_string(),
+            Type::Null => "null".to_string(),
+            Type::Closure => "Closure".to_string(),
+            Type::Object(class) => class.clone(),
+            Type::Nullable(inner) => format!("?{}", inner.name()),
+            Type::Mixed => "mixed".to_string(),
+        }
+    }
+}
+
+/// A property declared in a class
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub type_: Option<Type>,    // Declared type, if any
+    pub default: Option<Node>, // Constant expression for the initial value, null when missing
+    pub location: Location,
+}
+
+/// A method declared in a class
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub name: String,
+    pub is_static: bool,
+    pub params: Vec<(String, Option<Type>)>,
+    pub body: Node,
+    pub location: Location,
+}
+
 /// Binary operators
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOp {
@@ -33,10 +73,21 @@ pub enum BinaryOp {
     Multiply,
     Divide,
     Modulo,
+    Power,
+
+    // Bitwise
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
 
     // Comparison
     Equal,
     NotEqual,
+    Identical,
+    NotIdentical,
+    Spaceship, // <=>, -1, 0 or 1
     Less,
     LessEqual,
     Greater,
@@ -45,6 +96,10 @@ pub enum BinaryOp {
     // Logical
     LogicalAnd,
     LogicalOr,
+    LogicalXor, // Only written as the keyword xor
+
+    // Null coalescing, the left operand is read like isset() does
+    Coalesce,
 
     // Assignment
     Assign,
@@ -61,6 +116,7 @@ pub enum BinaryOp {
 pub enum UnaryOp {
     Negate,
     LogicalNot,
+    BitwiseNot,
 }
 
 /// AST nodes
@@ -83,6 +139,11 @@ pub enum Node {
         body: Box<Node>,
         location: Location,
     },
+    DoWhileStmt {
+        body: Box<Node>,
+        condition: Box<Node>,
+        location: Location,
+    },
     ForStmt {
         init: Option<Box<Node>>,
         condition: Option<Box<Node>>,
@@ -97,7 +158,14 @@ pub enum Node {
         body: Box<Node>,
         location: Location,
     },
+    SwitchStmt {
+        subject: Box<Node>,
+        cases: Vec<(Option<Node>, Vec<Node>)>, // (value, statements) pairs, the default case has no value
+        location: Location,
+    },
     ReturnStmt(Option<Box<Node>>, Location),
+    BreakStmt(usize, Location),    // Number of enclosing loops to leave
+    ContinueStmt(usize, Location), // Number of enclosing loops to leave before continuing
     EchoStmt(Vec<Node>, Location),
 
     // Declarations
@@ -112,6 +180,13 @@ pub enum Node {
         body: Box<Node>,
         location: Location,
     },
+    ClassDecl {
+        name: String,
+        constants: Vec<(String, Node)>, // (name, constant expression) pairs
+        properties: Vec<Property>,
+        methods: Vec<Method>,
+        location: Location,
+    },
 
     // Expressions
     BinaryExpr {
@@ -125,12 +200,74 @@ pub enum Node {
         expr: Box<Node>,
         location: Location,
     },
+    Ternary {
+        condition: Box<Node>,
+        then_branch: Option<Box<Node>>, // None for the short form `a ?: b`, which yields the condition
+        else_branch: Box<Node>,
+        location: Location,
+    },
+    Match {
+        subject: Box<Node>,
+        arms: Vec<(Vec<Node>, Node)>, // (conditions, result) pairs, the default arm has no conditions
+        location: Location,
+    },
+    CompoundAssign {
+        op: BinaryOp, // The operator applied, e.g. Add for +=
+        target: Box<Node>,
+        value: Box<Node>,
+        location: Location,
+    },
+    IncDec {
+        increment: bool, // ++ rather than --
+        prefix: bool,    // ++$a yields the new value, $a++ the old one
+        target: Box<Node>,
+        location: Location,
+    },
+    Closure {
+        params: Vec<(String, Option<Type>)>,
+        uses: Option<Vec<String>>, // Variables captured by value; None for an arrow function, which captures what it uses
+        body: Box<Node>,
+        location: Location,
+    },
     Variable(String, Location),
+    ArrayAppend(Box<Node>, Location), // $array[], only valid as an assignment target
     FunctionCall {
         name: String,
         args: Vec<Node>,
         location: Location,
     },
+    IndirectCall {
+        callee: Box<Node>, // Evaluates to the closure to call
+        args: Vec<Node>,
+        location: Location,
+    },
+    New {
+        class: String,
+        args: Vec<Node>, // Passed to the constructor
+        location: Location,
+    },
+    PropertyAccess {
+        object: Box<Node>,
+        property: String,
+        location: Location,
+    },
+    MethodCall {
+        object: Box<Node>,
+        method: String,
+        args: Vec<Node>,
+        location: Location,
+    },
+    StaticCall {
+        class: String, // self and static are resolved to the enclosing class by the parser
+        method: String,
+        args: Vec<Node>,
+        location: Location,
+    },
+    ClassConstant {
+        class: String,
+        name: String,
+        location: Location,
+    },
 
     // Literals
     IntLiteral(i64, Location),
@@ -0,0 +1,911 @@
+// bytecode is responsible for saving compiled modules to disk and loading them back (.oxb files)
+//
+// File layout, all integers little-endian:
+//
+//   magic      "OXB\0"
+//   version    u16, must equal VERSION
+//   flags      u16, reserved, 0
+//   constants  u32 count, then that many strings
+//   main       code
+//   functions  u32 count, then for each: name (string), u32 parameter count,
+//              that many parameter names (strings), u32 capture count,
+//              that many captured variable names (strings), code
+//   classes    u32 count, then for each: name (string), u32 property count,
+//              that many properties, each a name (string), a type and
+//              a byte that is 1 when it has a default value,
+//              u32 method count, that many method names (strings)
+//
+// A string is a u32 byte length followed by UTF-8 bytes. A type is a byte,
+// 0 when there is none, followed by the class name for an object type and by
+// the type that is made nullable for a nullable type. Code is a u32
+// instruction count followed by the instructions, each an opcode byte and
+// its operands: i64/f64/labels/indices as 8 bytes, names as strings,
+// the dimensions of an element access as a u32 count of 0/1 bytes,
+// and the labels of a jump table as a u32 count of labels.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::ast::Type;
+use crate::cfg::{self, ControlFlowGraph};
+use crate::codegen::{Class, ConstantPool, Function, Instruction, LabelId, Module, MAX_DIMENSIONS};
+use crate::error::{CompilerError, Result};
+
+/// First bytes of every bytecode file
+pub const MAGIC: &[u8; 4] = b"OXB\0";
+
+/// Version of the format, bumped whenever the encoding of a module changes
+pub const VERSION: u16 = 8;
+
+/// Conventional extension of bytecode files
+pub const EXTENSION: &str = "oxb";
+
+/// Functions that both backends provide without a definition in the module
+const BUILTINS: [&str; 2] = ["strlen", "substr"];
+
+// Opcodes, one per instruction
+const OP_PUSH_INT: u8 = 0x01;
+const OP_PUSH_FLOAT: u8 = 0x02;
+const OP_PUSH_STRING: u8 = 0x03;
+const OP_PUSH_BOOL: u8 = 0x04;
+const OP_PUSH_NULL: u8 = 0x05;
+const OP_POP: u8 = 0x06;
+const OP_DUP: u8 = 0x07;
+const OP_LOAD_VAR: u8 = 0x10;
+const OP_STORE_VAR: u8 = 0x11;
+const OP_CREATE_ARRAY: u8 = 0x20;
+const OP_ARRAY_PUSH: u8 = 0x21;
+const OP_ARRAY_SET: u8 = 0x22;
+const OP_ARRAY_GET: u8 = 0x23;
+const OP_LOAD_ELEMENT: u8 = 0x24;
+const OP_STORE_ELEMENT: u8 = 0x25;
+const OP_ARRAY_GET_QUIET: u8 = 0x26;
+const OP_ADD: u8 = 0x30;
+const OP_SUBTRACT: u8 = 0x31;
+const OP_MULTIPLY: u8 = 0x32;
+const OP_DIVIDE: u8 = 0x33;
+const OP_MODULO: u8 = 0x34;
+const OP_NEGATE: u8 = 0x35;
+const OP_INCREMENT: u8 = 0x36;
+const OP_DECREMENT: u8 = 0x37;
+const OP_BITWISE_AND: u8 = 0x38;
+const OP_BITWISE_OR: u8 = 0x39;
+const OP_BITWISE_XOR: u8 = 0x3a;
+const OP_BITWISE_NOT: u8 = 0x3b;
+const OP_SHIFT_LEFT: u8 = 0x3c;
+const OP_SHIFT_RIGHT: u8 = 0x3d;
+const OP_POWER: u8 = 0x3e;
+const OP_EQUAL: u8 = 0x40;
+const OP_NOT_EQUAL: u8 = 0x41;
+const OP_LESS: u8 = 0x42;
+const OP_LESS_EQUAL: u8 = 0x43;
+const OP_GREATER: u8 = 0x44;
+const OP_GREATER_EQUAL: u8 = 0x45;
+const OP_IDENTICAL: u8 = 0x46;
+const OP_NOT_IDENTICAL: u8 = 0x47;
+const OP_SPACESHIP: u8 = 0x48;
+const OP_LOGICAL_NOT: u8 = 0x52;
+const OP_ITER_INIT: u8 = 0x60;
+const OP_ITER_NEXT: u8 = 0x61;
+const OP_JUMP: u8 = 0x70;
+const OP_JUMP_IF_FALSE: u8 = 0x71;
+const OP_JUMP_IF_TRUE: u8 = 0x72;
+const OP_LABEL: u8 = 0x73;
+const OP_JUMP_TABLE: u8 = 0x74;
+const OP_UNHANDLED_MATCH: u8 = 0x75;
+const OP_CALL: u8 = 0x80;
+const OP_RETURN: u8 = 0x81;
+const OP_CALL_INDIRECT: u8 = 0x82;
+const OP_MAKE_CLOSURE: u8 = 0x83;
+const OP_ECHO: u8 = 0x90;
+const OP_ECHO_LINE: u8 = 0x91;
+const OP_CONCAT: u8 = 0xa0;
+const OP_NEW_OBJECT: u8 = 0xb0;
+const OP_LOAD_PROPERTY: u8 = 0xb1;
+const OP_LOAD_PROPERTY_ELEMENT: u8 = 0xb2;
+const OP_STORE_PROPERTY_ELEMENT: u8 = 0xb3;
+const OP_CALL_METHOD: u8 = 0xb4;
+const OP_LOAD_PROPERTY_QUIET: u8 = 0xb5;
+
+// Declared types of properties
+const TYPE_NONE: u8 = 0;
+const TYPE_INT: u8 = 1;
+const TYPE_FLOAT: u8 = 2;
+const TYPE_STRING: u8 = 3;
+const TYPE_BOOL: u8 = 4;
+const TYPE_ARRAY: u8 = 5;
+const TYPE_NULL: u8 = 6;
+const TYPE_CLOSURE: u8 = 7;
+const TYPE_OBJECT: u8 = 8;
+const TYPE_MIXED: u8 = 9;
+const TYPE_NULLABLE: u8 = 10;
+
+/// Encode a module in the bytecode format
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.bytes.extend_from_slice(&VERSION.to_le_bytes());
+    writer.bytes.extend_from_slice(&0u16.to_le_bytes());
+
+    writer.u32(module.constants.strings().len());
+    for constant in module.constants.strings() {
+        writer.string(constant);
+    }
+
+    writer.code(&module.main);
+
+    // Functions are written in a stable order so that the same source gives the same file
+    let mut names: Vec<&String> = module.functions.keys().collect();
+    names.sort();
+    writer.u32(names.len());
+    for name in names {
+        let function = &module.functions[name];
+        writer.string(&function.name);
+        writer.u32(function.params.len());
+        for param in &function.params {
+            writer.string(param);
+        }
+        writer.u32(function.captures.len());
+        for capture in &function.captures {
+            writer.string(capture);
+        }
+        writer.code(&function.instructions);
+    }
+
+    let mut names: Vec<&String> = module.classes.keys().collect();
+    names.sort();
+    writer.u32(names.len());
+    for name in names {
+        let class = &module.classes[name];
+        writer.string(&class.name);
+        writer.u32(class.properties.len());
+        for ((property, type_), &has_default) in
+            class.properties.iter().zip(&class.property_types).zip(&class.property_defaults)
+        {
+            writer.string(property);
+            writer.type_(type_.as_ref());
+            writer.bytes.push(has_default as u8);
+        }
+        writer.u32(class.methods.len());
+        for method in &class.methods {
+            writer.string(method);
+        }
+    }
+
+    writer.bytes
+}
+
+/// Decode and validate a module in the bytecode format
+pub fn decode(bytes: &[u8]) -> Result<Module> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(invalid("not an Oxiphant bytecode file"));
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(invalid(format!(
+            "unsupported version {} (this build reads version {})",
+            version, VERSION
+        )));
+    }
+    if reader.u16()? != 0 {
+        return Err(invalid("unknown flags"));
+    }
+
+    let mut constants = ConstantPool::new();
+    for _ in 0..reader.u32()? {
+        let constant = reader.string()?;
+        if constants.intern(&constant) != constants.strings().len() - 1 {
+            return Err(invalid(format!("duplicate constant {:?}", constant)));
+        }
+    }
+
+    let main = reader.code()?;
+
+    let mut functions = HashMap::new();
+    for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+        let param_count = reader.u32()?;
+        let mut params = Vec::new();
+        for _ in 0..param_count {
+            params.push(reader.string()?);
+        }
+        let mut captures = Vec::new();
+        for _ in 0..reader.u32()? {
+            captures.push(reader.string()?);
+        }
+        let instructions = reader.code()?;
+        let function = Function {
+            name: name.clone(),
+            param_count,
+            params,
+            captures,
+            instructions,
+        };
+        if functions.insert(name.clone(), function).is_some() {
+            return Err(invalid(format!("function {} is defined twice", name)));
+        }
+    }
+
+    let mut classes = HashMap::new();
+    for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+        let mut properties = Vec::new();
+        let mut property_types = Vec::new();
+        let mut property_defaults = Vec::new();
+        for _ in 0..reader.u32()? {
+            properties.push(reader.string()?);
+            property_types.push(reader.type_()?);
+            property_defaults.push(match reader.u8()? {
+                0 => false,
+                1 => true,
+                flag => return Err(invalid(format!("invalid default flag {}", flag))),
+            });
+        }
+        let mut methods = Vec::new();
+        for _ in 0..reader.u32()? {
+            methods.push(reader.string()?);
+        }
+        let class = Class {
+            name: name.clone(),
+            properties,
+            property_types,
+            property_defaults,
+            methods,
+        };
+        if classes.insert(name.clone(), class).is_some() {
+            return Err(invalid(format!("class {} is defined twice", name)));
+        }
+    }
+
+    if reader.pos != bytes.len() {
+        return Err(invalid("unexpected data after the last class"));
+    }
+
+    let module = Module {
+        main,
+        functions,
+        classes,
+        constants,
+    };
+    validate(&module)?;
+    Ok(module)
+}
+
+/// Write a module to a bytecode file, checking first that it can be read back
+pub fn write_file<P: AsRef<Path>>(module: &Module, path: P) -> Result<()> {
+    validate(module)?;
+    fs::write(path, encode(module))?;
+    Ok(())
+}
+
+/// Read a module from a bytecode file
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Module> {
+    decode(&fs::read(path)?)
+}
+
+/// Check that every operand refers to something that exists and that every body uses the operand stack soundly
+fn validate(module: &Module) -> Result<()> {
+    // Every method is compiled to a function that takes the object first
+    for class in module.classes.values() {
+        for method in &class.methods {
+            let function = class.function_name(method);
+            if module.functions.get(&function).is_none_or(|f| f.param_count == 0) {
+                return Err(invalid(format!("method {} has no function", function)));
+            }
+        }
+    }
+
+    // Main may run off its end, a function must return
+    for function in module.functions.values() {
+        if !matches!(function.instructions.last(), Some(Instruction::Return)) {
+            return Err(invalid(format!("function {} does not end in Return", function.name)));
+        }
+    }
+
+    let bodies = std::iter::once(("main", &module.main))
+        .chain(module.functions.values().map(|f| (f.name.as_str(), &f.instructions)));
+
+    // Labels are numbered across the whole module, so a label may only be defined in one body
+    let mut defined_in = HashMap::new();
+    for (name, instructions) in bodies {
+        for label in cfg::label_positions(instructions).map_err(|err| in_body(err, name))?.into_keys() {
+            if let Some(other) = defined_in.insert(label, name) {
+                return Err(invalid(format!("Label {} is defined in both {} and {}", label, other, name)));
+            }
+        }
+        // Every jump must go to a label of its own body
+        let graph = ControlFlowGraph::build(instructions).map_err(|err| in_body(err, name))?;
+
+        for (addr, instruction) in instructions.iter().enumerate() {
+            let error = |message: String| invalid(format!("{} at {} in {}", message, addr, name));
+            match instruction {
+                Instruction::PushString(index) if module.constants.get(*index).is_none() => {
+                    return Err(error(format!("constant {} does not exist", index)));
+                }
+                // A property may be accessed without keys, like a variable with LoadVar and StoreVar
+                Instruction::LoadElement(_, dims) | Instruction::StoreElement(_, dims)
+                    if dims.is_empty() || dims.len() > MAX_DIMENSIONS =>
+                {
+                    return Err(error(format!("element access with {} dimensions", dims.len())));
+                }
+                Instruction::LoadPropertyElement(_, dims) | Instruction::StorePropertyElement(_, dims)
+                    if dims.len() > MAX_DIMENSIONS =>
+                {
+                    return Err(error(format!("element access with {} dimensions", dims.len())));
+                }
+                // The type checker rejects missing arguments, so the backends do not supply them
+                Instruction::Call(function, arg_count) => match module.functions.get(function) {
+                    Some(f) if *arg_count < f.param_count => {
+                        return Err(error(format!("call to {} passes {} of {} arguments", function, arg_count, f.param_count)));
+                    }
+                    Some(_) => {}
+                    None if BUILTINS.contains(&function.as_str()) => {}
+                    None => return Err(error(format!("function {} does not exist", function))),
+                },
+                Instruction::MakeClosure(function, capture_count) => match module.functions.get(function) {
+                    Some(f) if f.captures.len() == *capture_count => {}
+                    Some(_) => return Err(error(format!("closure {} captures {} values", function, capture_count))),
+                    None => return Err(error(format!("function {} does not exist", function))),
+                },
+                Instruction::NewObject(class) if !module.classes.contains_key(class) => {
+                    return Err(error(format!("class {} does not exist", class)));
+                }
+                _ => {}
+            }
+        }
+
+        check_stack(module, instructions, &graph)
+            .map_err(|(message, addr)| invalid(format!("{} at {} in {}", message, addr, name)))?;
+    }
+    Ok(())
+}
+
+/// What a slot of the operand stack holds
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Value,
+    Iterator, // Pushed by IterInit, only IterNext and Pop may use it
+}
+
+/// Check that no instruction takes more from the operand stack than there is, and
+/// that every path into a block leaves the same slots on the stack
+///
+/// An error is a message and the index of the instruction it is about.
+fn check_stack(
+    module: &Module,
+    instructions: &[Instruction],
+    graph: &ControlFlowGraph,
+) -> std::result::Result<(), (String, usize)> {
+    let mut entry: Vec<Option<Vec<Slot>>> = vec![None; graph.blocks.len()];
+    let mut pending = Vec::new();
+    if !graph.blocks.is_empty() {
+        entry[0] = Some(Vec::new());
+        pending.push(0);
+    }
+
+    while let Some(n) = pending.pop() {
+        let block = &graph.blocks[n];
+        let mut stack = entry[n].clone().unwrap();
+        // IterNext jumps once the iterator is popped, and falls through with the next value and key on top of it
+        let mut jump = None;
+        for (addr, instruction) in instructions.iter().enumerate().take(block.end).skip(block.start) {
+            let underflow = || ("operand stack underflow".to_string(), addr);
+            match instruction {
+                Instruction::Pop => {
+                    stack.pop().ok_or_else(underflow)?;
+                }
+                Instruction::IterNext(label) => {
+                    if stack.last() != Some(&Slot::Iterator) {
+                        return Err(("IterNext without an iterator".to_string(), addr));
+                    }
+                    jump = Some((graph.block_of(*label), stack[..stack.len() - 1].to_vec()));
+                    stack.extend([Slot::Value, Slot::Value]);
+                }
+                _ => {
+                    let (pops, pushes) = stack_effect(module, instruction);
+                    let start = stack.len().checked_sub(pops).ok_or_else(underflow)?;
+                    if stack[start..].contains(&Slot::Iterator) {
+                        return Err(("iterator used as a value".to_string(), addr));
+                    }
+                    stack.truncate(start);
+                    let pushed = if *instruction == Instruction::IterInit { Slot::Iterator } else { Slot::Value };
+                    stack.extend(std::iter::repeat_n(pushed, pushes));
+                }
+            }
+        }
+
+        for &successor in &block.successors {
+            let state = match &jump {
+                Some((target, jump_stack)) if *target == Some(successor) => jump_stack,
+                _ => &stack,
+            };
+            match &entry[successor] {
+                None => {
+                    entry[successor] = Some(state.clone());
+                    pending.push(successor);
+                }
+                Some(existing) if existing == state => {}
+                Some(_) => {
+                    let message = "operand stack differs between the paths that reach it".to_string();
+                    return Err((message, graph.blocks[successor].start));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Slots an instruction takes from the operand stack and how many it puts back;
+/// Pop and IterNext depend on what is on the stack, so they are checked on their own
+fn stack_effect(module: &Module, instruction: &Instruction) -> (usize, usize) {
+    let keys = |dims: &[bool]| dims.iter().filter(|&&has_key| has_key).count();
+    match instruction {
+        Instruction::PushInt(_)
+        | Instruction::PushFloat(_)
+        | Instruction::PushString(_)
+        | Instruction::PushBool(_)
+        | Instruction::PushNull
+        | Instruction::LoadVar(_)
+        | Instruction::CreateArray => (0, 1),
+        Instruction::Jump(_) | Instruction::Label(_) | Instruction::Pop | Instruction::IterNext(_) => (0, 0),
+        Instruction::Dup => (1, 2),
+        Instruction::StoreVar(_)
+        | Instruction::JumpIfFalse(_)
+        | Instruction::JumpIfTrue(_)
+        | Instruction::UnhandledMatch
+        | Instruction::Return
+        | Instruction::Echo
+        | Instruction::EchoLine => (1, 0),
+        Instruction::Negate
+        | Instruction::Increment
+        | Instruction::Decrement
+        | Instruction::BitwiseNot
+        | Instruction::LogicalNot
+        | Instruction::IterInit
+        | Instruction::JumpTable(..)
+        | Instruction::LoadProperty(_)
+        | Instruction::LoadPropertyQuiet(_) => (1, 1),
+        Instruction::ArrayPush
+        | Instruction::ArrayGet
+        | Instruction::ArrayGetQuiet
+        | Instruction::Add
+        | Instruction::Subtract
+        | Instruction::Multiply
+        | Instruction::Divide
+        | Instruction::Modulo
+        | Instruction::Power
+        | Instruction::BitwiseAnd
+        | Instruction::BitwiseOr
+        | Instruction::BitwiseXor
+        | Instruction::ShiftLeft
+        | Instruction::ShiftRight
+        | Instruction::Equal
+        | Instruction::NotEqual
+        | Instruction::Identical
+        | Instruction::NotIdentical
+        | Instruction::Spaceship
+        | Instruction::Less
+        | Instruction::LessEqual
+        | Instruction::Greater
+        | Instruction::GreaterEqual
+        | Instruction::Concat => (2, 1),
+        Instruction::ArraySet => (3, 1),
+        Instruction::LoadElement(_, dims) => (keys(dims), keys(dims) + 1),
+        Instruction::StoreElement(_, dims) => (keys(dims) + 1, 1),
+        Instruction::LoadPropertyElement(_, dims) => (keys(dims) + 1, keys(dims) + 2),
+        Instruction::StorePropertyElement(_, dims) => (keys(dims) + 2, 1),
+        Instruction::Call(_, count) | Instruction::MakeClosure(_, count) => (*count, 1),
+        Instruction::CallIndirect(count) | Instruction::CallMethod(_, count) => (count + 1, 1),
+        Instruction::NewObject(class) => (module.classes.get(class).map_or(0, |class| class.properties.len()), 1),
+    }
+}
+
+/// Turn a control-flow error in a body into a bytecode error that names the body
+fn in_body(err: CompilerError, name: &str) -> CompilerError {
+    match err {
+        CompilerError::CodeGenError { message } => invalid(format!("{} in {}", message, name)),
+        err => err,
+    }
+}
+
+fn invalid(message: impl Into<String>) -> CompilerError {
+    CompilerError::BytecodeError {
+        message: message.into(),
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u32(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn type_(&mut self, type_: Option<&Type>) {
+        let tag = match type_ {
+            None => TYPE_NONE,
+            Some(Type::Integer) => TYPE_INT,
+            Some(Type::Float) => TYPE_FLOAT,
+            Some(Type::String) => TYPE_STRING,
+            Some(Type::Boolean) => TYPE_BOOL,
+            Some(Type::Array) => TYPE_ARRAY,
+            Some(Type::Null) => TYPE_NULL,
+            Some(Type::Closure) => TYPE_CLOSURE,
+            Some(Type::Object(_)) => TYPE_OBJECT,
+            Some(Type::Nullable(_)) => TYPE_NULLABLE,
+            Some(Type::Mixed) => TYPE_MIXED,
+        };
+        self.bytes.push(tag);
+        match type_ {
+            Some(Type::Object(class)) => self.string(class),
+            Some(Type::Nullable(inner)) => self.type_(Some(inner)),
+            _ => {}
+        }
+    }
+
+    fn code(&mut self, instructions: &[Instruction]) {
+        self.u32(instructions.len());
+        for instruction in instructions {
+            self.instruction(instruction);
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        let opcode = match instruction {
+            Instruction::PushNull => OP_PUSH_NULL,
+            Instruction::Pop => OP_POP,
+            Instruction::Dup => OP_DUP,
+            Instruction::CreateArray => OP_CREATE_ARRAY,
+            Instruction::ArrayPush => OP_ARRAY_PUSH,
+            Instruction::ArraySet => OP_ARRAY_SET,
+            Instruction::ArrayGet => OP_ARRAY_GET,
+            Instruction::ArrayGetQuiet => OP_ARRAY_GET_QUIET,
+            Instruction::Add => OP_ADD,
+            Instruction::Subtract => OP_SUBTRACT,
+            Instruction::Multiply => OP_MULTIPLY,
+            Instruction::Divide => OP_DIVIDE,
+            Instruction::Modulo => OP_MODULO,
+            Instruction::Negate => OP_NEGATE,
+            Instruction::Increment => OP_INCREMENT,
+            Instruction::Decrement => OP_DECREMENT,
+            Instruction::BitwiseAnd => OP_BITWISE_AND,
+            Instruction::BitwiseOr => OP_BITWISE_OR,
+            Instruction::BitwiseXor => OP_BITWISE_XOR,
+            Instruction::BitwiseNot => OP_BITWISE_NOT,
+            Instruction::ShiftLeft => OP_SHIFT_LEFT,
+            Instruction::ShiftRight => OP_SHIFT_RIGHT,
+            Instruction::Power => OP_POWER,
+            Instruction::Equal => OP_EQUAL,
+            Instruction::NotEqual => OP_NOT_EQUAL,
+            Instruction::Identical => OP_IDENTICAL,
+            Instruction::NotIdentical => OP_NOT_IDENTICAL,
+            Instruction::Spaceship => OP_SPACESHIP,
+            Instruction::Less => OP_LESS,
+            Instruction::LessEqual => OP_LESS_EQUAL,
+            Instruction::Greater => OP_GREATER,
+            Instruction::GreaterEqual => OP_GREATER_EQUAL,
+            Instruction::LogicalNot => OP_LOGICAL_NOT,
+            Instruction::IterInit => OP_ITER_INIT,
+            Instruction::Return => OP_RETURN,
+            Instruction::Echo => OP_ECHO,
+            Instruction::EchoLine => OP_ECHO_LINE,
+            Instruction::Concat => OP_CONCAT,
+            Instruction::PushInt(_) => OP_PUSH_INT,
+            Instruction::PushFloat(_) => OP_PUSH_FLOAT,
+            Instruction::PushString(_) => OP_PUSH_STRING,
+            Instruction::PushBool(_) => OP_PUSH_BOOL,
+            Instruction::LoadVar(_) => OP_LOAD_VAR,
+            Instruction::StoreVar(_) => OP_STORE_VAR,
+            Instruction::LoadElement(..) => OP_LOAD_ELEMENT,
+            Instruction::StoreElement(..) => OP_STORE_ELEMENT,
+            Instruction::IterNext(_) => OP_ITER_NEXT,
+            Instruction::Jump(_) => OP_JUMP,
+            Instruction::JumpIfFalse(_) => OP_JUMP_IF_FALSE,
+            Instruction::JumpIfTrue(_) => OP_JUMP_IF_TRUE,
+            Instruction::Label(_) => OP_LABEL,
+            Instruction::JumpTable(..) => OP_JUMP_TABLE,
+            Instruction::UnhandledMatch => OP_UNHANDLED_MATCH,
+            Instruction::Call(..) => OP_CALL,
+            Instruction::CallIndirect(_) => OP_CALL_INDIRECT,
+            Instruction::MakeClosure(..) => OP_MAKE_CLOSURE,
+            Instruction::NewObject(_) => OP_NEW_OBJECT,
+            Instruction::LoadProperty(_) => OP_LOAD_PROPERTY,
+            Instruction::LoadPropertyQuiet(_) => OP_LOAD_PROPERTY_QUIET,
+            Instruction::LoadPropertyElement(..) => OP_LOAD_PROPERTY_ELEMENT,
+            Instruction::StorePropertyElement(..) => OP_STORE_PROPERTY_ELEMENT,
+            Instruction::CallMethod(..) => OP_CALL_METHOD,
+        };
+        self.bytes.push(opcode);
+
+        // Operands
+        match instruction {
+            Instruction::PushInt(value) => self.u64(*value as u64),
+            Instruction::PushFloat(value) => self.u64(value.to_bits()),
+            Instruction::PushBool(value) => self.bytes.push(*value as u8),
+            Instruction::PushString(index) => self.u64(*index as u64),
+            Instruction::IterNext(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpIfFalse(label)
+            | Instruction::JumpIfTrue(label)
+            | Instruction::Label(label) => self.u64(label.0 as u64),
+            Instruction::JumpTable(first, targets, otherwise) => {
+                self.u64(*first as u64);
+                self.u32(targets.len());
+                for label in targets {
+                    self.u64(label.0 as u64);
+                }
+                self.u64(otherwise.0 as u64);
+            }
+            Instruction::LoadVar(name)
+            | Instruction::StoreVar(name)
+            | Instruction::NewObject(name)
+            | Instruction::LoadProperty(name)
+            | Instruction::LoadPropertyQuiet(name) => self.string(name),
+            Instruction::LoadElement(name, dims)
+            | Instruction::StoreElement(name, dims)
+            | Instruction::LoadPropertyElement(name, dims)
+            | Instruction::StorePropertyElement(name, dims) => {
+                self.string(name);
+                self.u32(dims.len());
+                self.bytes.extend(dims.iter().map(|&has_key| has_key as u8));
+            }
+            Instruction::Call(name, count) | Instruction::MakeClosure(name, count) | Instruction::CallMethod(name, count) => {
+                self.string(name);
+                self.u32(*count);
+            }
+            Instruction::CallIndirect(arg_count) => self.u32(*arg_count),
+            _ => {}
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len());
+        let Some(end) = end else {
+            return Err(invalid("unexpected end of file"));
+        };
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<usize> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn index(&mut self) -> Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid("index out of range"))
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(invalid(format!("invalid boolean {}", byte))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("string is not valid UTF-8"))
+    }
+
+    fn type_(&mut self) -> Result<Option<Type>> {
+        Ok(Some(match self.u8()? {
+            TYPE_NONE => return Ok(None),
+            TYPE_INT => Type::Integer,
+            TYPE_FLOAT => Type::Float,
+            TYPE_STRING => Type::String,
+            TYPE_BOOL => Type::Boolean,
+            TYPE_ARRAY => Type::Array,
+            TYPE_NULL => Type::Null,
+            TYPE_CLOSURE => Type::Closure,
+            TYPE_OBJECT => Type::Object(self.string()?),
+            TYPE_MIXED => Type::Mixed,
+            TYPE_NULLABLE => match self.type_()? {
+                Some(Type::Nullable(_) | Type::Mixed | Type::Null) | None => {
+                    return Err(invalid("invalid nullable type"));
+                }
+                Some(inner) => Type::Nullable(Box::new(inner)),
+            },
+            tag => return Err(invalid(format!("invalid type {}", tag))),
+        }))
+    }
+
+    fn code(&mut self) -> Result<Vec<Instruction>> {
+        let count = self.u32()?;
+        // Every instruction takes at least one byte, which bounds a corrupt count
+        if count > self.bytes.len() - self.pos {
+            return Err(invalid("unexpected end of file"));
+        }
+        let mut instructions = Vec::with_capacity(count);
+        for _ in 0..count {
+            instructions.push(self.instruction()?);
+        }
+        Ok(instructions)
+    }
+
+    fn instruction(&mut self) -> Result<Instruction> {
+        let opcode = self.u8()?;
+        Ok(match opcode {
+            OP_PUSH_INT => Instruction::PushInt(self.u64()? as i64),
+            OP_PUSH_FLOAT => Instruction::PushFloat(f64::from_bits(self.u64()?)),
+            OP_PUSH_STRING => Instruction::PushString(self.index()?),
+            OP_PUSH_BOOL => Instruction::PushBool(self.bool()?),
+            OP_PUSH_NULL => Instruction::PushNull,
+            OP_POP => Instruction::Pop,
+            OP_DUP => Instruction::Dup,
+            OP_LOAD_VAR => Instruction::LoadVar(self.string()?),
+            OP_STORE_VAR => Instruction::StoreVar(self.string()?),
+            OP_CREATE_ARRAY => Instruction::CreateArray,
+            OP_ARRAY_PUSH => Instruction::ArrayPush,
+            OP_ARRAY_SET => Instruction::ArraySet,
+            OP_ARRAY_GET => Instruction::ArrayGet,
+            OP_ARRAY_GET_QUIET => Instruction::ArrayGetQuiet,
+            OP_LOAD_ELEMENT | OP_STORE_ELEMENT | OP_LOAD_PROPERTY_ELEMENT | OP_STORE_PROPERTY_ELEMENT => {
+                let name = self.string()?;
+                let count = self.u32()?;
+                let mut dims = Vec::new();
+                for _ in 0..count {
+                    dims.push(self.bool()?);
+                }
+                match opcode {
+                    OP_LOAD_ELEMENT => Instruction::LoadElement(name, dims),
+                    OP_STORE_ELEMENT => Instruction::StoreElement(name, dims),
+                    OP_LOAD_PROPERTY_ELEMENT => Instruction::LoadPropertyElement(name, dims),
+                    _ => Instruction::StorePropertyElement(name, dims),
+                }
+            }
+            OP_ADD => Instruction::Add,
+            OP_SUBTRACT => Instruction::Subtract,
+            OP_MULTIPLY => Instruction::Multiply,
+            OP_DIVIDE => Instruction::Divide,
+            OP_MODULO => Instruction::Modulo,
+            OP_NEGATE => Instruction::Negate,
+            OP_INCREMENT => Instruction::Increment,
+            OP_DECREMENT => Instruction::Decrement,
+            OP_BITWISE_AND => Instruction::BitwiseAnd,
+            OP_BITWISE_OR => Instruction::BitwiseOr,
+            OP_BITWISE_XOR => Instruction::BitwiseXor,
+            OP_BITWISE_NOT => Instruction::BitwiseNot,
+            OP_SHIFT_LEFT => Instruction::ShiftLeft,
+            OP_SHIFT_RIGHT => Instruction::ShiftRight,
+            OP_POWER => Instruction::Power,
+            OP_EQUAL => Instruction::Equal,
+            OP_NOT_EQUAL => Instruction::NotEqual,
+            OP_IDENTICAL => Instruction::Identical,
+            OP_NOT_IDENTICAL => Instruction::NotIdentical,
+            OP_SPACESHIP => Instruction::Spaceship,
+            OP_LESS => Instruction::Less,
+            OP_LESS_EQUAL => Instruction::LessEqual,
+            OP_GREATER => Instruction::Greater,
+            OP_GREATER_EQUAL => Instruction::GreaterEqual,
+            OP_LOGICAL_NOT => Instruction::LogicalNot,
+            OP_ITER_INIT => Instruction::IterInit,
+            OP_ITER_NEXT => Instruction::IterNext(LabelId(self.index()?)),
+            OP_JUMP => Instruction::Jump(LabelId(self.index()?)),
+            OP_JUMP_IF_FALSE => Instruction::JumpIfFalse(LabelId(self.index()?)),
+            OP_JUMP_IF_TRUE => Instruction::JumpIfTrue(LabelId(self.index()?)),
+            OP_LABEL => Instruction::Label(LabelId(self.index()?)),
+            OP_JUMP_TABLE => {
+                let first = self.u64()? as i64;
+                let count = self.u32()?;
+                // Every label takes 8 bytes, which bounds a corrupt count
+                if count > (self.bytes.len() - self.pos) / 8 {
+                    return Err(invalid("unexpected end of file"));
+                }
+                let mut targets = Vec::with_capacity(count);
+                for _ in 0..count {
+                    targets.push(LabelId(self.index()?));
+                }
+                Instruction::JumpTable(first, targets, LabelId(self.index()?))
+            }
+            OP_UNHANDLED_MATCH => Instruction::UnhandledMatch,
+            OP_CALL => Instruction::Call(self.string()?, self.u32()?),
+            OP_CALL_INDIRECT => Instruction::CallIndirect(self.u32()?),
+            OP_MAKE_CLOSURE => Instruction::MakeClosure(self.string()?, self.u32()?),
+            OP_RETURN => Instruction::Return,
+            OP_ECHO => Instruction::Echo,
+            OP_ECHO_LINE => Instruction::EchoLine,
+            OP_CONCAT => Instruction::Concat,
+            OP_NEW_OBJECT => Instruction::NewObject(self.string()?),
+            OP_LOAD_PROPERTY => Instruction::LoadProperty(self.string()?),
+            OP_LOAD_PROPERTY_QUIET => Instruction::LoadPropertyQuiet(self.string()?),
+            OP_CALL_METHOD => Instruction::CallMethod(self.string()?, self.u32()?),
+            _ => return Err(invalid(format!("unknown opcode 0x{:02x}", opcode))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module whose main prints a call to `name`, where `f($a, $b)` runs `body`
+    fn module(name: &str, arg_count: usize, body: Vec<Instruction>) -> Module {
+        let function = Function {
+            name: "f".to_string(),
+            param_count: 2,
+            params: vec!["a".to_string(), "b".to_string()],
+            captures: Vec::new(),
+            instructions: body,
+        };
+        let mut main: Vec<Instruction> = (0..arg_count as i64).map(Instruction::PushInt).collect();
+        main.push(Instruction::Call(name.to_string(), arg_count));
+        main.push(Instruction::Echo);
+        Module {
+            main,
+            functions: HashMap::from([("f".to_string(), function)]),
+            ..Module::default()
+        }
+    }
+
+    /// `return $b;`
+    fn body() -> Vec<Instruction> {
+        vec![Instruction::LoadVar("b".to_string()), Instruction::Return]
+    }
+
+    /// Decode an encoded module and return the message it is rejected with
+    fn rejection(module: &Module) -> String {
+        match decode(&encode(module)) {
+            Err(CompilerError::BytecodeError { message }) => message,
+            result => panic!("module was not rejected: {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn accepts_calls() {
+        assert!(decode(&encode(&module("f", 2, body()))).is_ok());
+        assert!(decode(&encode(&module("f", 3, body()))).is_ok());
+        assert!(decode(&encode(&module("strlen", 1, body()))).is_ok());
+    }
+
+    #[test]
+    fn rejects_calls_with_missing_arguments() {
+        assert_eq!(rejection(&module("f", 0, body())), "call to f passes 0 of 2 arguments at 0 in main");
+    }
+
+    #[test]
+    fn rejects_calls_to_unknown_functions() {
+        assert_eq!(rejection(&module("g", 1, body())), "function g does not exist at 1 in main");
+    }
+
+    #[test]
+    fn rejects_functions_without_return() {
+        let body = vec![Instruction::LoadVar("b".to_string()), Instruction::Echo];
+        assert_eq!(rejection(&module("f", 2, body)), "function f does not end in Return");
+    }
+}
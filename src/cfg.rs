@@ -0,0 +1,153 @@
+// cfg is responsible for the control-flow view of bytecode: basic blocks and the edges between them
+
+use std::collections::HashMap;
+
+use crate::codegen::{Instruction, LabelId};
+use crate::error::{CompilerError, Result};
+
+/// A run of instructions that is only entered at its first instruction and only left after its last
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,           // Index of the first instruction
+    pub end: usize,             // Index after the last instruction
+    pub successors: Vec<usize>, // Blocks that control can continue in
+}
+
+/// Control-flow graph of a function body or of the top-level code
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>, // In instruction order, the first one is the entry
+    labels: HashMap<LabelId, usize>, // Block that starts at each label
+}
+
+impl ControlFlowGraph {
+    /// Split instructions into basic blocks and link them
+    ///
+    /// Fails if a label is defined twice or a jump goes to a label that is not defined.
+    pub fn build(instructions: &[Instruction]) -> Result<Self> {
+        let positions = label_positions(instructions)?;
+
+        // A block starts at the first instruction, at every label and after every jump or return
+        let mut starts = vec![0];
+        for (i, instruction) in instructions.iter().enumerate() {
+            if matches!(instruction, Instruction::Label(_)) && i > 0 {
+                starts.push(i);
+            }
+            if ends_block(instruction) && i + 1 < instructions.len() {
+                starts.push(i + 1);
+            }
+        }
+        starts.dedup();
+
+        let mut blocks = Vec::new();
+        let mut block_at = HashMap::new();
+        for (n, &start) in starts.iter().enumerate() {
+            let end = starts.get(n + 1).copied().unwrap_or(instructions.len());
+            block_at.insert(start, n);
+            blocks.push(BasicBlock {
+                start,
+                end,
+                successors: Vec::new(),
+            });
+        }
+        let labels: HashMap<LabelId, usize> = positions
+            .iter()
+            .map(|(&label, position)| (label, block_at[position]))
+            .collect();
+
+        for n in 0..blocks.len() {
+            // Only the block of an empty body has no instructions
+            if blocks[n].start == blocks[n].end {
+                continue;
+            }
+            let last = &instructions[blocks[n].end - 1];
+            let mut successors = Vec::new();
+            for label in jump_targets(last) {
+                let Some(&target) = labels.get(&label) else {
+                    return Err(CompilerError::CodeGenError {
+                        message: format!("Jump to undefined label {}", label),
+                    });
+                };
+                successors.push(target);
+            }
+            // Everything but an unconditional jump or a return can fall through to the next block
+            let falls_through = !matches!(
+                last,
+                Instruction::Jump(_) | Instruction::JumpTable(..) | Instruction::Return | Instruction::UnhandledMatch
+            );
+            if falls_through && n + 1 < blocks.len() {
+                successors.push(n + 1);
+            }
+            successors.sort_unstable();
+            successors.dedup();
+            blocks[n].successors = successors;
+        }
+
+        Ok(Self { blocks, labels })
+    }
+
+    /// Block that starts at a label
+    pub fn block_of(&self, label: LabelId) -> Option<usize> {
+        self.labels.get(&label).copied()
+    }
+
+    /// Blocks that can continue in each block
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (n, block) in self.blocks.iter().enumerate() {
+            for &successor in &block.successors {
+                predecessors[successor].push(n);
+            }
+        }
+        predecessors
+    }
+
+    /// Whether each block can be reached from the entry
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = if self.blocks.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(n) = pending.pop() {
+            if !reachable[n] {
+                reachable[n] = true;
+                pending.extend(&self.blocks[n].successors);
+            }
+        }
+        reachable
+    }
+}
+
+/// Index of every label in a body
+///
+/// Fails if a label is defined twice.
+pub fn label_positions(instructions: &[Instruction]) -> Result<HashMap<LabelId, usize>> {
+    let mut positions = HashMap::new();
+    for (i, instruction) in instructions.iter().enumerate() {
+        if let Instruction::Label(label) = instruction {
+            if positions.insert(*label, i).is_some() {
+                return Err(CompilerError::CodeGenError {
+                    message: format!("Label {} is defined twice", label),
+                });
+            }
+        }
+    }
+    Ok(positions)
+}
+
+/// The labels an instruction may jump to
+pub fn jump_targets(instruction: &Instruction) -> Vec<LabelId> {
+    match instruction {
+        Instruction::Jump(label)
+        | Instruction::JumpIfFalse(label)
+        | Instruction::JumpIfTrue(label)
+        | Instruction::IterNext(label) => vec![*label],
+        Instruction::JumpTable(_, targets, otherwise) => {
+            targets.iter().chain(std::iter::once(otherwise)).copied().collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether control may leave the straight line after an instruction
+fn ends_block(instruction: &Instruction) -> bool {
+    !jump_targets(instruction).is_empty() || matches!(instruction, Instruction::Return | Instruction::UnhandledMatch)
+}
@@ -2,19 +2,40 @@
 
 use std::collections::HashMap;
 
-use crate::ast::{BinaryOp, Node, UnaryOp};
+use crate::ast::{BinaryOp, Node, Property, Type, UnaryOp};
 use crate::error::{CompilerError, Result};
 
+/// Hidden variable holding the old value of a postfix `++`/`--` on an array element;
+/// PHP variable names cannot start with `#`
+const POSTFIX_OLD_VALUE: &str = "#old";
+
+/// Hidden variables holding the object and keys of a `??=` target while its value is read
+const COALESCE_OBJECT: &str = "#object";
+const COALESCE_KEY: &str = "#key";
+
+/// Most dimensions an element assignment may have; asmgen passes them to the runtime as a 64-bit mask
+pub const MAX_DIMENSIONS: usize = 64;
+
+/// Prefix of the names that closures are compiled under; PHP function names cannot contain `.`
+const CLOSURE_PREFIX: &str = "closure.";
+
+/// Fewest integer cases for which a switch gets a jump table
+const JUMP_TABLE_MIN_CASES: usize = 3;
+
+/// Most jump table entries per integer case, so that sparse cases are compared instead
+const JUMP_TABLE_MAX_SPREAD: usize = 2;
+
 /// Bytecode instructions for the virtual machine
 #[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     // Stack operations
     PushInt(i64),
     PushFloat(f64),
-    PushString(String),
+    PushString(usize), // Index into the module's constant pool
     PushBool(bool),
     PushNull,
     Pop,
+    Dup, // Push a copy of the top of stack
 
     // Variable operations
     LoadVar(String),
@@ -25,6 +46,9 @@ pub enum Instruction {
     ArrayPush,
     ArraySet,
     ArrayGet,
+    ArrayGetQuiet, // ArrayGet for ??, a missing element or an offset of a non-array reads as null without a warning
+    LoadElement(String, Vec<bool>),  // Push $var[k1]...[kn] with the keys on the stack, keeping them; [] reads null
+    StoreElement(String, Vec<bool>), // Store into $var[k1]...[kn], popping the keys but leaving the value on the stack
 
     // Arithmetic operations
     Add,
@@ -32,31 +56,59 @@ pub enum Instruction {
     Multiply,
     Divide,
     Modulo,
+    Power,
     Negate,
+    Increment, // ++ on the top of stack, as PHP does it for every type
+    Decrement,
+
+    // Bitwise operations
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseNot,
+    ShiftLeft,
+    ShiftRight,
 
     // Comparison operations
     Equal,
     NotEqual,
+    Identical,    // Same type and value
+    NotIdentical,
+    Spaceship, // -1, 0 or 1 as the first operand is smaller, equal or greater
     Less,
     LessEqual,
     Greater,
     GreaterEqual,
 
-    // Logical operations
-    LogicalAnd,
-    LogicalOr,
+    // Logical operations (&& and || are compiled to jumps)
     LogicalNot,
 
+    // Iteration
+    IterInit,          // Turn the array on top of stack into an iterator
+    IterNext(LabelId), // Push the next value and key, or pop the iterator and jump to the label when done
+
     // Control flow
-    Jump(usize),         // Jump to absolute address
-    JumpIfFalse(usize),  // Jump to absolute address if top of stack is false
-    JumpIfTrue(usize),   // Jump to absolute address if top of stack is true
-    Label(usize),        // Label for jumps
+    Jump(LabelId),        // Jump to label
+    JumpIfFalse(LabelId), // Jump to label if top of stack is false
+    JumpIfTrue(LabelId),  // Jump to label if top of stack is true
+    Label(LabelId),       // Jump target, does nothing when executed
+    JumpTable(i64, Vec<LabelId>, LabelId), // Jump by the int on top of stack, which stays, to the label at its offset from the first value; anything else goes to the last label
+    UnhandledMatch, // Throw an UnhandledMatchError for the value on top of stack
 
     // Function operations
     Call(String, usize), // Function name, argument count
+    CallIndirect(usize), // Argument count; the closure to call lies below the arguments
+    MakeClosure(String, usize), // Function name, number of captured values on the stack with the first one on top
     Return,
 
+    // Objects
+    NewObject(String), // Class name; the default values of its properties are on the stack with the first one on top
+    LoadProperty(String), // Replace the object on top of stack with one of its properties
+    LoadPropertyQuiet(String), // LoadProperty for ??, a missing property or a property of a non-object reads as null without a warning
+    LoadPropertyElement(String, Vec<bool>), // Push $object->name[k1]...[kn] with the object and the keys on the stack, keeping them
+    StorePropertyElement(String, Vec<bool>), // Store into $object->name[k1]...[kn], popping the object and the keys but leaving the value; with no keys the value is converted to the property's type
+    CallMethod(String, usize), // Method name, argument count; the object lies below the arguments
+
     // I/O operations
     Echo,
     EchoLine, // Echo with a newline
@@ -65,18 +117,154 @@ pub enum Instruction {
     Concat,
 }
 
+/// Jump target, unique within a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LabelId(pub usize);
+
+impl std::fmt::Display for LabelId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
 /// Compiled function
 #[derive(Debug, Clone)]
 pub struct Function {
     pub name: String,
     pub param_count: usize,
+    pub params: Vec<String>, // Parameter names, in declaration order
+    pub captures: Vec<String>, // Variables a closure binds from its environment, before its parameters
     pub instructions: Vec<Instruction>,
 }
 
+/// Compiled class
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: String,
+    pub properties: Vec<String>,           // Property names, in declaration order, which is the order of an object's slots
+    pub property_types: Vec<Option<Type>>, // Declared type of each property, which stored values are converted to
+    pub property_defaults: Vec<bool>,      // Whether each property declares a default value
+    pub methods: Vec<String>,              // Method names, each compiled to the function named by `function_name`
+}
+
+impl Class {
+    /// Whether a property starts out uninitialized, as typed properties without a default do
+    pub fn starts_uninitialized(&self, slot: usize) -> bool {
+        self.property_types[slot].is_some() && !self.property_defaults[slot]
+    }
+
+    /// Name of the function that a method is compiled to
+    pub fn function_name(&self, method: &str) -> String {
+        format!("{}::{}", self.name, method)
+    }
+}
+
+/// Interned string constants, referred to by index from `PushString`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConstantPool {
+    strings: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl ConstantPool {
+    /// Create an empty constant pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the index of a string, adding it to the pool if it is not there yet
+    pub fn intern(&mut self, value: &str) -> usize {
+        if let Some(&index) = self.indices.get(value) {
+            return index;
+        }
+        let index = self.strings.len();
+        self.strings.push(value.to_string());
+        self.indices.insert(value.to_string(), index);
+        index
+    }
+
+    /// Get the string at an index
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.strings.get(index).map(String::as_str)
+    }
+
+    /// All strings, in index order
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+}
+
+/// A compiled program, the unit that backends consume
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub main: Vec<Instruction>,              // Top-level code
+    pub functions: HashMap<String, Function>, // User-defined functions, by name
+    pub classes: HashMap<String, Class>,      // User-defined classes, by name
+    pub constants: ConstantPool,
+}
+
+impl Module {
+    /// Get a string constant referred to by a `PushString`
+    pub fn constant(&self, index: usize) -> &str {
+        self.constants.get(index).expect("constant index out of range")
+    }
+}
+
+/// Where break and continue go in an enclosing loop or switch
+struct LoopLabels {
+    break_label: LabelId,
+    continue_label: LabelId,
+    keeps_value: bool, // foreach keeps its iterator, and switch its subject, on the stack until the statement ends
+}
+
+/// What `new` and class constants need from a class declaration, which they may come before
+struct ClassLayout {
+    constants: HashMap<String, Node>,
+    properties: Vec<Property>,
+    methods: HashMap<String, bool>, // Whether each method is static
+}
+
+/// Where an array element assignment target keeps its array
+enum ElementBase {
+    Variable(String),
+    Property(String), // The object is on the stack below the keys
+}
+
+impl ElementBase {
+    /// Instruction that pushes the element, keeping what leads to it on the stack
+    fn load(&self, dims: Vec<bool>) -> Instruction {
+        match self {
+            ElementBase::Variable(name) => Instruction::LoadElement(name.clone(), dims),
+            ElementBase::Property(name) => Instruction::LoadPropertyElement(name.clone(), dims),
+        }
+    }
+
+    /// Instruction that stores the value on top of stack into the element
+    fn store(&self, dims: Vec<bool>) -> Instruction {
+        match self {
+            ElementBase::Variable(name) => Instruction::StoreElement(name.clone(), dims),
+            ElementBase::Property(name) => Instruction::StorePropertyElement(name.clone(), dims),
+        }
+    }
+}
+
 /// Code generator for PHP AST
 pub struct CodeGenerator {
     functions: HashMap<String, Function>,
+    classes: HashMap<String, Class>,
+    layouts: HashMap<String, ClassLayout>,
+    constant_stack: Vec<(String, String)>, // Class constants being generated, to catch ones that refer to themselves
+    constants: ConstantPool,
     current_instructions: Vec<Instruction>,
+    label_counter: usize, // Labels are numbered across the whole module, functions included
+    loops: Vec<LoopLabels>, // Loops and switches around the current statement, innermost last
+    closure_counter: usize, // Closures are numbered across the whole module
+}
+
+impl Default for CodeGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CodeGenerator {
@@ -84,20 +272,49 @@ impl CodeGenerator {
     pub fn new() -> Self {
         Self {
             functions: HashMap::new(),
+            classes: HashMap::new(),
+            layouts: HashMap::new(),
+            constant_stack: Vec::new(),
+            constants: ConstantPool::new(),
             current_instructions: Vec::new(),
+            label_counter: 0,
+            loops: Vec::new(),
+            closure_counter: 0,
         }
     }
 
-    /// Generate code for a node
-    pub fn generate(&mut self, node: &Node) -> Result<Vec<Instruction>> {
+    /// Generate a module for a program
+    pub fn generate(&mut self, node: &Node) -> Result<Module> {
+        self.functions.clear();
+        self.classes.clear();
+        self.layouts.clear();
+        self.constant_stack.clear();
+        self.constants = ConstantPool::new();
         self.current_instructions.clear();
-        self.generate_node(node)?;
-        Ok(self.current_instructions.clone())
-    }
+        self.label_counter = 0;
+        self.loops.clear();
+        self.closure_counter = 0;
 
-    /// Get the compiled functions
-    pub fn get_functions(&self) -> &HashMap<String, Function> {
-        &self.functions
+        // Classes can be instantiated before their declaration, which only happens at the top level
+        if let Node::Program(statements) = node {
+            for stmt in statements {
+                if let Node::ClassDecl { name, constants, properties, methods, .. } = stmt {
+                    self.layouts.insert(name.clone(), ClassLayout {
+                        constants: constants.iter().cloned().collect(),
+                        properties: properties.clone(),
+                        methods: methods.iter().map(|method| (method.name.clone(), method.is_static)).collect(),
+                    });
+                }
+            }
+        }
+
+        self.generate_node(node)?;
+        Ok(Module {
+            main: std::mem::take(&mut self.current_instructions),
+            functions: std::mem::take(&mut self.functions),
+            classes: std::mem::take(&mut self.classes),
+            constants: std::mem::take(&mut self.constants),
+        })
     }
 
     /// Generate code for a node
@@ -123,79 +340,78 @@ impl CodeGenerator {
                 self.generate_node(condition)?;
 
                 // Jump to else branch if condition is false
-                let jump_to_else = self.current_instructions.len();
-                self.current_instructions.push(Instruction::JumpIfFalse(0)); // Placeholder
+                let else_label = self.new_label();
+                self.current_instructions.push(Instruction::JumpIfFalse(else_label));
 
                 // Generate code for the then branch
                 self.generate_node(then_branch)?;
 
                 if let Some(else_branch) = else_branch {
                     // Jump over the else branch
-                    let jump_over_else = self.current_instructions.len();
-                    self.current_instructions.push(Instruction::Jump(0)); // Placeholder
-
-                    // Update the jump to else
-                    let else_start = self.current_instructions.len();
-                    self.current_instructions[jump_to_else] = Instruction::JumpIfFalse(else_start);
-
-                    // Add a label for the else branch
-                    self.current_instructions.push(Instruction::Label(else_start));
+                    let end_label = self.new_label();
+                    self.current_instructions.push(Instruction::Jump(end_label));
 
                     // Generate code for the else branch
+                    self.current_instructions.push(Instruction::Label(else_label));
                     self.generate_node(else_branch)?;
 
-                    // Update the jump over else
-                    let after_else = self.current_instructions.len();
-                    self.current_instructions[jump_over_else] = Instruction::Jump(after_else);
-
                     // Add a label for after the else branch
-                    self.current_instructions.push(Instruction::Label(after_else));
+                    self.current_instructions.push(Instruction::Label(end_label));
                 } else {
-                    // Update the jump to else (which is actually the end of the if statement)
-                    let after_if = self.current_instructions.len();
-                    self.current_instructions[jump_to_else] = Instruction::JumpIfFalse(after_if);
-
-                    // Add a label for after the if statement
-                    self.current_instructions.push(Instruction::Label(after_if));
+                    // Without an else branch, the jump goes to the end of the if statement
+                    self.current_instructions.push(Instruction::Label(else_label));
                 }
             }
             Node::WhileStmt { condition, body, .. } => {
-                // Loop start
-                let loop_start = self.current_instructions.len();
-
                 // Add a label for the loop start
+                let loop_start = self.new_label();
+                let loop_end = self.new_label();
                 self.current_instructions.push(Instruction::Label(loop_start));
 
                 // Generate code for the condition
                 self.generate_node(condition)?;
 
                 // Jump out of the loop if condition is false
-                let jump_out = self.current_instructions.len();
-                self.current_instructions.push(Instruction::JumpIfFalse(0)); // Placeholder
+                self.current_instructions.push(Instruction::JumpIfFalse(loop_end));
 
                 // Generate code for the body
-                self.generate_node(body)?;
+                self.generate_loop_body(body, loop_end, loop_start, false)?;
 
                 // Jump back to the start of the loop
                 self.current_instructions.push(Instruction::Jump(loop_start));
 
-                // Update the jump out
-                let after_loop = self.current_instructions.len();
-                self.current_instructions[jump_out] = Instruction::JumpIfFalse(after_loop);
+                // Add a label for after the loop
+                self.current_instructions.push(Instruction::Label(loop_end));
+            }
+            Node::DoWhileStmt { body, condition, .. } => {
+                // The body runs once before the condition is checked
+                let loop_start = self.new_label();
+                let loop_condition = self.new_label();
+                let loop_end = self.new_label();
+                self.current_instructions.push(Instruction::Label(loop_start));
+
+                // Generate code for the body, continue goes on with the condition
+                self.generate_loop_body(body, loop_end, loop_condition, false)?;
+                self.current_instructions.push(Instruction::Label(loop_condition));
+
+                // Jump back to the start of the loop while the condition holds
+                self.generate_node(condition)?;
+                self.current_instructions.push(Instruction::JumpIfTrue(loop_start));
 
                 // Add a label for after the loop
-                self.current_instructions.push(Instruction::Label(after_loop));
+                self.current_instructions.push(Instruction::Label(loop_end));
             }
             Node::ForStmt { init, condition, increment, body, .. } => {
                 // Generate code for the initialization
                 if let Some(init) = init {
                     self.generate_node(init)?;
+                    // Pop the result of the initialization if it's not used
+                    self.current_instructions.push(Instruction::Pop);
                 }
 
-                // Loop start
-                let loop_start = self.current_instructions.len();
-
                 // Add a label for the loop start
+                let loop_start = self.new_label();
+                let loop_end = self.new_label();
                 self.current_instructions.push(Instruction::Label(loop_start));
 
                 // Generate code for the condition
@@ -207,11 +423,12 @@ impl CodeGenerator {
                 }
 
                 // Jump out of the loop if condition is false
-                let jump_out = self.current_instructions.len();
-                self.current_instructions.push(Instruction::JumpIfFalse(0)); // Placeholder
+                self.current_instructions.push(Instruction::JumpIfFalse(loop_end));
 
-                // Generate code for the body
-                self.generate_node(body)?;
+                // Generate code for the body, continue goes on with the increment
+                let loop_continue = self.new_label();
+                self.generate_loop_body(body, loop_end, loop_continue, false)?;
+                self.current_instructions.push(Instruction::Label(loop_continue));
 
                 // Generate code for the increment
                 if let Some(increment) = increment {
@@ -223,25 +440,91 @@ impl CodeGenerator {
                 // Jump back to the start of the loop
                 self.current_instructions.push(Instruction::Jump(loop_start));
 
-                // Update the jump out
-                let after_loop = self.current_instructions.len();
-                self.current_instructions[jump_out] = Instruction::JumpIfFalse(after_loop);
+                // Add a label for after the loop
+                self.current_instructions.push(Instruction::Label(loop_end));
+            }
+            Node::ForeachStmt { array, value_var, key_var, body, .. } => {
+                // Generate code for the array and keep an iterator over it on the stack
+                self.generate_node(array)?;
+                self.current_instructions.push(Instruction::IterInit);
+
+                // Add a label for the loop start
+                let loop_start = self.new_label();
+                let loop_end = self.new_label();
+                self.current_instructions.push(Instruction::Label(loop_start));
+
+                // Fetch the next element, or leave the loop when there is none
+                self.current_instructions.push(Instruction::IterNext(loop_end));
+
+                // The key is on top of the value
+                if let Some(key_var) = key_var {
+                    self.current_instructions.push(Instruction::StoreVar(key_var.clone()));
+                } else {
+                    self.current_instructions.push(Instruction::Pop);
+                }
+                self.current_instructions.push(Instruction::StoreVar(value_var.clone()));
+
+                // Generate code for the body
+                self.generate_loop_body(body, loop_end, loop_start, true)?;
+
+                // Jump back to the start of the loop
+                self.current_instructions.push(Instruction::Jump(loop_start));
 
                 // Add a label for after the loop
-                self.current_instructions.push(Instruction::Label(after_loop));
+                self.current_instructions.push(Instruction::Label(loop_end));
             }
-            Node::ForeachStmt { array, .. } => {
-                // This is a simplified implementation of foreach
-                // In a real implementation, we would need to handle iterating over arrays
+            Node::SwitchStmt { subject, cases, .. } => {
+                // The subject stays on the stack while it is compared with the cases
+                self.generate_node(subject)?;
 
-                // Generate code for the array
-                self.generate_node(array)?;
+                let case_labels: Vec<LabelId> = cases.iter().map(|_| self.new_label()).collect();
+                let switch_done = self.new_label(); // Pops the subject
+                let switch_end = self.new_label();
 
-                // TODO: Implement proper foreach loop
-                // For now, we'll just generate a warning
-                return Err(CompilerError::CodeGenError {
-                    message: "Foreach loops are not fully implemented yet".to_string(),
-                });
+                // Without a matching case, control goes to the default one or leaves the switch
+                let no_match = cases
+                    .iter()
+                    .position(|(value, _)| value.is_none())
+                    .map_or(switch_done, |i| case_labels[i]);
+
+                if let Some((first, targets)) = Self::jump_table(cases, &case_labels, no_match) {
+                    // Integer subjects go straight to their case, anything else is compared with each case
+                    let compare = self.new_label();
+                    self.current_instructions.push(Instruction::JumpTable(first, targets, compare));
+                    self.current_instructions.push(Instruction::Label(compare));
+                }
+
+                // Cases are compared loosely, in order
+                for ((value, _), &label) in cases.iter().zip(&case_labels) {
+                    if let Some(value) = value {
+                        self.current_instructions.push(Instruction::Dup);
+                        self.generate_node(value)?;
+                        self.current_instructions.push(Instruction::Equal);
+                        self.current_instructions.push(Instruction::JumpIfTrue(label));
+                    }
+                }
+                self.current_instructions.push(Instruction::Jump(no_match));
+
+                self.generate_switch_cases(cases, &case_labels, switch_end, switch_done)?;
+
+                // Add a label for after the switch
+                self.current_instructions.push(Instruction::Label(switch_done));
+                self.current_instructions.push(Instruction::Pop);
+                self.current_instructions.push(Instruction::Label(switch_end));
+            }
+            Node::BreakStmt(depth, _) => {
+                let target = self.enclosing_loop(*depth)?;
+                // Values kept by every statement that is left, the target included
+                self.pop_loop_values(target);
+                let label = self.loops[target].break_label;
+                self.current_instructions.push(Instruction::Jump(label));
+            }
+            Node::ContinueStmt(depth, _) => {
+                let target = self.enclosing_loop(*depth)?;
+                // The target loop goes on, so its own value stays
+                self.pop_loop_values(target + 1);
+                let label = self.loops[target].continue_label;
+                self.current_instructions.push(Instruction::Jump(label));
             }
             Node::ReturnStmt(value, _) => {
                 if let Some(value) = value {
@@ -283,32 +566,82 @@ impl CodeGenerator {
                 }
             }
             Node::FunctionDecl { name, params, body, .. } => {
-                // Save the current instructions
-                let saved_instructions = self.current_instructions.clone();
-                self.current_instructions.clear();
-
-                // Generate code for the function body
-                self.generate_node(body)?;
-
-                // Make sure the function returns
-                if !self.current_instructions.iter().any(|i| matches!(i, Instruction::Return)) {
-                    // If the function doesn't return, add a return null
-                    self.current_instructions.push(Instruction::PushNull);
-                    self.current_instructions.push(Instruction::Return);
-                }
+                let instructions = self.generate_function_body(body)?;
 
                 // Create a new function
                 let function = Function {
                     name: name.clone(),
                     param_count: params.len(),
-                    instructions: self.current_instructions.clone(),
+                    params: params.iter().map(|(param_name, _)| param_name.clone()).collect(),
+                    captures: Vec::new(),
+                    instructions,
                 };
 
                 // Add the function to the map
                 self.functions.insert(name.clone(), function);
+            }
+            Node::ClassDecl { name, properties, methods, .. } => {
+                let class = Class {
+                    name: name.clone(),
+                    properties: properties.iter().map(|property| property.name.clone()).collect(),
+                    property_types: properties.iter().map(|property| property.type_.clone()).collect(),
+                    property_defaults: properties.iter().map(|property| property.default.is_some()).collect(),
+                    methods: methods.iter().map(|method| method.name.clone()).collect(),
+                };
+
+                // Methods are functions whose first parameter is the object, which is null for static ones
+                for method in methods {
+                    let instructions = self.generate_function_body(&method.body)?;
+                    let params: Vec<String> = std::iter::once("this".to_string())
+                        .chain(method.params.iter().map(|(param_name, _)| param_name.clone()))
+                        .collect();
+                    let function_name = class.function_name(&method.name);
+                    self.functions.insert(function_name.clone(), Function {
+                        name: function_name,
+                        param_count: params.len(),
+                        params,
+                        captures: Vec::new(),
+                        instructions,
+                    });
+                }
+
+                self.classes.insert(name.clone(), class);
+            }
+            Node::Closure { params, uses, body, .. } => {
+                let instructions = self.generate_function_body(body)?;
+                let params: Vec<String> = params.iter().map(|(param_name, _)| param_name.clone()).collect();
+
+                // An arrow function captures every variable its body uses, except its parameters
+                let free_variables = Self::free_variables(&instructions, &params);
+                let captures = match uses {
+                    Some(uses) => {
+                        // Closures in a method are bound to its object
+                        let mut captures = uses.clone();
+                        if free_variables.iter().any(|name| name == "this") {
+                            captures.push("this".to_string());
+                        }
+                        captures
+                    }
+                    None => free_variables,
+                };
 
-                // Restore the current instructions
-                self.current_instructions = saved_instructions;
+                // The closure's code is compiled as a function of its own
+                self.closure_counter += 1;
+                let name = format!("{}{}", CLOSURE_PREFIX, self.closure_counter);
+                let capture_count = captures.len();
+                for capture in captures.iter().rev() {
+                    self.current_instructions.push(Instruction::LoadVar(capture.clone()));
+                }
+                self.functions.insert(name.clone(), Function {
+                    name: name.clone(),
+                    param_count: params.len(),
+                    params,
+                    captures,
+                    instructions,
+                });
+
+                // Captured values are copied into the closure when it is created
+                self.current_instructions.push(Instruction::MakeClosure(name, capture_count));
             }
             Node::BinaryExpr { op, left, right, .. } => {
                 match op {
@@ -324,35 +657,58 @@ impl CodeGenerator {
                             // Load the variable again (assignment is an expression in PHP)
                             self.current_instructions.push(Instruction::LoadVar(name.clone()));
                         } else {
-                            return Err(CompilerError::CodeGenError {
-                                message: "Left-hand side of assignment must be a variable".to_string(),
-                            });
+                            // Property or array element: the object and keys are evaluated before the value
+                            let (base, dims) = self.generate_element_keys(left)?;
+                            self.generate_node(right)?;
+                            self.current_instructions.push(base.store(dims));
                         }
                     }
+                    BinaryOp::LogicalAnd | BinaryOp::LogicalOr => {
+                        // The right operand is only evaluated when the left one doesn't decide the result
+                        let is_and = *op == BinaryOp::LogicalAnd;
+                        let short_circuit = self.new_label();
+                        let end = self.new_label();
+                        let skip = |label| if is_and { Instruction::JumpIfFalse(label) } else { Instruction::JumpIfTrue(label) };
+
+                        self.generate_node(left)?;
+                        self.current_instructions.push(skip(short_circuit));
+                        self.generate_node(right)?;
+                        self.current_instructions.push(skip(short_circuit));
+
+                        // Both operands were needed, so the result is the opposite of the short-circuit one
+                        self.current_instructions.push(Instruction::PushBool(is_and));
+                        self.current_instructions.push(Instruction::Jump(end));
+                        self.current_instructions.push(Instruction::Label(short_circuit));
+                        self.current_instructions.push(Instruction::PushBool(!is_and));
+                        self.current_instructions.push(Instruction::Label(end));
+                    }
+                    BinaryOp::LogicalXor => {
+                        // Both operands are always evaluated, and are true together when their negations are
+                        self.generate_node(left)?;
+                        self.current_instructions.push(Instruction::LogicalNot);
+                        self.generate_node(right)?;
+                        self.current_instructions.push(Instruction::LogicalNot);
+                        self.current_instructions.push(Instruction::NotIdentical);
+                    }
+                    BinaryOp::Coalesce => {
+                        // The right operand is only evaluated when the left one is null
+                        let end = self.new_label();
+                        self.generate_isset_operand(left)?;
+                        self.current_instructions.push(Instruction::Dup);
+                        self.current_instructions.push(Instruction::PushNull);
+                        self.current_instructions.push(Instruction::NotIdentical);
+                        self.current_instructions.push(Instruction::JumpIfTrue(end));
+                        self.current_instructions.push(Instruction::Pop);
+                        self.generate_node(right)?;
+                        self.current_instructions.push(Instruction::Label(end));
+                    }
                     _ => {
                         // Generate code for the left and right operands
                         self.generate_node(left)?;
                         self.generate_node(right)?;
 
                         // Generate code for the operation
-                        match op {
-                            BinaryOp::Add => self.current_instructions.push(Instruction::Add),
-                            BinaryOp::Subtract => self.current_instructions.push(Instruction::Subtract),
-                            BinaryOp::Multiply => self.current_instructions.push(Instruction::Multiply),
-                            BinaryOp::Divide => self.current_instructions.push(Instruction::Divide),
-                            BinaryOp::Modulo => self.current_instructions.push(Instruction::Modulo),
-                            BinaryOp::Equal => self.current_instructions.push(Instruction::Equal),
-                            BinaryOp::NotEqual => self.current_instructions.push(Instruction::NotEqual),
-                            BinaryOp::Less => self.current_instructions.push(Instruction::Less),
-                            BinaryOp::LessEqual => self.current_instructions.push(Instruction::LessEqual),
-                            BinaryOp::Greater => self.current_instructions.push(Instruction::Greater),
-                            BinaryOp::GreaterEqual => self.current_instructions.push(Instruction::GreaterEqual),
-                            BinaryOp::LogicalAnd => self.current_instructions.push(Instruction::LogicalAnd),
-                            BinaryOp::LogicalOr => self.current_instructions.push(Instruction::LogicalOr),
-                            BinaryOp::Concat => self.current_instructions.push(Instruction::Concat),
-                            BinaryOp::ArrayAccess => self.current_instructions.push(Instruction::ArrayGet),
-                            BinaryOp::Assign => unreachable!(), // Handled above
-                        }
+                        self.current_instructions.push(Self::binary_instruction(op));
                     }
                 }
             }
@@ -364,12 +720,124 @@ impl CodeGenerator {
                 match op {
                     UnaryOp::Negate => self.current_instructions.push(Instruction::Negate),
                     UnaryOp::LogicalNot => self.current_instructions.push(Instruction::LogicalNot),
+                    UnaryOp::BitwiseNot => self.current_instructions.push(Instruction::BitwiseNot),
+                }
+            }
+            Node::Ternary { condition, then_branch, else_branch, .. } => {
+                // Only the branch that is taken is evaluated
+                let else_label = self.new_label();
+                let end = self.new_label();
+                self.generate_node(condition)?;
+                match then_branch {
+                    Some(then_branch) => {
+                        self.current_instructions.push(Instruction::JumpIfFalse(else_label));
+                        self.generate_node(then_branch)?;
+                    }
+                    None => {
+                        // `a ?: b` yields a itself when it is truthy
+                        self.current_instructions.push(Instruction::Dup);
+                        self.current_instructions.push(Instruction::JumpIfFalse(else_label));
+                    }
+                }
+                self.current_instructions.push(Instruction::Jump(end));
+                self.current_instructions.push(Instruction::Label(else_label));
+                if then_branch.is_none() {
+                    self.current_instructions.push(Instruction::Pop);
+                }
+                self.generate_node(else_branch)?;
+                self.current_instructions.push(Instruction::Label(end));
+            }
+            Node::Match { subject, arms, .. } => {
+                // The subject stays on the stack until an arm is chosen
+                self.generate_node(subject)?;
+
+                let arm_labels: Vec<LabelId> = arms.iter().map(|_| self.new_label()).collect();
+                let match_end = self.new_label();
+
+                // Conditions are compared strictly, in order
+                for ((conditions, _), &label) in arms.iter().zip(&arm_labels) {
+                    for condition in conditions {
+                        self.current_instructions.push(Instruction::Dup);
+                        self.generate_node(condition)?;
+                        self.current_instructions.push(Instruction::Identical);
+                        self.current_instructions.push(Instruction::JumpIfTrue(label));
+                    }
+                }
+                match arms.iter().position(|(conditions, _)| conditions.is_empty()) {
+                    Some(default) => self.current_instructions.push(Instruction::Jump(arm_labels[default])),
+                    None => self.current_instructions.push(Instruction::UnhandledMatch),
+                }
+
+                // Only the result of the chosen arm is evaluated, there is no fallthrough
+                for ((_, result), &label) in arms.iter().zip(&arm_labels) {
+                    self.current_instructions.push(Instruction::Label(label));
+                    self.current_instructions.push(Instruction::Pop);
+                    self.generate_node(result)?;
+                    self.current_instructions.push(Instruction::Jump(match_end));
+                }
+
+                self.current_instructions.push(Instruction::Label(match_end));
+            }
+            Node::CompoundAssign { op: BinaryOp::Coalesce, target, value, .. } => {
+                self.generate_coalesce_assign(target, value)?;
+            }
+            Node::CompoundAssign { op, target, value, .. } => {
+                if let Node::Variable(name, _) = &**target {
+                    self.current_instructions.push(Instruction::LoadVar(name.clone()));
+                    self.generate_node(value)?;
+                    self.current_instructions.push(Self::binary_instruction(op));
+                    self.current_instructions.push(Instruction::StoreVar(name.clone()));
+                    self.current_instructions.push(Instruction::LoadVar(name.clone()));
+                } else {
+                    // Read the element through the same keys that the result is stored with
+                    let (base, dims) = self.generate_element_keys(target)?;
+                    self.current_instructions.push(base.load(dims.clone()));
+                    self.generate_node(value)?;
+                    self.current_instructions.push(Self::binary_instruction(op));
+                    self.current_instructions.push(base.store(dims));
+                }
+            }
+            Node::IncDec { increment, prefix, target, .. } => {
+                let step = if *increment { Instruction::Increment } else { Instruction::Decrement };
+                if let Node::Variable(name, _) = &**target {
+                    self.current_instructions.push(Instruction::LoadVar(name.clone()));
+                    if !*prefix {
+                        // The old value stays on the stack as the result
+                        self.current_instructions.push(Instruction::LoadVar(name.clone()));
+                    }
+                    self.current_instructions.push(step);
+                    self.current_instructions.push(Instruction::StoreVar(name.clone()));
+                    if *prefix {
+                        self.current_instructions.push(Instruction::LoadVar(name.clone()));
+                    }
+                } else {
+                    // Read the element through the same keys that the result is stored with
+                    let (base, dims) = self.generate_element_keys(target)?;
+                    self.current_instructions.push(base.load(dims.clone()));
+                    if *prefix {
+                        self.current_instructions.push(step);
+                        self.current_instructions.push(base.store(dims));
+                    } else {
+                        // The keys are below the old value, so it is set aside until the store is done
+                        let old = POSTFIX_OLD_VALUE.to_string();
+                        self.current_instructions.push(Instruction::StoreVar(old.clone()));
+                        self.current_instructions.push(Instruction::LoadVar(old.clone()));
+                        self.current_instructions.push(step);
+                        self.current_instructions.push(base.store(dims));
+                        self.current_instructions.push(Instruction::Pop);
+                        self.current_instructions.push(Instruction::LoadVar(old));
+                    }
                 }
             }
             Node::Variable(name, _) => {
                 // Load the variable
                 self.current_instructions.push(Instruction::LoadVar(name.clone()));
             }
+            Node::ArrayAppend(..) => {
+                return Err(CompilerError::CodeGenError {
+                    message: "Cannot use [] for reading".to_string(),
+                });
+            }
             Node::FunctionCall { name, args, .. } => {
                 // Generate code for the arguments (in reverse order)
                 for arg in args.iter().rev() {
@@ -379,6 +847,93 @@ impl CodeGenerator {
                 // Call the function
                 self.current_instructions.push(Instruction::Call(name.clone(), args.len()));
             }
+            Node::IndirectCall { callee, args, .. } => {
+                // The closure goes below the arguments, which are generated in reverse order
+                self.generate_node(callee)?;
+                for arg in args.iter().rev() {
+                    self.generate_node(arg)?;
+                }
+
+                // Call the closure
+                self.current_instructions.push(Instruction::CallIndirect(args.len()));
+            }
+            Node::New { class, args, .. } => {
+                let Some(layout) = self.layouts.get(class) else {
+                    return Err(CompilerError::CodeGenError {
+                        message: format!("Class \"{}\" not found", class),
+                    });
+                };
+                let defaults: Vec<Option<Node>> = layout.properties.iter().map(|property| property.default.clone()).collect();
+                let has_constructor = layout.methods.contains_key("__construct");
+
+                // Properties without a default value start out null, or uninitialized when they are typed
+                for default in defaults.iter().rev() {
+                    match default {
+                        Some(default) => self.generate_node(default)?,
+                        None => self.current_instructions.push(Instruction::PushNull),
+                    }
+                }
+                self.current_instructions.push(Instruction::NewObject(class.clone()));
+
+                // The constructor runs on a copy of the object, and its result is dropped
+                if has_constructor {
+                    self.current_instructions.push(Instruction::Dup);
+                    for arg in args.iter().rev() {
+                        self.generate_node(arg)?;
+                    }
+                    self.current_instructions.push(Instruction::CallMethod("__construct".to_string(), args.len()));
+                    self.current_instructions.push(Instruction::Pop);
+                }
+            }
+            Node::PropertyAccess { object, property, .. } => {
+                self.generate_node(object)?;
+                self.current_instructions.push(Instruction::LoadProperty(property.clone()));
+            }
+            Node::MethodCall { object, method, args, .. } => {
+                // The object goes below the arguments, which are generated in reverse order
+                self.generate_node(object)?;
+                for arg in args.iter().rev() {
+                    self.generate_node(arg)?;
+                }
+                self.current_instructions.push(Instruction::CallMethod(method.clone(), args.len()));
+            }
+            Node::StaticCall { class, method, args, .. } => {
+                let Some(&is_static) = self.layouts.get(class).and_then(|layout| layout.methods.get(method)) else {
+                    return Err(CompilerError::CodeGenError {
+                        message: format!("Call to undefined method {}::{}()", class, method),
+                    });
+                };
+                for arg in args.iter().rev() {
+                    self.generate_node(arg)?;
+                }
+
+                // A non-static method called like this runs on the current object
+                if is_static {
+                    self.current_instructions.push(Instruction::PushNull);
+                } else {
+                    self.current_instructions.push(Instruction::LoadVar("this".to_string()));
+                }
+                self.current_instructions.push(Instruction::Call(format!("{}::{}", class, method), args.len() + 1));
+            }
+            Node::ClassConstant { class, name, .. } => {
+                let Some(value) = self.layouts.get(class).and_then(|layout| layout.constants.get(name)).cloned() else {
+                    return Err(CompilerError::CodeGenError {
+                        message: format!("Undefined constant {}::{}", class, name),
+                    });
+                };
+
+                // Constants are inlined, so one that refers to itself would never end
+                let key = (class.clone(), name.clone());
+                if self.constant_stack.contains(&key) {
+                    return Err(CompilerError::CodeGenError {
+                        message: format!("Cannot declare self-referencing constant {}::{}", class, name),
+                    });
+                }
+                self.constant_stack.push(key);
+                let result = self.generate_node(&value);
+                self.constant_stack.pop();
+                result?;
+            }
             Node::IntLiteral(value, _) => {
                 self.current_instructions.push(Instruction::PushInt(*value));
             }
@@ -386,7 +941,8 @@ impl CodeGenerator {
                 self.current_instructions.push(Instruction::PushFloat(*value));
             }
             Node::StringLiteral(value, _) => {
-                self.current_instructions.push(Instruction::PushString(value.clone()));
+                let index = self.constants.intern(value);
+                self.current_instructions.push(Instruction::PushString(index));
             }
             Node::BooleanLiteral(value, _) => {
                 self.current_instructions.push(Instruction::PushBool(*value));
@@ -418,4 +974,278 @@ impl CodeGenerator {
 
         Ok(())
     }
+
+    /// Allocate a new label
+    fn new_label(&mut self) -> LabelId {
+        let label = LabelId(self.label_counter);
+        self.label_counter += 1;
+        label
+    }
+
+    /// Generate the instructions of a function or closure body
+    fn generate_function_body(&mut self, body: &Node) -> Result<Vec<Instruction>> {
+        // Save the current instructions, loops around the declaration don't apply inside it
+        let saved_instructions = std::mem::take(&mut self.current_instructions);
+        let saved_loops = std::mem::take(&mut self.loops);
+
+        // Generate code for the function body
+        let result = self.generate_node(body);
+
+        // Make sure the function returns, even when control falls off the end
+        // of a body whose only return statements sit inside a branch
+        if !matches!(self.current_instructions.last(), Some(Instruction::Return)) {
+            // If the function doesn't return, add a return null
+            self.current_instructions.push(Instruction::PushNull);
+            self.current_instructions.push(Instruction::Return);
+        }
+
+        // Restore the current instructions
+        let instructions = std::mem::replace(&mut self.current_instructions, saved_instructions);
+        self.loops = saved_loops;
+        result.map(|_| instructions)
+    }
+
+    /// Variables that a function body uses other than its parameters, in order of first use
+    fn free_variables(instructions: &[Instruction], params: &[String]) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for instruction in instructions {
+            if let Instruction::LoadVar(name)
+            | Instruction::StoreVar(name)
+            | Instruction::LoadElement(name, _)
+            | Instruction::StoreElement(name, _) = instruction
+            {
+                // Hidden variables such as POSTFIX_OLD_VALUE are never captured
+                if !name.starts_with('#') && !params.contains(name) && !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    }
+
+    /// Generate code for a loop body that break and continue can leave
+    fn generate_loop_body(&mut self, body: &Node, break_label: LabelId, continue_label: LabelId, keeps_value: bool) -> Result<()> {
+        self.loops.push(LoopLabels {
+            break_label,
+            continue_label,
+            keeps_value,
+        });
+        let result = self.generate_node(body);
+        self.loops.pop();
+        result
+    }
+
+    /// Generate code for the statements of each case, falling through from one case into the next
+    ///
+    /// break leaves the switch; continue does too, the same way it would leave a loop.
+    fn generate_switch_cases(&mut self, cases: &[(Option<Node>, Vec<Node>)], case_labels: &[LabelId], break_label: LabelId, continue_label: LabelId) -> Result<()> {
+        self.loops.push(LoopLabels {
+            break_label,
+            continue_label,
+            keeps_value: true,
+        });
+        let result = cases.iter().zip(case_labels).try_for_each(|((_, statements), &label)| {
+            self.current_instructions.push(Instruction::Label(label));
+            statements.iter().try_for_each(|stmt| self.generate_node(stmt))
+        });
+        self.loops.pop();
+        result
+    }
+
+    /// Jump table for a switch whose case values are dense integer literals:
+    /// the smallest value and the label of each value from there on
+    fn jump_table(cases: &[(Option<Node>, Vec<Node>)], case_labels: &[LabelId], no_match: LabelId) -> Option<(i64, Vec<LabelId>)> {
+        let mut values = Vec::new();
+        for ((value, _), &label) in cases.iter().zip(case_labels) {
+            if let Some(value) = value {
+                values.push((Self::int_case_value(value)?, label));
+            }
+        }
+        if values.len() < JUMP_TABLE_MIN_CASES {
+            return None;
+        }
+
+        let first = values.iter().map(|&(value, _)| value).min()?;
+        let last = values.iter().map(|&(value, _)| value).max()?;
+        let size = usize::try_from(last.checked_sub(first)?).ok()?.checked_add(1)?;
+        if size > values.len() * JUMP_TABLE_MAX_SPREAD {
+            return None;
+        }
+
+        // The first of several equal cases wins, so they are filled in from the last
+        let mut targets = vec![no_match; size];
+        for &(value, label) in values.iter().rev() {
+            targets[(value - first) as usize] = label;
+        }
+        Some((first, targets))
+    }
+
+    /// The value of a case that is an integer literal, possibly negated
+    fn int_case_value(node: &Node) -> Option<i64> {
+        match node {
+            Node::IntLiteral(value, _) => Some(*value),
+            Node::UnaryExpr { op: UnaryOp::Negate, expr, .. } => match &**expr {
+                Node::IntLiteral(value, _) => value.checked_neg(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Index in `loops` of the loop that `break depth` or `continue depth` refers to
+    fn enclosing_loop(&self, depth: usize) -> Result<usize> {
+        match self.loops.len().checked_sub(depth) {
+            Some(target) if depth > 0 => Ok(target),
+            _ => Err(CompilerError::CodeGenError {
+                message: format!("Cannot leave {} loops from a depth of {}", depth, self.loops.len()),
+            }),
+        }
+    }
+
+    /// Pop the values kept on the stack by the loops and switches from `from` inwards
+    fn pop_loop_values(&mut self, from: usize) {
+        let count = self.loops[from..].iter().filter(|l| l.keeps_value).count();
+        for _ in 0..count {
+            self.current_instructions.push(Instruction::Pop);
+        }
+    }
+
+    /// Generate code for the left operand of `??`, reading its elements without warnings
+    fn generate_isset_operand(&mut self, node: &Node) -> Result<()> {
+        match node {
+            Node::BinaryExpr { op: BinaryOp::ArrayAccess, left, right, .. } => {
+                self.generate_isset_operand(left)?;
+                self.generate_node(right)?;
+                self.current_instructions.push(Instruction::ArrayGetQuiet);
+                Ok(())
+            }
+            Node::PropertyAccess { object, property, .. } => {
+                self.generate_isset_operand(object)?;
+                self.current_instructions.push(Instruction::LoadPropertyQuiet(property.clone()));
+                Ok(())
+            }
+            _ => self.generate_node(node),
+        }
+    }
+
+    /// Generate code for `??=` on an array element or property
+    ///
+    /// The object and keys are set aside in hidden variables, so that they are evaluated
+    /// once for both the quiet read and the store.
+    fn generate_coalesce_assign(&mut self, target: &Node, value: &Node) -> Result<()> {
+        let (base, dims) = self.generate_element_keys(target)?;
+        if dims.contains(&false) {
+            return Err(CompilerError::CodeGenError {
+                message: "Cannot use [] for reading".to_string(),
+            });
+        }
+        let keys: Vec<String> = (0..dims.len()).map(|i| format!("{}{}", COALESCE_KEY, i)).collect();
+        for key in keys.iter().rev() {
+            self.current_instructions.push(Instruction::StoreVar(key.clone()));
+        }
+
+        // Read the element without warnings, as ?? does
+        let end = self.new_label();
+        match &base {
+            ElementBase::Variable(name) => self.current_instructions.push(Instruction::LoadVar(name.clone())),
+            ElementBase::Property(name) => {
+                self.current_instructions.push(Instruction::StoreVar(COALESCE_OBJECT.to_string()));
+                self.current_instructions.push(Instruction::LoadVar(COALESCE_OBJECT.to_string()));
+                self.current_instructions.push(Instruction::LoadPropertyQuiet(name.clone()));
+            }
+        }
+        for key in &keys {
+            self.current_instructions.push(Instruction::LoadVar(key.clone()));
+            self.current_instructions.push(Instruction::ArrayGetQuiet);
+        }
+        self.current_instructions.push(Instruction::Dup);
+        self.current_instructions.push(Instruction::PushNull);
+        self.current_instructions.push(Instruction::NotIdentical);
+        self.current_instructions.push(Instruction::JumpIfTrue(end));
+        self.current_instructions.push(Instruction::Pop);
+
+        // The value is only evaluated when the element is null; it may itself use the hidden variables
+        if let ElementBase::Property(_) = base {
+            self.current_instructions.push(Instruction::LoadVar(COALESCE_OBJECT.to_string()));
+        }
+        for key in keys {
+            self.current_instructions.push(Instruction::LoadVar(key));
+        }
+        self.generate_node(value)?;
+        self.current_instructions.push(base.store(dims));
+        self.current_instructions.push(Instruction::Label(end));
+        Ok(())
+    }
+
+    /// Generate code for the keys of an array element assignment target such as `$a[$k]['x']`
+    ///
+    /// Returns the variable or property holding the array and, from the outermost dimension
+    /// inwards, whether each dimension has a key on the stack (`[]` has none). The object
+    /// of a property is generated before the keys.
+    fn generate_element_keys(&mut self, target: &Node) -> Result<(ElementBase, Vec<bool>)> {
+        match target {
+            Node::Variable(name, _) => Ok((ElementBase::Variable(name.clone()), Vec::new())),
+            Node::PropertyAccess { object, property, .. } => {
+                self.generate_node(object)?;
+                Ok((ElementBase::Property(property.clone()), Vec::new()))
+            }
+            Node::BinaryExpr { op: BinaryOp::ArrayAccess, left, right, .. } => {
+                let (base, mut dims) = self.generate_element_keys(left)?;
+                Self::check_dimensions(&dims)?;
+                self.generate_node(right)?;
+                dims.push(true);
+                Ok((base, dims))
+            }
+            Node::ArrayAppend(array, _) => {
+                let (base, mut dims) = self.generate_element_keys(array)?;
+                Self::check_dimensions(&dims)?;
+                dims.push(false);
+                Ok((base, dims))
+            }
+            _ => Err(CompilerError::CodeGenError {
+                message: "Left-hand side of assignment must be a variable, a property or an array element".to_string(),
+            }),
+        }
+    }
+
+    /// Check that an element assignment target has room for one more dimension
+    fn check_dimensions(dims: &[bool]) -> Result<()> {
+        if dims.len() >= MAX_DIMENSIONS {
+            return Err(CompilerError::CodeGenError {
+                message: format!("Cannot assign to an array element nested more than {} levels deep", MAX_DIMENSIONS),
+            });
+        }
+        Ok(())
+    }
+
+    /// Instruction for a binary operator
+    fn binary_instruction(op: &BinaryOp) -> Instruction {
+        match op {
+            BinaryOp::Add => Instruction::Add,
+            BinaryOp::Subtract => Instruction::Subtract,
+            BinaryOp::Multiply => Instruction::Multiply,
+            BinaryOp::Divide => Instruction::Divide,
+            BinaryOp::Modulo => Instruction::Modulo,
+            BinaryOp::Power => Instruction::Power,
+            BinaryOp::BitwiseAnd => Instruction::BitwiseAnd,
+            BinaryOp::BitwiseOr => Instruction::BitwiseOr,
+            BinaryOp::BitwiseXor => Instruction::BitwiseXor,
+            BinaryOp::ShiftLeft => Instruction::ShiftLeft,
+            BinaryOp::ShiftRight => Instruction::ShiftRight,
+            BinaryOp::Equal => Instruction::Equal,
+            BinaryOp::NotEqual => Instruction::NotEqual,
+            BinaryOp::Identical => Instruction::Identical,
+            BinaryOp::NotIdentical => Instruction::NotIdentical,
+            BinaryOp::Spaceship => Instruction::Spaceship,
+            BinaryOp::Less => Instruction::Less,
+            BinaryOp::LessEqual => Instruction::LessEqual,
+            BinaryOp::Greater => Instruction::Greater,
+            BinaryOp::GreaterEqual => Instruction::GreaterEqual,
+            BinaryOp::Concat => Instruction::Concat,
+            BinaryOp::ArrayAccess => Instruction::ArrayGet,
+            BinaryOp::Assign => unreachable!(), // Assignments store instead of computing a value
+            BinaryOp::LogicalAnd | BinaryOp::LogicalOr | BinaryOp::Coalesce => unreachable!(), // Compiled to jumps
+            BinaryOp::LogicalXor => unreachable!(), // Compiled to a comparison
+        }
+    }
 }
@@ -21,10 +21,29 @@ pub enum CompilerError {
     #[error("Code generation error: {message}")]
     CodeGenError { message: String },
 
+    #[error("Runtime error: {message}")]
+    RuntimeError { message: String },
+
+    #[error("Invalid bytecode: {message}")]
+    BytecodeError { message: String },
+
     #[error("I/O error: {0}")]
     IoError(#[from] io::Error),
 }
 
+/// A problem found while compiling that does not stop compilation
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompilerWarning {
+    pub location: Location,
+    pub message: String,
+}
+
+impl std::fmt::Display for CompilerWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Warning at {}: {}", self.location, self.message)
+    }
+}
+
 /// Create a lexical error
 pub fn lexical_error(location: &Location, message: impl Into<String>) -> CompilerError {
     CompilerError::LexicalError(location.clone(), message.into())
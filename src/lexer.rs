@@ -18,17 +18,38 @@ pub enum TokenKind {
     If,
     Else,
     ElseIf,
+    EndIf,
+    Do,
     While,
+    EndWhile,
     For,
+    EndFor,
     Foreach,
+    EndForeach,
     As,
     Function,
+    Fn,
+    Use,
     Return,
+    Break,
+    Continue,
+    Switch,
+    Case,
+    Default,
+    Match,
+    Class,
+    New,
+    Static,
+    Const,
+    Public,
+    Protected,
+    Private,
     True,
     False,
     Null,
     And,
     Or,
+    Xor,
     Not,
 
     // Identifiers and literals
@@ -42,27 +63,45 @@ pub enum TokenKind {
     Plus,           // +
     Minus,          // -
     Asterisk,       // *
+    Power,          // **
     Slash,          // /
     Percent,        // %
+    Increment,      // ++
+    Decrement,      // --
     Equal,          // ==
     NotEqual,       // !=
     Identical,      // ===
     NotIdentical,   // !==
     LessThan,       // <
     LessThanEqual,  // <=
+    Spaceship,      // <=>
     GreaterThan,    // >
     GreaterThanEqual, // >=
     LogicalAnd,     // &&
     LogicalOr,      // ||
     LogicalNot,     // !
+    BitwiseAnd,     // &
+    BitwiseOr,      // |
+    BitwiseXor,     // ^
+    BitwiseNot,     // ~
+    ShiftLeft,      // <<
+    ShiftRight,     // >>
     Concat,         // .
     Assign,         // =
     PlusAssign,     // +=
     MinusAssign,    // -=
     MultiplyAssign, // *=
+    PowerAssign,    // **=
     DivideAssign,   // /=
     ModuloAssign,   // %=
     ConcatAssign,   // .=
+    AndAssign,      // &=
+    OrAssign,       // |=
+    XorAssign,      // ^=
+    ShiftLeftAssign,  // <<=
+    ShiftRightAssign, // >>=
+    Coalesce,       // ??
+    CoalesceAssign, // ??=
 
     // Punctuation
     LeftParen,      // (
@@ -97,17 +136,38 @@ lazy_static! {
         m.insert("if", TokenKind::If);
         m.insert("else", TokenKind::Else);
         m.insert("elseif", TokenKind::ElseIf);
+        m.insert("endif", TokenKind::EndIf);
+        m.insert("do", TokenKind::Do);
         m.insert("while", TokenKind::While);
+        m.insert("endwhile", TokenKind::EndWhile);
         m.insert("for", TokenKind::For);
+        m.insert("endfor", TokenKind::EndFor);
         m.insert("foreach", TokenKind::Foreach);
+        m.insert("endforeach", TokenKind::EndForeach);
         m.insert("as", TokenKind::As);
         m.insert("function", TokenKind::Function);
+        m.insert("fn", TokenKind::Fn);
+        m.insert("use", TokenKind::Use);
         m.insert("return", TokenKind::Return);
+        m.insert("break", TokenKind::Break);
+        m.insert("continue", TokenKind::Continue);
+        m.insert("switch", TokenKind::Switch);
+        m.insert("case", TokenKind::Case);
+        m.insert("default", TokenKind::Default);
+        m.insert("match", TokenKind::Match);
+        m.insert("class", TokenKind::Class);
+        m.insert("new", TokenKind::New);
+        m.insert("static", TokenKind::Static);
+        m.insert("const", TokenKind::Const);
+        m.insert("public", TokenKind::Public);
+        m.insert("protected", TokenKind::Protected);
+        m.insert("private", TokenKind::Private);
         m.insert("true", TokenKind::True);
         m.insert("false", TokenKind::False);
         m.insert("null", TokenKind::Null);
         m.insert("and", TokenKind::And);
         m.insert("or", TokenKind::Or);
+        m.insert("xor", TokenKind::Xor);
         m.insert("not", TokenKind::Not);
         m
     };
@@ -246,6 +306,8 @@ impl<'a> Lexer<'a> {
                 '>' => self.tokenize_greater_than(),
                 '&' => self.tokenize_ampersand(),
                 '|' => self.tokenize_pipe(),
+                '^' => self.tokenize_caret(),
+                '~' => self.tokenize_single(TokenKind::BitwiseNot),
                 '.' => self.tokenize_dot(),
                 '(' => self.tokenize_single(TokenKind::LeftParen),
                 ')' => self.tokenize_single(TokenKind::RightParen),
@@ -256,7 +318,7 @@ impl<'a> Lexer<'a> {
                 ';' => self.tokenize_single(TokenKind::Semicolon),
                 ',' => self.tokenize_single(TokenKind::Comma),
                 ':' => self.tokenize_colon(),
-                '?' => self.tokenize_single(TokenKind::QuestionMark),
+                '?' => self.tokenize_question_mark(),
 
                 // Invalid character
                 _ => {
@@ -516,7 +578,7 @@ impl<'a> Lexer<'a> {
 
         // Parse digits before decimal point
         while let Some(&c) = self.chars.peek() {
-            if c.is_digit(10) {
+            if c.is_ascii_digit() {
                 number.push(c);
                 self.advance();
             } else if c == '.' {
@@ -532,7 +594,7 @@ impl<'a> Lexer<'a> {
         // Parse digits after decimal point if it's a float
         if is_float {
             while let Some(&c) = self.chars.peek() {
-                if c.is_digit(10) {
+                if c.is_ascii_digit() {
                     number.push(c);
                     self.advance();
                 } else {
@@ -541,6 +603,30 @@ impl<'a> Lexer<'a> {
             }
         }
 
+        // Parse an exponent, which needs at least one digit after the optional sign
+        if let Some('e' | 'E') = self.chars.peek() {
+            let mut lookahead = self.chars.clone();
+            lookahead.next();
+            let sign = lookahead.next_if(|&c| c == '+' || c == '-');
+            if lookahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                is_float = true;
+                number.push('e');
+                self.advance();
+                if let Some(sign) = sign {
+                    number.push(sign);
+                    self.advance();
+                }
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_ascii_digit() {
+                        number.push(c);
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+
         // Parse the number
         if is_float {
             match number.parse::<f64>() {
@@ -559,10 +645,17 @@ impl<'a> Lexer<'a> {
                     kind: TokenKind::IntLiteral(value),
                     location,
                 }),
-                Err(_) => Err(lexical_error(
-                    &location,
-                    format!("Invalid integer literal: {}", number),
-                )),
+                // Integers too large for an int are floats, as in PHP
+                Err(_) => match number.parse::<f64>() {
+                    Ok(value) => Ok(Token {
+                        kind: TokenKind::FloatLiteral(value),
+                        location,
+                    }),
+                    Err(_) => Err(lexical_error(
+                        &location,
+                        format!("Invalid integer literal: {}", number),
+                    )),
+                },
             }
         }
     }
@@ -632,7 +725,7 @@ impl<'a> Lexer<'a> {
         token
     }
 
-    /// Tokenize plus (+) or plus equals (+=)
+    /// Tokenize plus (+), plus equals (+=) or increment (++)
     fn tokenize_plus(&mut self) -> Token {
         let location = Location {
             file: self.file.clone(),
@@ -642,7 +735,13 @@ impl<'a> Lexer<'a> {
 
         self.advance(); // Skip +
 
-        if self.chars.peek() == Some(&'=') {
+        if self.chars.peek() == Some(&'+') {
+            self.advance(); // Skip +
+            Token {
+                kind: TokenKind::Increment,
+                location,
+            }
+        } else if self.chars.peek() == Some(&'=') {
             self.advance(); // Skip =
             Token {
                 kind: TokenKind::PlusAssign,
@@ -656,7 +755,7 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Tokenize minus (-) or minus equals (-=) or arrow (->)
+    /// Tokenize minus (-), minus equals (-=), decrement (--) or arrow (->)
     fn tokenize_minus(&mut self) -> Token {
         let location = Location {
             file: self.file.clone(),
@@ -666,7 +765,13 @@ impl<'a> Lexer<'a> {
 
         self.advance(); // Skip -
 
-        if self.chars.peek() == Some(&'=') {
+        if self.chars.peek() == Some(&'-') {
+            self.advance(); // Skip -
+            Token {
+                kind: TokenKind::Decrement,
+                location,
+            }
+        } else if self.chars.peek() == Some(&'=') {
             self.advance(); // Skip =
             Token {
                 kind: TokenKind::MinusAssign,
@@ -686,7 +791,7 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Tokenize asterisk (*) or multiply equals (*=)
+    /// Tokenize asterisk (*), multiply equals (*=), power (**) or power equals (**=)
     fn tokenize_asterisk(&mut self) -> Token {
         let location = Location {
             file: self.file.clone(),
@@ -696,7 +801,22 @@ impl<'a> Lexer<'a> {
 
         self.advance(); // Skip *
 
-        if self.chars.peek() == Some(&'=') {
+        if self.chars.peek() == Some(&'*') {
+            self.advance(); // Skip *
+
+            if self.chars.peek() == Some(&'=') {
+                self.advance(); // Skip =
+                Token {
+                    kind: TokenKind::PowerAssign,
+                    location,
+                }
+            } else {
+                Token {
+                    kind: TokenKind::Power,
+                    location,
+                }
+            }
+        } else if self.chars.peek() == Some(&'=') {
             self.advance(); // Skip =
             Token {
                 kind: TokenKind::MultiplyAssign,
@@ -830,7 +950,7 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Tokenize less than (<) or less than or equal (<=)
+    /// Tokenize less than (<), less than or equal (<=), spaceship (<=>), shift left (<<) or shift left equals (<<=)
     fn tokenize_less_than(&mut self) -> Token {
         let location = Location {
             file: self.file.clone(),
@@ -840,11 +960,35 @@ impl<'a> Lexer<'a> {
 
         self.advance(); // Skip <
 
-        if self.chars.peek() == Some(&'=') {
+        if self.chars.peek() == Some(&'<') {
+            self.advance(); // Skip <
+
+            if self.chars.peek() == Some(&'=') {
+                self.advance(); // Skip =
+                Token {
+                    kind: TokenKind::ShiftLeftAssign,
+                    location,
+                }
+            } else {
+                Token {
+                    kind: TokenKind::ShiftLeft,
+                    location,
+                }
+            }
+        } else if self.chars.peek() == Some(&'=') {
             self.advance(); // Skip =
-            Token {
-                kind: TokenKind::LessThanEqual,
-                location,
+
+            if self.chars.peek() == Some(&'>') {
+                self.advance(); // Skip >
+                Token {
+                    kind: TokenKind::Spaceship,
+                    location,
+                }
+            } else {
+                Token {
+                    kind: TokenKind::LessThanEqual,
+                    location,
+                }
             }
         } else {
             Token {
@@ -854,7 +998,7 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Tokenize greater than (>) or greater than or equal (>=)
+    /// Tokenize greater than (>), greater than or equal (>=), shift right (>>) or shift right equals (>>=)
     fn tokenize_greater_than(&mut self) -> Token {
         let location = Location {
             file: self.file.clone(),
@@ -864,7 +1008,22 @@ impl<'a> Lexer<'a> {
 
         self.advance(); // Skip >
 
-        if self.chars.peek() == Some(&'=') {
+        if self.chars.peek() == Some(&'>') {
+            self.advance(); // Skip >
+
+            if self.chars.peek() == Some(&'=') {
+                self.advance(); // Skip =
+                Token {
+                    kind: TokenKind::ShiftRightAssign,
+                    location,
+                }
+            } else {
+                Token {
+                    kind: TokenKind::ShiftRight,
+                    location,
+                }
+            }
+        } else if self.chars.peek() == Some(&'=') {
             self.advance(); // Skip =
             Token {
                 kind: TokenKind::GreaterThanEqual,
@@ -878,7 +1037,7 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Tokenize ampersand (&) or logical and (&&)
+    /// Tokenize ampersand (&), and equals (&=) or logical and (&&)
     fn tokenize_ampersand(&mut self) -> Token {
         let location = Location {
             file: self.file.clone(),
@@ -894,16 +1053,21 @@ impl<'a> Lexer<'a> {
                 kind: TokenKind::LogicalAnd,
                 location,
             }
+        } else if self.chars.peek() == Some(&'=') {
+            self.advance(); // Skip =
+            Token {
+                kind: TokenKind::AndAssign,
+                location,
+            }
         } else {
-            // PHP doesn't use single & much, but we'll tokenize it anyway
             Token {
-                kind: TokenKind::Asterisk, // Using Asterisk as a placeholder for BitwiseAnd
+                kind: TokenKind::BitwiseAnd,
                 location,
             }
         }
     }
 
-    /// Tokenize pipe (|) or logical or (||)
+    /// Tokenize pipe (|), or equals (|=) or logical or (||)
     fn tokenize_pipe(&mut self) -> Token {
         let location = Location {
             file: self.file.clone(),
@@ -919,10 +1083,39 @@ impl<'a> Lexer<'a> {
                 kind: TokenKind::LogicalOr,
                 location,
             }
+        } else if self.chars.peek() == Some(&'=') {
+            self.advance(); // Skip =
+            Token {
+                kind: TokenKind::OrAssign,
+                location,
+            }
         } else {
-            // PHP doesn't use single | much, but we'll tokenize it anyway
             Token {
-                kind: TokenKind::Plus, // Using Plus as a placeholder for BitwiseOr
+                kind: TokenKind::BitwiseOr,
+                location,
+            }
+        }
+    }
+
+    /// Tokenize caret (^) or xor equals (^=)
+    fn tokenize_caret(&mut self) -> Token {
+        let location = Location {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+        };
+
+        self.advance(); // Skip ^
+
+        if self.chars.peek() == Some(&'=') {
+            self.advance(); // Skip =
+            Token {
+                kind: TokenKind::XorAssign,
+                location,
+            }
+        } else {
+            Token {
+                kind: TokenKind::BitwiseXor,
                 location,
             }
         }
@@ -952,6 +1145,39 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Tokenize question mark (?), null coalescing (??) or null coalescing assignment (??=)
+    fn tokenize_question_mark(&mut self) -> Token {
+        let location = Location {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+        };
+
+        self.advance(); // Skip ?
+
+        if self.chars.peek() == Some(&'?') {
+            self.advance(); // Skip ?
+
+            if self.chars.peek() == Some(&'=') {
+                self.advance(); // Skip =
+                Token {
+                    kind: TokenKind::CoalesceAssign,
+                    location,
+                }
+            } else {
+                Token {
+                    kind: TokenKind::Coalesce,
+                    location,
+                }
+            }
+        } else {
+            Token {
+                kind: TokenKind::QuestionMark,
+                location,
+            }
+        }
+    }
+
     /// Tokenize colon (:) or double colon (::)
     fn tokenize_colon(&mut self) -> Token {
         let location = Location {
@@ -4,20 +4,23 @@ pub mod lexer;
 pub mod parser;
 pub mod typechecker;
 pub mod codegen;
+pub mod cfg;
 pub mod asmgen;
+pub mod bytecode;
+pub mod vm;
 
 use std::fs;
 use std::path::Path;
 
 
-use crate::error::Result;
+use crate::error::{CompilerWarning, Result};
 use crate::lexer::Lexer;
 use crate::parser::Parser;
 use crate::typechecker::TypeChecker;
-use crate::codegen::CodeGenerator;
+use crate::codegen::{CodeGenerator, Module};
 
-/// Compile a PHP file to bytecode
-pub fn compile_file<P: AsRef<Path>>(path: P) -> Result<Vec<codegen::Instruction>> {
+/// Compile a PHP file to a bytecode module, along with the warnings found while compiling it
+pub fn compile_file<P: AsRef<Path>>(path: P) -> Result<(Module, Vec<CompilerWarning>)> {
     // Read the file
     let source = fs::read_to_string(path.as_ref())?;
     let file_name = path.as_ref().to_string_lossy().to_string();
@@ -36,7 +39,6 @@ pub fn compile_file<P: AsRef<Path>>(path: P) -> Result<Vec<codegen::Instruction>
 
     // Generate code
     let mut codegen = CodeGenerator::new();
-    let instructions = codegen.generate(&ast)?;
-
-    Ok(instructions)
+    let module = codegen.generate(&ast)?;
+    Ok((module, typechecker.warnings().to_vec()))
 }
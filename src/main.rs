@@ -1,26 +1,54 @@
 use std::env;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 use std::process::{self, Command};
-use oxiphant::asmgen::AsmGenerator;
+use oxiphant::asmgen::{AsmGenerator, Target, RUNTIME_SOURCE};
+use oxiphant::bytecode;
+use oxiphant::codegen::Module;
+use oxiphant::error::{CompilerError, Result};
+use oxiphant::vm::Vm;
 
 fn main() {
     // Parse command-line arguments
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
 
-    if args.len() < 2 {
-        eprintln!("Usage: {} <input.php> [output.exe]", args[0]);
+    // --target selects the calling convention, defaulting to the host's
+    let mut target = Target::host();
+    if let Some(pos) = args.iter().position(|arg| arg == "--target") {
+        let name = args.get(pos + 1).cloned().unwrap_or_default();
+        target = name.parse().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        args.drain(pos..pos + 2);
+    }
+
+    if args.len() < 2 || (matches!(args[1].as_str(), "run" | "build") && args.len() < 3) {
+        eprintln!("Usage: {} [--target windows-x64|sysv-x64] <input.php|input.oxb> [output.exe]", args[0]);
+        eprintln!("       {} run <input.php|input.oxb>", args[0]);
+        eprintln!("       {} build <input.php> [output.oxb]", args[0]);
         process::exit(1);
     }
 
+    if args[1] == "run" {
+        run(&args[2]);
+        return;
+    }
+    if args[1] == "build" {
+        build(&args[2], args.get(3));
+        return;
+    }
+
     let input_file = &args[1];
     let output_file = args.get(2).map(|s| s.as_str()).unwrap_or("output.exe");
 
-    // Create temporary assembly file path
+    // Create temporary assembly and runtime file paths
     let asm_file = format!("{}.s", input_file);
+    let runtime_file = format!("{}.rt.c", input_file);
 
     // Compile the PHP file to bytecode
-    let compilation_result = oxiphant::compile_file(input_file);
+    let compilation_result = load_module(input_file);
 
     if compilation_result.is_err() {
         let err = compilation_result.err().unwrap();
@@ -29,12 +57,15 @@ fn main() {
     }
 
     // If we get here, compilation was successful
-    let instructions = compilation_result.unwrap();
-    println!("Generated bytecode instructions: {:?}", instructions);
+    let module = compilation_result.unwrap();
+    println!("Generated bytecode instructions: {:?}", module.main);
 
     // Generate assembly code directly
-    let mut asmgen = AsmGenerator::new();
-    let asm_code = asmgen.generate(&instructions);
+    let mut asmgen = AsmGenerator::with_target(target);
+    let asm_code = asmgen.generate(&module).unwrap_or_else(|err| {
+        eprintln!("Compilation error: {}", err);
+        process::exit(1);
+    });
 
     // Write the assembly code to a file
     let mut file = File::create(&asm_file).unwrap_or_else(|err| {
@@ -49,10 +80,16 @@ fn main() {
 
     println!("Generated assembly code in {}", asm_file);
 
+    // Write the runtime support library next to it
+    std::fs::write(&runtime_file, RUNTIME_SOURCE).unwrap_or_else(|err| {
+        eprintln!("Error writing runtime file: {}", err);
+        process::exit(1);
+    });
+
     // Compile the assembly file with GCC
     println!("Compiling with GCC...");
     let status = Command::new("gcc")
-        .args(["-o", output_file, &asm_file])
+        .args(["-o", output_file, &asm_file, &runtime_file, "-lm"])
         .status()
         .unwrap_or_else(|err| {
             eprintln!("Failed to execute GCC: {}", err);
@@ -64,8 +101,69 @@ fn main() {
         process::exit(1);
     }
 
-    // Keep the assembly file for debugging
+    // The runtime source is always the same, so only the assembly file is kept for debugging
+    let _ = std::fs::remove_file(&runtime_file);
     println!("Assembly file kept at: {}", asm_file);
 
     println!("Successfully compiled {} to {}", input_file, output_file);
 }
+
+/// Run a PHP script with the bytecode interpreter instead of compiling it
+fn run(input_file: &str) {
+    let module = load_module(input_file).unwrap_or_else(|err| {
+        eprintln!("Compilation error: {}", err);
+        process::exit(1);
+    });
+
+    let stdout = io::stdout();
+    let mut vm = Vm::new(BufWriter::new(stdout.lock()));
+    match vm.run(&module) {
+        Ok(()) => {}
+        Err(CompilerError::RuntimeError { message }) => {
+            // Exit like PHP does on an uncaught error
+            eprintln!("PHP Fatal error:  Uncaught {}", message);
+            process::exit(255);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Compile a PHP script to a bytecode file that `run` and native compilation accept in its place
+fn build(input_file: &str, output_file: Option<&String>) {
+    let output_file = match output_file {
+        Some(file) => file.clone(),
+        None => Path::new(input_file).with_extension(bytecode::EXTENSION).to_string_lossy().into_owned(),
+    };
+
+    let module = compile(input_file).unwrap_or_else(|err| {
+        eprintln!("Compilation error: {}", err);
+        process::exit(1);
+    });
+    bytecode::write_file(&module, &output_file).unwrap_or_else(|err| {
+        eprintln!("Error writing bytecode file: {}", err);
+        process::exit(1);
+    });
+
+    println!("Successfully compiled {} to {}", input_file, output_file);
+}
+
+/// Load a precompiled bytecode file, or compile a PHP script
+fn load_module(input_file: &str) -> Result<Module> {
+    if Path::new(input_file).extension().is_some_and(|ext| ext == bytecode::EXTENSION) {
+        bytecode::read_file(input_file)
+    } else {
+        compile(input_file)
+    }
+}
+
+/// Compile a PHP script, printing the warnings found on the way
+fn compile(input_file: &str) -> Result<Module> {
+    let (module, warnings) = oxiphant::compile_file(input_file)?;
+    for warning in &warnings {
+        eprintln!("{}", warning);
+    }
+    Ok(module)
+}
@@ -1,14 +1,19 @@
 use std::iter::Peekable;
 use std::slice::Iter;
 
-use crate::ast::{BinaryOp, Location, Node, UnaryOp};
+use crate::ast::{BinaryOp, Location, Method, Node, Property, Type, UnaryOp};
 use crate::error::{syntax_error, Result};
 use crate::lexer::{Token, TokenKind};
 
+/// Constants, properties and methods of a class, in declaration order
+type ClassMembers = (Vec<(String, Node)>, Vec<Property>, Vec<Method>);
+
 /// Parser for PHP source code
 pub struct Parser<'a> {
     tokens: Peekable<Iter<'a, Token>>,
     current: Option<&'a Token>,
+    loop_depth: usize, // Loops and switches around the current statement, within the current function
+    current_class: Option<String>, // Class whose body is being parsed, which self and static refer to
 }
 
 impl<'a> Parser<'a> {
@@ -19,6 +24,8 @@ impl<'a> Parser<'a> {
         Self {
             tokens: iter,
             current,
+            loop_depth: 0,
+            current_class: None,
         }
     }
 
@@ -40,14 +47,6 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Check if the current token is a specific kind with a specific value
-    fn check_specific(&self, kind: &TokenKind) -> bool {
-        match self.current {
-            Some(token) => &token.kind == kind,
-            None => false,
-        }
-    }
-
     /// Consume the current token if it matches the expected kind
     fn match_token(&mut self, kind: &TokenKind) -> bool {
         if self.check(kind) {
@@ -103,11 +102,18 @@ impl<'a> Parser<'a> {
             Some(token) => match &token.kind {
                 TokenKind::Echo => self.parse_echo_statement(),
                 TokenKind::If => self.parse_if_statement(),
+                TokenKind::Do => self.parse_do_while_statement(),
                 TokenKind::While => self.parse_while_statement(),
                 TokenKind::For => self.parse_for_statement(),
                 TokenKind::Foreach => self.parse_foreach_statement(),
-                TokenKind::Function => self.parse_function_declaration(),
+                TokenKind::Switch => self.parse_switch_statement(),
+                // An anonymous function starts an expression statement
+                TokenKind::Function if !self.peek().is_some_and(|t| matches!(t.kind, TokenKind::LeftParen)) => {
+                    self.parse_function_declaration()
+                }
+                TokenKind::Class => self.parse_class_declaration(),
                 TokenKind::Return => self.parse_return_statement(),
+                TokenKind::Break | TokenKind::Continue => self.parse_break_statement(),
                 TokenKind::LeftBrace => self.parse_block(),
                 TokenKind::Variable(_) => {
                     // Variable assignment or expression
@@ -162,6 +168,10 @@ impl<'a> Parser<'a> {
         let condition = self.parse_expression()?;
         self.expect(&TokenKind::RightParen, "Expected ')' after condition")?;
 
+        if self.match_token(&TokenKind::Colon) {
+            return self.parse_alternative_if(condition, location);
+        }
+
         let then_branch = self.parse_statement()?;
 
         let else_branch = if self.match_token(&TokenKind::Else) {
@@ -181,6 +191,87 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parse the rest of an `if (...):` statement, up to and including its `endif;`
+    fn parse_alternative_if(&mut self, condition: Node, location: Location) -> Result<Node> {
+        let then_branch = self.parse_statement_list(&[TokenKind::ElseIf, TokenKind::Else, TokenKind::EndIf])?;
+
+        let else_branch = if self.check(&TokenKind::ElseIf) {
+            // Each elseif shares the endif of the whole statement
+            let elseif_location = self.current.unwrap().location.clone();
+            self.advance(); // Skip 'elseif'
+            self.expect(&TokenKind::LeftParen, "Expected '(' after 'elseif'")?;
+            let elseif_condition = self.parse_expression()?;
+            self.expect(&TokenKind::RightParen, "Expected ')' after condition")?;
+            self.expect(&TokenKind::Colon, "Expected ':' after 'elseif' condition")?;
+            Some(Box::new(self.parse_alternative_if(elseif_condition, elseif_location)?))
+        } else if self.match_token(&TokenKind::Else) {
+            self.expect(&TokenKind::Colon, "Expected ':' after 'else'")?;
+            let else_branch = self.parse_statement_list(&[TokenKind::EndIf])?;
+            self.expect(&TokenKind::EndIf, "Expected 'endif'")?;
+            self.expect(&TokenKind::Semicolon, "Expected ';' after 'endif'")?;
+            Some(Box::new(else_branch))
+        } else {
+            self.expect(&TokenKind::EndIf, "Expected 'endif'")?;
+            self.expect(&TokenKind::Semicolon, "Expected ';' after 'endif'")?;
+            None
+        };
+
+        Ok(Node::IfStmt {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch,
+            location,
+        })
+    }
+
+    /// Parse statements up to one of the keywords that close an alternative syntax block
+    fn parse_statement_list(&mut self, terminators: &[TokenKind]) -> Result<Node> {
+        let location = match self.current {
+            Some(token) => token.location.clone(),
+            None => return Err(syntax_error(
+                &Location {
+                    file: "unknown".to_string(),
+                    line: 0,
+                    column: 0,
+                },
+                "Unexpected end of file",
+            )),
+        };
+
+        let mut statements = Vec::new();
+        while !terminators.iter().any(|kind| self.check(kind)) {
+            if self.current.is_none() || self.check(&TokenKind::Eof) {
+                return Err(syntax_error(
+                    &location,
+                    format!("Unexpected end of file, expecting {:?}", terminators[terminators.len() - 1]),
+                ));
+            }
+            statements.push(self.parse_statement()?);
+        }
+
+        Ok(Node::BlockStmt(statements, location))
+    }
+
+    /// Parse a do-while statement
+    fn parse_do_while_statement(&mut self) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        self.advance(); // Skip 'do'
+
+        let body = self.parse_loop_body(None)?;
+
+        self.expect(&TokenKind::While, "Expected 'while' after do-while body")?;
+        self.expect(&TokenKind::LeftParen, "Expected '(' after 'while'")?;
+        let condition = self.parse_expression()?;
+        self.expect(&TokenKind::RightParen, "Expected ')' after condition")?;
+        self.expect(&TokenKind::Semicolon, "Expected ';' after do-while statement")?;
+
+        Ok(Node::DoWhileStmt {
+            body: Box::new(body),
+            condition: Box::new(condition),
+            location,
+        })
+    }
+
     /// Parse a while statement
     fn parse_while_statement(&mut self) -> Result<Node> {
         let location = self.current.unwrap().location.clone();
@@ -190,7 +281,7 @@ impl<'a> Parser<'a> {
         let condition = self.parse_expression()?;
         self.expect(&TokenKind::RightParen, "Expected ')' after condition")?;
 
-        let body = self.parse_statement()?;
+        let body = self.parse_loop_body(Some((TokenKind::EndWhile, "endwhile")))?;
 
         Ok(Node::WhileStmt {
             condition: Box::new(condition),
@@ -233,7 +324,7 @@ impl<'a> Parser<'a> {
             Some(Box::new(inc_expr))
         };
 
-        let body = self.parse_statement()?;
+        let body = self.parse_loop_body(Some((TokenKind::EndFor, "endfor")))?;
 
         Ok(Node::ForStmt {
             init,
@@ -299,7 +390,7 @@ impl<'a> Parser<'a> {
 
         self.expect(&TokenKind::RightParen, "Expected ')' after foreach parameters")?;
 
-        let body = self.parse_statement()?;
+        let body = self.parse_loop_body(Some((TokenKind::EndForeach, "endforeach")))?;
 
         Ok(Node::ForeachStmt {
             array: Box::new(array),
@@ -310,6 +401,85 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parse a switch statement
+    fn parse_switch_statement(&mut self) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        self.advance(); // Skip 'switch'
+
+        self.expect(&TokenKind::LeftParen, "Expected '(' after 'switch'")?;
+        let subject = self.parse_expression()?;
+        self.expect(&TokenKind::RightParen, "Expected ')' after switch subject")?;
+        self.expect(&TokenKind::LeftBrace, "Expected '{' after switch subject")?;
+
+        // break and continue leave a switch like they leave a loop
+        self.loop_depth += 1;
+        let cases = self.parse_switch_cases();
+        self.loop_depth -= 1;
+        let cases = cases?;
+
+        if cases.iter().filter(|(value, _)| value.is_none()).count() > 1 {
+            return Err(syntax_error(
+                &location,
+                "Switch statements may only contain one default clause",
+            ));
+        }
+
+        Ok(Node::SwitchStmt {
+            subject: Box::new(subject),
+            cases,
+            location,
+        })
+    }
+
+    /// Parse the cases of a switch statement, up to and including its closing brace
+    fn parse_switch_cases(&mut self) -> Result<Vec<(Option<Node>, Vec<Node>)>> {
+        let mut cases = Vec::new();
+        while !self.match_token(&TokenKind::RightBrace) {
+            let value = if self.match_token(&TokenKind::Case) {
+                Some(self.parse_expression()?)
+            } else if self.match_token(&TokenKind::Default) {
+                None
+            } else {
+                return Err(match self.current {
+                    Some(token) => syntax_error(
+                        &token.location,
+                        format!("Expected 'case' or 'default' in switch, found {:?}", token.kind),
+                    ),
+                    None => syntax_error(
+                        &Location {
+                            file: "unknown".to_string(),
+                            line: 0,
+                            column: 0,
+                        },
+                        "Unexpected end of file in switch",
+                    ),
+                });
+            };
+
+            // A case label may also end with a semicolon
+            if !self.match_token(&TokenKind::Semicolon) {
+                self.expect(&TokenKind::Colon, "Expected ':' after case")?;
+            }
+
+            let mut statements = Vec::new();
+            while !self.check(&TokenKind::Case) && !self.check(&TokenKind::Default) && !self.check(&TokenKind::RightBrace) {
+                if self.current.is_none() || self.check(&TokenKind::Eof) {
+                    return Err(syntax_error(
+                        &self.current.map(|token| token.location.clone()).unwrap_or(Location {
+                            file: "unknown".to_string(),
+                            line: 0,
+                            column: 0,
+                        }),
+                        "Unexpected end of file in switch",
+                    ));
+                }
+                statements.push(self.parse_statement()?);
+            }
+            cases.push((value, statements));
+        }
+        Ok(cases)
+    }
+
     /// Parse a function declaration
     fn parse_function_declaration(&mut self) -> Result<Node> {
         let location = self.current.unwrap().location.clone();
@@ -334,8 +504,19 @@ impl<'a> Parser<'a> {
         };
 
         self.expect(&TokenKind::LeftParen, "Expected '(' after function name")?;
+        let params = self.parse_parameters(&location)?;
+        let body = self.parse_function_body()?;
 
-        // Parse parameters
+        Ok(Node::FunctionDecl {
+            name,
+            params,
+            body: Box::new(body),
+            location,
+        })
+    }
+
+    /// Parse the parameters of a function after its '(', up to and including the ')'
+    fn parse_parameters(&mut self, location: &Location) -> Result<Vec<(String, Option<Type>)>> {
         let mut params = Vec::new();
 
         if !self.check(&TokenKind::RightParen) {
@@ -356,7 +537,7 @@ impl<'a> Parser<'a> {
                     }
                 } else {
                     return Err(syntax_error(
-                        &location,
+                        location,
                         "Unexpected end of file",
                     ));
                 }
@@ -368,13 +549,258 @@ impl<'a> Parser<'a> {
         }
 
         self.expect(&TokenKind::RightParen, "Expected ')' after parameters")?;
+        Ok(params)
+    }
 
-        // Parse function body
-        let body = self.parse_block()?;
+    /// Parse the body of a function, where loops around its declaration cannot be left
+    fn parse_function_body(&mut self) -> Result<Node> {
+        let saved_loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.parse_block();
+        self.loop_depth = saved_loop_depth;
+        body
+    }
 
-        Ok(Node::FunctionDecl {
-            name,
+    /// Parse a class declaration with its constants, properties and methods
+    fn parse_class_declaration(&mut self) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        self.advance(); // Skip 'class'
+
+        let token = self.expect(&TokenKind::Identifier(String::new()), "Expected class name")?;
+        let TokenKind::Identifier(name) = &token.kind else { unreachable!() };
+        self.expect(&TokenKind::LeftBrace, "Expected '{' after class name")?;
+
+        // self and static refer to this class inside its body
+        let saved_class = self.current_class.replace(name.clone());
+        let members = self.parse_class_members(name, &location);
+        self.current_class = saved_class;
+        let (constants, properties, methods) = members?;
+
+        Ok(Node::ClassDecl {
+            name: name.clone(),
+            constants,
+            properties,
+            methods,
+            location,
+        })
+    }
+
+    /// Parse the members of a class after its '{', up to and including the '}'
+    fn parse_class_members(
+        &mut self,
+        class: &str,
+        class_location: &Location,
+    ) -> Result<ClassMembers> {
+        let mut constants: Vec<(String, Node)> = Vec::new();
+        let mut properties: Vec<Property> = Vec::new();
+        let mut methods: Vec<Method> = Vec::new();
+
+        while !self.match_token(&TokenKind::RightBrace) {
+            let location = match self.current {
+                Some(token) if token.kind != TokenKind::Eof => token.location.clone(),
+                _ => return Err(syntax_error(class_location, "Expected '}' after class members")),
+            };
+
+            // Visibility is accepted, but every member can be used from anywhere
+            let mut has_modifier = false;
+            let mut is_static = false;
+            while let Some(token) = self.current {
+                match token.kind {
+                    TokenKind::Public | TokenKind::Protected | TokenKind::Private => {}
+                    TokenKind::Static => is_static = true,
+                    _ => break,
+                }
+                has_modifier = true;
+                self.advance();
+            }
+
+            if self.match_token(&TokenKind::Const) {
+                if is_static {
+                    return Err(syntax_error(&location, "Cannot use 'static' as constant modifier"));
+                }
+                loop {
+                    let token = self.expect(&TokenKind::Identifier(String::new()), "Expected constant name")?;
+                    let TokenKind::Identifier(name) = &token.kind else { unreachable!() };
+                    if constants.iter().any(|(constant, _)| constant == name) {
+                        return Err(syntax_error(
+                            &token.location,
+                            format!("Cannot redefine class constant {}::{}", class, name),
+                        ));
+                    }
+                    self.expect(&TokenKind::Assign, "Expected '=' after constant name")?;
+                    constants.push((name.clone(), self.parse_expression()?));
+
+                    if !self.match_token(&TokenKind::Comma) {
+                        break;
+                    }
+                }
+                self.expect(&TokenKind::Semicolon, "Expected ';' after class constant")?;
+            } else if self.match_token(&TokenKind::Function) {
+                let token = self.expect(&TokenKind::Identifier(String::new()), "Expected method name")?;
+                let TokenKind::Identifier(name) = &token.kind else { unreachable!() };
+                if methods.iter().any(|method| method.name == *name) {
+                    return Err(syntax_error(&token.location, format!("Cannot redeclare {}::{}()", class, name)));
+                }
+                if is_static && name == "__construct" {
+                    return Err(syntax_error(
+                        &token.location,
+                        format!("Method {}::__construct() cannot be static", class),
+                    ));
+                }
+
+                self.expect(&TokenKind::LeftParen, "Expected '(' after method name")?;
+                let params = self.parse_parameters(&location)?;
+                let body = self.parse_function_body()?;
+                methods.push(Method {
+                    name: name.clone(),
+                    is_static,
+                    params,
+                    body,
+                    location,
+                });
+            } else {
+                // A property needs a modifier, and may have a type
+                if !has_modifier {
+                    return Err(syntax_error(&location, "Expected a constant, property or method declaration"));
+                }
+                if is_static {
+                    return Err(syntax_error(&location, "Static properties are not supported"));
+                }
+                let type_ = if self.check(&TokenKind::Variable(String::new())) {
+                    None
+                } else {
+                    Some(self.parse_type()?)
+                };
+
+                loop {
+                    let token = self.expect(&TokenKind::Variable(String::new()), "Expected property name")?;
+                    let TokenKind::Variable(name) = &token.kind else { unreachable!() };
+                    if properties.iter().any(|property| property.name == *name) {
+                        return Err(syntax_error(&token.location, format!("Cannot redeclare {}::${}", class, name)));
+                    }
+                    let default = if self.match_token(&TokenKind::Assign) {
+                        Some(self.parse_expression()?)
+                    } else {
+                        None
+                    };
+                    properties.push(Property {
+                        name: name.clone(),
+                        type_: type_.clone(),
+                        default,
+                        location: token.location.clone(),
+                    });
+
+                    if !self.match_token(&TokenKind::Comma) {
+                        break;
+                    }
+                }
+                self.expect(&TokenKind::Semicolon, "Expected ';' after property declaration")?;
+            }
+        }
+
+        Ok((constants, properties, methods))
+    }
+
+    /// Parse a type declaration: int, float, string, bool, array, mixed or a class name,
+    /// which a ? in front of it makes nullable
+    fn parse_type(&mut self) -> Result<Type> {
+        if let Some(question_mark) = self.current.filter(|token| token.kind == TokenKind::QuestionMark) {
+            self.advance();
+            return match self.parse_type()? {
+                Type::Mixed => Err(syntax_error(
+                    &question_mark.location,
+                    "Type mixed cannot be marked as nullable since mixed already includes null",
+                )),
+                Type::Nullable(_) => Err(syntax_error(&question_mark.location, "Expected type, found QuestionMark")),
+                type_ => Ok(Type::Nullable(Box::new(type_))),
+            };
+        }
+        let token = self.expect(&TokenKind::Identifier(String::new()), "Expected type")?;
+        let TokenKind::Identifier(name) = &token.kind else { unreachable!() };
+        Ok(match name.as_str() {
+            "int" => Type::Integer,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "bool" => Type::Boolean,
+            "array" => Type::Array,
+            "mixed" => Type::Mixed,
+            _ => Type::Object(self.class_name(token)?),
+        })
+    }
+
+    /// The class that a class name refers to, with self and static resolved to the enclosing class
+    fn class_name(&self, token: &Token) -> Result<String> {
+        let keyword = match &token.kind {
+            TokenKind::Identifier(name) if name != "self" => return Ok(name.clone()),
+            TokenKind::Identifier(_) => "self",
+            _ => "static",
+        };
+        self.current_class.clone().ok_or_else(|| {
+            syntax_error(&token.location, format!("Cannot use \"{}\" when no class scope is active", keyword))
+        })
+    }
+
+    /// Parse an anonymous function: function (params) use ($a, $b) { body }
+    fn parse_closure(&mut self) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        self.advance(); // Skip 'function'
+
+        self.expect(&TokenKind::LeftParen, "Expected '(' after 'function'")?;
+        let params = self.parse_parameters(&location)?;
+
+        // Variables captured by value from the enclosing scope
+        let mut uses = Vec::new();
+        if self.match_token(&TokenKind::Use) {
+            self.expect(&TokenKind::LeftParen, "Expected '(' after 'use'")?;
+            while !self.match_token(&TokenKind::RightParen) {
+                let token = self.expect(&TokenKind::Variable(String::new()), "Expected variable in use list")?;
+                let TokenKind::Variable(name) = &token.kind else { unreachable!() };
+                if name == "this" {
+                    return Err(syntax_error(&token.location, "Cannot use $this as lexical variable"));
+                }
+                if params.iter().any(|(param, _)| param == name) {
+                    return Err(syntax_error(
+                        &token.location,
+                        format!("Cannot use lexical variable ${} as a parameter name", name),
+                    ));
+                }
+                uses.push(name.clone());
+
+                if !self.match_token(&TokenKind::Comma) {
+                    self.expect(&TokenKind::RightParen, "Expected ')' after use list")?;
+                    break;
+                }
+            }
+        }
+
+        let body = self.parse_function_body()?;
+
+        Ok(Node::Closure {
             params,
+            uses: Some(uses),
+            body: Box::new(body),
+            location,
+        })
+    }
+
+    /// Parse an arrow function: fn (params) => expression
+    fn parse_arrow_function(&mut self) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        self.advance(); // Skip 'fn'
+
+        self.expect(&TokenKind::LeftParen, "Expected '(' after 'fn'")?;
+        let params = self.parse_parameters(&location)?;
+        self.expect(&TokenKind::DoubleArrow, "Expected '=>' after arrow function parameters")?;
+
+        // The body is a single expression whose value is returned
+        let saved_loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let value = self.parse_expression();
+        self.loop_depth = saved_loop_depth;
+        let value = value?;
+        let body = Node::BlockStmt(vec![Node::ReturnStmt(Some(Box::new(value)), location.clone())], location.clone());
+
+        Ok(Node::Closure {
+            params,
+            uses: None,
             body: Box::new(body),
             location,
         })
@@ -396,6 +822,75 @@ impl<'a> Parser<'a> {
         Ok(Node::ReturnStmt(value, location))
     }
 
+    /// Parse a break or continue statement with an optional number of loops
+    fn parse_break_statement(&mut self) -> Result<Node> {
+        let token = self.current.unwrap();
+        let location = token.location.clone();
+        let is_break = token.kind == TokenKind::Break;
+        let keyword = if is_break { "break" } else { "continue" };
+        self.advance(); // Skip 'break' or 'continue'
+
+        let depth = match self.current.map(|token| &token.kind) {
+            Some(TokenKind::IntLiteral(depth)) if *depth > 0 => {
+                let depth = *depth as usize;
+                self.advance();
+                depth
+            }
+            Some(TokenKind::IntLiteral(_)) => {
+                return Err(syntax_error(
+                    &location,
+                    format!("'{}' operator accepts only positive integers", keyword),
+                ));
+            }
+            Some(TokenKind::Semicolon) => 1,
+            _ => {
+                return Err(syntax_error(
+                    &location,
+                    format!("'{}' operator with non-integer operand is no longer supported", keyword),
+                ));
+            }
+        };
+        self.expect(&TokenKind::Semicolon, &format!("Expected ';' after '{}'", keyword))?;
+
+        if self.loop_depth == 0 {
+            return Err(syntax_error(
+                &location,
+                format!("'{}' not in the 'loop' or 'switch' context", keyword),
+            ));
+        }
+        if depth > self.loop_depth {
+            return Err(syntax_error(
+                &location,
+                format!("Cannot '{}' {} levels", keyword, depth),
+            ));
+        }
+
+        if is_break {
+            Ok(Node::BreakStmt(depth, location))
+        } else {
+            Ok(Node::ContinueStmt(depth, location))
+        }
+    }
+
+    /// Parse the body of a loop, which break and continue may leave
+    ///
+    /// With an end keyword, the body may also be written as `: statements endkeyword;`.
+    fn parse_loop_body(&mut self, end: Option<(TokenKind, &str)>) -> Result<Node> {
+        self.loop_depth += 1;
+        let body = match end {
+            Some((end, keyword)) if self.match_token(&TokenKind::Colon) => {
+                self.parse_statement_list(std::slice::from_ref(&end)).and_then(|body| {
+                    self.expect(&end, &format!("Expected '{}'", keyword))?;
+                    self.expect(&TokenKind::Semicolon, &format!("Expected ';' after '{}'", keyword))?;
+                    Ok(body)
+                })
+            }
+            _ => self.parse_statement(),
+        };
+        self.loop_depth -= 1;
+        body
+    }
+
     /// Parse a block statement
     fn parse_block(&mut self) -> Result<Node> {
         let location = self.current.unwrap().location.clone();
@@ -414,181 +909,257 @@ impl<'a> Parser<'a> {
 
     /// Parse an expression
     fn parse_expression(&mut self) -> Result<Node> {
-        self.parse_assignment()
+        self.parse_keyword_or()
+    }
+
+    /// Parse an `or` expression, which binds more loosely than assignment
+    fn parse_keyword_or(&mut self) -> Result<Node> {
+        let mut expr = self.parse_keyword_xor()?;
+
+        while self.match_token(&TokenKind::Or) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_keyword_xor()?;
+
+            expr = Node::BinaryExpr {
+                op: BinaryOp::LogicalOr,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse an `xor` expression
+    fn parse_keyword_xor(&mut self) -> Result<Node> {
+        let mut expr = self.parse_keyword_and()?;
+
+        while self.match_token(&TokenKind::Xor) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_keyword_and()?;
+
+            expr = Node::BinaryExpr {
+                op: BinaryOp::LogicalXor,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse an `and` expression
+    fn parse_keyword_and(&mut self) -> Result<Node> {
+        let mut expr = self.parse_assignment()?;
+
+        while self.match_token(&TokenKind::And) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_assignment()?;
+
+            expr = Node::BinaryExpr {
+                op: BinaryOp::LogicalAnd,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
     }
 
     /// Parse an assignment expression
     fn parse_assignment(&mut self) -> Result<Node> {
-        let expr = self.parse_logical_or()?;
+        let expr = self.parse_ternary()?;
 
         if self.match_token(&TokenKind::Assign) {
             let location = self.current.unwrap().location.clone();
             let value = self.parse_assignment()?;
 
             // Check that the left side is a valid assignment target
-            match expr {
-                Node::Variable(_, _) => {
-                    Ok(Node::BinaryExpr {
-                        op: BinaryOp::Assign,
-                        left: Box::new(expr),
-                        right: Box::new(value),
-                        location,
-                    })
-                }
-                _ => Err(syntax_error(
+            if Self::is_assignment_target(&expr) {
+                Ok(Node::BinaryExpr {
+                    op: BinaryOp::Assign,
+                    left: Box::new(expr),
+                    right: Box::new(value),
+                    location,
+                })
+            } else {
+                Err(syntax_error(
                     &location,
                     "Invalid assignment target",
-                )),
+                ))
             }
         } else if self.match_token(&TokenKind::PlusAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::Add)
+        } else if self.match_token(&TokenKind::MinusAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::Subtract)
+        } else if self.match_token(&TokenKind::MultiplyAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::Multiply)
+        } else if self.match_token(&TokenKind::PowerAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::Power)
+        } else if self.match_token(&TokenKind::DivideAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::Divide)
+        } else if self.match_token(&TokenKind::ModuloAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::Modulo)
+        } else if self.match_token(&TokenKind::ConcatAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::Concat)
+        } else if self.match_token(&TokenKind::AndAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::BitwiseAnd)
+        } else if self.match_token(&TokenKind::OrAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::BitwiseOr)
+        } else if self.match_token(&TokenKind::XorAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::BitwiseXor)
+        } else if self.match_token(&TokenKind::ShiftLeftAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::ShiftLeft)
+        } else if self.match_token(&TokenKind::ShiftRightAssign) {
+            self.parse_compound_assignment(expr, BinaryOp::ShiftRight)
+        } else if self.match_token(&TokenKind::CoalesceAssign) {
             let location = self.current.unwrap().location.clone();
             let value = self.parse_assignment()?;
 
-            // a += b is equivalent to a = a + b
-            match expr {
-                Node::Variable(ref name, ref var_loc) => {
-                    let var_expr = Node::Variable(name.clone(), var_loc.clone());
-                    let add_expr = Node::BinaryExpr {
-                        op: BinaryOp::Add,
-                        left: Box::new(var_expr),
-                        right: Box::new(value),
-                        location: location.clone(),
-                    };
+            if !Self::is_assignment_target(&expr) {
+                return Err(syntax_error(&location, "Invalid assignment target"));
+            }
 
-                    Ok(Node::BinaryExpr {
-                        op: BinaryOp::Assign,
-                        left: Box::new(expr),
-                        right: Box::new(add_expr),
-                        location,
-                    })
-                }
-                _ => Err(syntax_error(
-                    &location,
-                    "Invalid assignment target",
-                )),
+            // Array elements and properties keep their keys and object, which must only be evaluated once
+            if !matches!(expr, Node::Variable(..)) {
+                return Ok(Node::CompoundAssign {
+                    op: BinaryOp::Coalesce,
+                    target: Box::new(expr),
+                    value: Box::new(value),
+                    location,
+                });
             }
-        } else if self.match_token(&TokenKind::MinusAssign) {
-            let location = self.current.unwrap().location.clone();
-            let value = self.parse_assignment()?;
 
-            // a -= b is equivalent to a = a - b
-            match expr {
-                Node::Variable(ref name, ref var_loc) => {
-                    let var_expr = Node::Variable(name.clone(), var_loc.clone());
-                    let sub_expr = Node::BinaryExpr {
-                        op: BinaryOp::Subtract,
-                        left: Box::new(var_expr),
-                        right: Box::new(value),
-                        location: location.clone(),
-                    };
+            // a ??= b is equivalent to a ?? (a = b), so b is only evaluated when a is null
+            let assign = Node::BinaryExpr {
+                op: BinaryOp::Assign,
+                left: Box::new(expr.clone()),
+                right: Box::new(value),
+                location: location.clone(),
+            };
+            Ok(Node::BinaryExpr {
+                op: BinaryOp::Coalesce,
+                left: Box::new(expr),
+                right: Box::new(assign),
+                location,
+            })
+        } else {
+            Ok(expr)
+        }
+    }
 
-                    Ok(Node::BinaryExpr {
-                        op: BinaryOp::Assign,
-                        left: Box::new(expr),
-                        right: Box::new(sub_expr),
-                        location,
-                    })
-                }
-                _ => Err(syntax_error(
-                    &location,
-                    "Invalid assignment target",
-                )),
+    /// Parse the value of a compound assignment such as `a += b`
+    fn parse_compound_assignment(&mut self, expr: Node, op: BinaryOp) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        let value = self.parse_assignment()?;
+
+        match expr {
+            // a += b is equivalent to a = a + b
+            Node::Variable(ref name, ref var_loc) => {
+                let var_expr = Node::Variable(name.clone(), var_loc.clone());
+                let op_expr = Node::BinaryExpr {
+                    op,
+                    left: Box::new(var_expr),
+                    right: Box::new(value),
+                    location: location.clone(),
+                };
+
+                Ok(Node::BinaryExpr {
+                    op: BinaryOp::Assign,
+                    left: Box::new(expr),
+                    right: Box::new(op_expr),
+                    location,
+                })
             }
-        } else if self.match_token(&TokenKind::MultiplyAssign) {
-            let location = self.current.unwrap().location.clone();
-            let value = self.parse_assignment()?;
+            // Array elements keep their keys, which must only be evaluated once
+            _ if Self::is_assignment_target(&expr) => Ok(Node::CompoundAssign {
+                op,
+                target: Box::new(expr),
+                value: Box::new(value),
+                location,
+            }),
+            _ => Err(syntax_error(
+                &location,
+                "Invalid assignment target",
+            )),
+        }
+    }
 
-            // a *= b is equivalent to a = a * b
-            match expr {
-                Node::Variable(ref name, ref var_loc) => {
-                    let var_expr = Node::Variable(name.clone(), var_loc.clone());
-                    let mul_expr = Node::BinaryExpr {
-                        op: BinaryOp::Multiply,
-                        left: Box::new(var_expr),
-                        right: Box::new(value),
-                        location: location.clone(),
-                    };
+    /// Check whether a node can be assigned to: a variable, a property, or an element
+    /// of one such as `$a[1]['x']`, `$a[]` or `$o->items[]`
+    fn is_assignment_target(node: &Node) -> bool {
+        match node {
+            Node::Variable(_, _) | Node::PropertyAccess { .. } => true,
+            Node::BinaryExpr { op: BinaryOp::ArrayAccess, left, .. } => Self::is_assignment_target(left),
+            Node::ArrayAppend(array, _) => Self::is_assignment_target(array),
+            _ => false,
+        }
+    }
 
-                    Ok(Node::BinaryExpr {
-                        op: BinaryOp::Assign,
-                        left: Box::new(expr),
-                        right: Box::new(mul_expr),
-                        location,
-                    })
-                }
-                _ => Err(syntax_error(
-                    &location,
-                    "Invalid assignment target",
-                )),
-            }
-        } else if self.match_token(&TokenKind::DivideAssign) {
-            let location = self.current.unwrap().location.clone();
-            let value = self.parse_assignment()?;
+    /// Parse a conditional expression: `a ? b : c` or `a ?: b`
+    fn parse_ternary(&mut self) -> Result<Node> {
+        let mut expr = self.parse_coalesce()?;
+        let mut previous_short = None;
 
-            // a /= b is equivalent to a = a / b
-            match expr {
-                Node::Variable(ref name, ref var_loc) => {
-                    let var_expr = Node::Variable(name.clone(), var_loc.clone());
-                    let div_expr = Node::BinaryExpr {
-                        op: BinaryOp::Divide,
-                        left: Box::new(var_expr),
-                        right: Box::new(value),
-                        location: location.clone(),
-                    };
+        while self.match_token(&TokenKind::QuestionMark) {
+            let location = self.current.unwrap().location.clone();
+            let then_branch = if self.match_token(&TokenKind::Colon) {
+                None
+            } else {
+                let then_branch = self.parse_assignment()?;
+                self.expect(&TokenKind::Colon, "Expected ':' in conditional expression")?;
+                Some(Box::new(then_branch))
+            };
 
-                    Ok(Node::BinaryExpr {
-                        op: BinaryOp::Assign,
-                        left: Box::new(expr),
-                        right: Box::new(div_expr),
-                        location,
-                    })
-                }
-                _ => Err(syntax_error(
+            // Like PHP 8, only chains of `?:` may go without parentheses
+            let short = then_branch.is_none();
+            if previous_short.is_some_and(|previous| !(previous && short)) {
+                return Err(syntax_error(
                     &location,
-                    "Invalid assignment target",
-                )),
+                    "Nested ternary expressions must be parenthesized",
+                ));
             }
-        } else if self.match_token(&TokenKind::ConcatAssign) {
-            let location = self.current.unwrap().location.clone();
-            let value = self.parse_assignment()?;
+            previous_short = Some(short);
 
-            // a .= b is equivalent to a = a . b
-            match expr {
-                Node::Variable(ref name, ref var_loc) => {
-                    let var_expr = Node::Variable(name.clone(), var_loc.clone());
-                    let concat_expr = Node::BinaryExpr {
-                        op: BinaryOp::Concat,
-                        left: Box::new(var_expr),
-                        right: Box::new(value),
-                        location: location.clone(),
-                    };
+            let else_branch = self.parse_coalesce()?;
+            expr = Node::Ternary {
+                condition: Box::new(expr),
+                then_branch,
+                else_branch: Box::new(else_branch),
+                location,
+            };
+        }
 
-                    Ok(Node::BinaryExpr {
-                        op: BinaryOp::Assign,
-                        left: Box::new(expr),
-                        right: Box::new(concat_expr),
-                        location,
-                    })
-                }
-                _ => Err(syntax_error(
-                    &location,
-                    "Invalid assignment target",
-                )),
-            }
-        } else {
-            Ok(expr)
+        Ok(expr)
+    }
+
+    /// Parse a null coalescing expression, which groups to the right
+    fn parse_coalesce(&mut self) -> Result<Node> {
+        let expr = self.parse_logical_or()?;
+
+        if self.match_token(&TokenKind::Coalesce) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_coalesce()?;
+
+            return Ok(Node::BinaryExpr {
+                op: BinaryOp::Coalesce,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            });
         }
+
+        Ok(expr)
     }
 
     /// Parse a logical OR expression
     fn parse_logical_or(&mut self) -> Result<Node> {
         let mut expr = self.parse_logical_and()?;
 
-        while self.match_token(&TokenKind::LogicalOr) || self.check_specific(&TokenKind::Or) {
-            if self.check_specific(&TokenKind::Or) {
-                self.advance(); // Skip 'or'
-            }
-
+        while self.match_token(&TokenKind::LogicalOr) {
             let location = self.current.unwrap().location.clone();
             let right = self.parse_logical_and()?;
 
@@ -605,18 +1176,71 @@ impl<'a> Parser<'a> {
 
     /// Parse a logical AND expression
     fn parse_logical_and(&mut self) -> Result<Node> {
-        let mut expr = self.parse_equality()?;
+        let mut expr = self.parse_bitwise_or()?;
 
-        while self.match_token(&TokenKind::LogicalAnd) || self.check_specific(&TokenKind::And) {
-            if self.check_specific(&TokenKind::And) {
-                self.advance(); // Skip 'and'
-            }
+        while self.match_token(&TokenKind::LogicalAnd) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_bitwise_or()?;
 
+            expr = Node::BinaryExpr {
+                op: BinaryOp::LogicalAnd,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse a bitwise OR expression
+    fn parse_bitwise_or(&mut self) -> Result<Node> {
+        let mut expr = self.parse_bitwise_xor()?;
+
+        while self.match_token(&TokenKind::BitwiseOr) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_bitwise_xor()?;
+
+            expr = Node::BinaryExpr {
+                op: BinaryOp::BitwiseOr,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse a bitwise XOR expression
+    fn parse_bitwise_xor(&mut self) -> Result<Node> {
+        let mut expr = self.parse_bitwise_and()?;
+
+        while self.match_token(&TokenKind::BitwiseXor) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_bitwise_and()?;
+
+            expr = Node::BinaryExpr {
+                op: BinaryOp::BitwiseXor,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse a bitwise AND expression
+    fn parse_bitwise_and(&mut self) -> Result<Node> {
+        let mut expr = self.parse_equality()?;
+
+        while self.match_token(&TokenKind::BitwiseAnd) {
             let location = self.current.unwrap().location.clone();
             let right = self.parse_equality()?;
 
             expr = Node::BinaryExpr {
-                op: BinaryOp::LogicalAnd,
+                op: BinaryOp::BitwiseAnd,
                 left: Box::new(expr),
                 right: Box::new(right),
                 location,
@@ -636,11 +1260,11 @@ impl<'a> Parser<'a> {
             } else if self.match_token(&TokenKind::NotEqual) {
                 BinaryOp::NotEqual
             } else if self.match_token(&TokenKind::Identical) {
-                // For simplicity, we'll treat === the same as == for now
-                BinaryOp::Equal
+                BinaryOp::Identical
             } else if self.match_token(&TokenKind::NotIdentical) {
-                // For simplicity, we'll treat !== the same as != for now
-                BinaryOp::NotEqual
+                BinaryOp::NotIdentical
+            } else if self.match_token(&TokenKind::Spaceship) {
+                BinaryOp::Spaceship
             } else {
                 break;
             };
@@ -661,7 +1285,7 @@ impl<'a> Parser<'a> {
 
     /// Parse a relational expression
     fn parse_relational(&mut self) -> Result<Node> {
-        let mut expr = self.parse_additive()?;
+        let mut expr = self.parse_concat()?;
 
         loop {
             let op = if self.match_token(&TokenKind::LessThan) {
@@ -676,6 +1300,52 @@ impl<'a> Parser<'a> {
                 break;
             };
 
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_concat()?;
+
+            expr = Node::BinaryExpr {
+                op,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse a concatenation, which binds more loosely than shifts and arithmetic as in PHP 8
+    fn parse_concat(&mut self) -> Result<Node> {
+        let mut expr = self.parse_shift()?;
+
+        while self.match_token(&TokenKind::Concat) {
+            let location = self.current.unwrap().location.clone();
+            let right = self.parse_shift()?;
+
+            expr = Node::BinaryExpr {
+                op: BinaryOp::Concat,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse a shift expression
+    fn parse_shift(&mut self) -> Result<Node> {
+        let mut expr = self.parse_additive()?;
+
+        loop {
+            let op = if self.match_token(&TokenKind::ShiftLeft) {
+                BinaryOp::ShiftLeft
+            } else if self.match_token(&TokenKind::ShiftRight) {
+                BinaryOp::ShiftRight
+            } else {
+                break;
+            };
+
             let location = self.current.unwrap().location.clone();
             let right = self.parse_additive()?;
 
@@ -699,8 +1369,6 @@ impl<'a> Parser<'a> {
                 BinaryOp::Add
             } else if self.match_token(&TokenKind::Minus) {
                 BinaryOp::Subtract
-            } else if self.match_token(&TokenKind::Concat) {
-                BinaryOp::Concat
             } else {
                 break;
             };
@@ -751,6 +1419,13 @@ impl<'a> Parser<'a> {
     /// Parse a unary expression
     fn parse_unary(&mut self) -> Result<Node> {
         if let Some(token) = self.current {
+            // Prefix increment and decrement
+            if matches!(token.kind, TokenKind::Increment | TokenKind::Decrement) {
+                self.advance();
+                let target = self.parse_primary()?;
+                return Self::inc_dec(token, target, true);
+            }
+
             let op = match token.kind {
                 TokenKind::Minus => {
                     self.advance();
@@ -760,6 +1435,10 @@ impl<'a> Parser<'a> {
                     self.advance();
                     Some(UnaryOp::LogicalNot)
                 }
+                TokenKind::BitwiseNot => {
+                    self.advance();
+                    Some(UnaryOp::BitwiseNot)
+                }
                 _ => None,
             };
 
@@ -775,20 +1454,118 @@ impl<'a> Parser<'a> {
             }
         }
 
-        self.parse_primary()
+        self.parse_power()
+    }
+
+    /// Parse an exponentiation, which binds tighter than unary operators on its left and groups to the right
+    fn parse_power(&mut self) -> Result<Node> {
+        let expr = self.parse_postfix()?;
+
+        if self.match_token(&TokenKind::Power) {
+            let location = self.current.unwrap().location.clone();
+            // The exponent may itself have a sign, as in 2 ** -1
+            let right = self.parse_unary()?;
+
+            return Ok(Node::BinaryExpr {
+                op: BinaryOp::Power,
+                left: Box::new(expr),
+                right: Box::new(right),
+                location,
+            });
+        }
+
+        Ok(expr)
+    }
+
+    /// Parse a primary expression followed by an optional postfix increment or decrement
+    fn parse_postfix(&mut self) -> Result<Node> {
+        let expr = self.parse_primary()?;
+
+        // Postfix increment and decrement
+        if let Some(token) = self.current {
+            if matches!(token.kind, TokenKind::Increment | TokenKind::Decrement) {
+                self.advance();
+                return Self::inc_dec(token, expr, false);
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Build an increment or decrement of a variable, a property or an array element
+    fn inc_dec(token: &Token, target: Node, prefix: bool) -> Result<Node> {
+        if !Self::is_assignment_target(&target) || matches!(target, Node::ArrayAppend(..)) {
+            let operator = if token.kind == TokenKind::Increment { "++" } else { "--" };
+            return Err(syntax_error(
+                &token.location,
+                format!("Cannot use {} on an expression that is not a variable, a property or an array element", operator),
+            ));
+        }
+
+        Ok(Node::IncDec {
+            increment: token.kind == TokenKind::Increment,
+            prefix,
+            target: Box::new(target),
+            location: token.location.clone(),
+        })
     }
 
     /// Parse a primary expression
     fn parse_primary(&mut self) -> Result<Node> {
         let expr = self.parse_primary_inner()?;
-        self.parse_array_access(expr)
+        self.parse_accesses_and_calls(expr)
     }
 
-    /// Parse array access after a primary expression
-    fn parse_array_access(&mut self, mut expr: Node) -> Result<Node> {
-        // Check for array access: expr[index]
-        while self.match_token(&TokenKind::LeftBracket) {
+    /// Parse array accesses, member accesses and calls after a primary expression
+    fn parse_accesses_and_calls(&mut self, mut expr: Node) -> Result<Node> {
+        loop {
+            // expr->name reads a property, expr->name(args) calls a method
+            if self.match_token(&TokenKind::Arrow) {
+                let token = self.expect(&TokenKind::Identifier(String::new()), "Expected property or method name after '->'")?;
+                let TokenKind::Identifier(name) = &token.kind else { unreachable!() };
+                let location = token.location.clone();
+                expr = if self.match_token(&TokenKind::LeftParen) {
+                    Node::MethodCall {
+                        object: Box::new(expr),
+                        method: name.clone(),
+                        args: self.parse_arguments()?,
+                        location,
+                    }
+                } else {
+                    Node::PropertyAccess {
+                        object: Box::new(expr),
+                        property: name.clone(),
+                        location,
+                    }
+                };
+                continue;
+            }
+
+            // expr(args) calls the closure that expr evaluates to
+            if self.check(&TokenKind::LeftParen) {
+                let location = self.current.unwrap().location.clone();
+                self.advance(); // Skip (
+                let args = self.parse_arguments()?;
+                expr = Node::IndirectCall {
+                    callee: Box::new(expr),
+                    args,
+                    location,
+                };
+                continue;
+            }
+
+            // Check for array access: expr[index]
+            if !self.match_token(&TokenKind::LeftBracket) {
+                break;
+            }
             let location = self.current.unwrap().location.clone();
+
+            // expr[] appends to an array
+            if self.match_token(&TokenKind::RightBracket) {
+                expr = Node::ArrayAppend(Box::new(expr), location);
+                continue;
+            }
+
             let index = self.parse_expression()?;
             self.expect(&TokenKind::RightBracket, "Expected ']' after array index")?;
 
@@ -844,41 +1621,29 @@ impl<'a> Parser<'a> {
                         self.expect(&TokenKind::RightParen, "Expected ')' after expression")?;
                         Ok(expr)
                     }
+                    TokenKind::Match => self.parse_match_expression(),
+                    TokenKind::Function => self.parse_closure(),
+                    TokenKind::Fn => self.parse_arrow_function(),
+                    TokenKind::New => self.parse_new_expression(),
+                    TokenKind::Identifier(_) | TokenKind::Static
+                        if self.peek().is_some_and(|t| matches!(t.kind, TokenKind::DoubleColon)) => {
+                        let class = self.class_name(token)?;
+                        self.advance(); // Skip the class name
+                        self.advance(); // Skip ::
+                        self.parse_static_member(class, location)
+                    }
                     TokenKind::LeftBracket => {
                         // Array literal
                         self.advance();
-
-                        let mut elements = Vec::new();
-
-                        if !self.check(&TokenKind::RightBracket) {
-                            loop {
-                                // Parse key => value or just value
-                                let key = if self.peek().map_or(false, |t|
-                                    matches!(t.kind, TokenKind::DoubleArrow)) {
-                                    // Key is present
-                                    let key_expr = self.parse_expression()?;
-                                    self.expect(&TokenKind::DoubleArrow, "Expected '=>' after array key")?;
-                                    Some(key_expr)
-                                } else {
-                                    None
-                                };
-
-                                let value = self.parse_expression()?;
-                                elements.push((key, value));
-
-                                if !self.match_token(&TokenKind::Comma) {
-                                    break;
-                                }
-
-                                // Allow trailing comma
-                                if self.check(&TokenKind::RightBracket) {
-                                    break;
-                                }
-                            }
-                        }
-
-                        self.expect(&TokenKind::RightBracket, "Expected ']' after array elements")?;
-
+                        let elements = self.parse_array_elements(&TokenKind::RightBracket, "Expected ']' after array elements")?;
+                        Ok(Node::ArrayLiteral(elements, location))
+                    }
+                    TokenKind::Identifier(name) if name.eq_ignore_ascii_case("array")
+                        && self.peek().is_some_and(|t| matches!(t.kind, TokenKind::LeftParen)) => {
+                        // Long-form array literal: array(...)
+                        self.advance(); // Skip array
+                        self.advance(); // Skip (
+                        let elements = self.parse_array_elements(&TokenKind::RightParen, "Expected ')' after array elements")?;
                         Ok(Node::ArrayLiteral(elements, location))
                     }
                     TokenKind::Identifier(name) => {
@@ -887,20 +1652,7 @@ impl<'a> Parser<'a> {
                         // Check if it's a function call
                         if self.check(&TokenKind::LeftParen) {
                             self.advance(); // Skip (
-
-                            let mut args = Vec::new();
-
-                            if !self.check(&TokenKind::RightParen) {
-                                loop {
-                                    args.push(self.parse_expression()?);
-
-                                    if !self.match_token(&TokenKind::Comma) {
-                                        break;
-                                    }
-                                }
-                            }
-
-                            self.expect(&TokenKind::RightParen, "Expected ')' after arguments")?;
+                            let args = self.parse_arguments()?;
 
                             Ok(Node::FunctionCall {
                                 name: name.clone(),
@@ -929,5 +1681,151 @@ impl<'a> Parser<'a> {
             )),
         }
     }
-}
 
+    /// Parse an object creation: new Class(args), where the parentheses may be left out
+    fn parse_new_expression(&mut self) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        self.advance(); // Skip 'new'
+
+        let Some(token) = self.current.filter(|t| matches!(t.kind, TokenKind::Identifier(_) | TokenKind::Static)) else {
+            return Err(syntax_error(&location, "Expected class name after 'new'"));
+        };
+        let class = self.class_name(token)?;
+        self.advance();
+
+        let args = if self.match_token(&TokenKind::LeftParen) {
+            self.parse_arguments()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Node::New { class, args, location })
+    }
+
+    /// Parse what follows `Class::`: a static method call, a class constant, or `class` for the class name
+    fn parse_static_member(&mut self, class: String, location: Location) -> Result<Node> {
+        if self.match_token(&TokenKind::Class) {
+            return Ok(Node::StringLiteral(class, location));
+        }
+
+        let token = self.expect(&TokenKind::Identifier(String::new()), "Expected method or constant name after '::'")?;
+        let TokenKind::Identifier(name) = &token.kind else { unreachable!() };
+        if self.match_token(&TokenKind::LeftParen) {
+            Ok(Node::StaticCall {
+                class,
+                method: name.clone(),
+                args: self.parse_arguments()?,
+                location,
+            })
+        } else {
+            Ok(Node::ClassConstant {
+                class,
+                name: name.clone(),
+                location,
+            })
+        }
+    }
+
+    /// Parse the arguments of a call after its '(', up to and including the ')'
+    fn parse_arguments(&mut self) -> Result<Vec<Node>> {
+        let mut args = Vec::new();
+
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                args.push(self.parse_expression()?);
+
+                if !self.match_token(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.expect(&TokenKind::RightParen, "Expected ')' after arguments")?;
+        Ok(args)
+    }
+
+    /// Parse a match expression
+    fn parse_match_expression(&mut self) -> Result<Node> {
+        let location = self.current.unwrap().location.clone();
+        self.advance(); // Skip 'match'
+
+        self.expect(&TokenKind::LeftParen, "Expected '(' after 'match'")?;
+        let subject = self.parse_expression()?;
+        self.expect(&TokenKind::RightParen, "Expected ')' after match subject")?;
+        self.expect(&TokenKind::LeftBrace, "Expected '{' after match subject")?;
+
+        let mut arms = Vec::new();
+        let mut has_default = false;
+        while !self.match_token(&TokenKind::RightBrace) {
+            let arm_location = match self.current {
+                Some(token) => token.location.clone(),
+                None => location.clone(),
+            };
+
+            // Conditions are separated by commas, and may have a trailing one
+            let mut conditions = Vec::new();
+            if self.match_token(&TokenKind::Default) {
+                if has_default {
+                    return Err(syntax_error(
+                        &arm_location,
+                        "Match expressions may only contain one default arm",
+                    ));
+                }
+                has_default = true;
+            } else {
+                loop {
+                    conditions.push(self.parse_expression()?);
+                    if !self.match_token(&TokenKind::Comma) || self.check(&TokenKind::DoubleArrow) {
+                        break;
+                    }
+                }
+            }
+            self.expect(&TokenKind::DoubleArrow, "Expected '=>' after match arm conditions")?;
+            let result = self.parse_expression()?;
+            arms.push((conditions, result));
+
+            // Arms are separated by commas, and may have a trailing one
+            if !self.match_token(&TokenKind::Comma) {
+                self.expect(&TokenKind::RightBrace, "Expected ',' or '}' after match arm")?;
+                break;
+            }
+        }
+
+        Ok(Node::Match {
+            subject: Box::new(subject),
+            arms,
+            location,
+        })
+    }
+
+    /// Parse the elements of an array literal up to and including the closing token
+    fn parse_array_elements(&mut self, closing: &TokenKind, message: &str) -> Result<Vec<(Option<Node>, Node)>> {
+        let mut elements = Vec::new();
+
+        if !self.check(closing) {
+            loop {
+                // Parse key => value or just value; a key may be any expression
+                let first = self.parse_expression()?;
+                if self.match_token(&TokenKind::DoubleArrow) {
+                    let value = self.parse_expression()?;
+                    elements.push((Some(first), value));
+                } else {
+                    elements.push((None, first));
+                }
+
+                if !self.match_token(&TokenKind::Comma) {
+                    break;
+                }
+
+                // Allow trailing comma
+                if self.check(closing) {
+                    break;
+                }
+            }
+        }
+
+        self.expect(closing, message)?;
+
+        Ok(elements)
+    }
+}
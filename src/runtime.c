@@ -0,0 +1,2303 @@
+/*
+ * Oxiphant runtime support library.
+ *
+ * Every PHP value in a compiled program lives in a 16-byte tagged slot: a
+ * 32-bit type tag, a 32-bit aux field and an 8-byte payload. The generated
+ * assembly handles the common integer cases inline and calls into these
+ * helpers whenever it has to dispatch on the real type of a value.
+ *
+ * Helpers borrow the values they are passed and write new values that the
+ * caller owns. Binary helpers take the output slot first, which must not
+ * alias an operand.
+ *
+ * Strings are length-tracked: the payload points to the bytes and the aux
+ * field holds the length. The bytes are not NUL-terminated. Every string
+ * buffer, including the literals emitted by asmgen, is preceded by an
+ * ox_string_header so that concatenation can append in place.
+ *
+ * Arrays are heap-allocated, insertion-ordered hash tables. The payload of
+ * an array value points to an ox_array.
+ *
+ * Objects are heap-allocated too, and the payload of an object value points
+ * to an ox_object. Copies of the value are handles to the same object.
+ *
+ * Strings, arrays, objects and closures are reference-counted, and freed
+ * when the last variable, element, property or operand stack slot holding
+ * them lets go; see docs/memory.md. The count is the first field of each,
+ * or of the string header, where the generated code increments it inline.
+ */
+
+#include <errno.h>
+#include <inttypes.h>
+#include <math.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Type tags, must match asmgen */
+enum {
+    OX_NULL = 0,
+    OX_BOOL = 1,
+    OX_INT = 2,
+    OX_FLOAT = 3,
+    OX_STRING = 4,
+    OX_ARRAY = 5,
+    OX_CLOSURE = 6,
+    OX_OBJECT = 7,
+    OX_UNINITIALIZED = 8, /* Only in the slot of a typed property that has not been assigned yet */
+};
+
+typedef struct ox_value {
+    uint32_t type;
+    uint32_t aux; /* Length of a string, or the position of an array iterator */
+    union {
+        int64_t i;
+        double f;
+        const char *s;
+        void *p;
+    } u;
+} ox_value;
+
+/*
+ * Header in front of every string buffer. A buffer is shared by all the
+ * strings that are a prefix of its contents; only the string whose length
+ * equals `used` may append to it in place. Literals have a capacity of 0 and
+ * are never written to or freed; counting references to them is harmless.
+ */
+typedef struct ox_string_header {
+    int64_t refcount;  /* Number of values holding a string in the buffer */
+    uint64_t capacity;
+    uint64_t used;
+} ox_string_header;
+
+/* A string constant with a header, usable as a string payload */
+#define OX_STRING_CONSTANT(name, text) \
+    static struct { ox_string_header header; char data[sizeof text]; } name = { { 0, 0, sizeof text - 1 }, text }
+
+OX_STRING_CONSTANT(ox_empty_string, "");
+OX_STRING_CONSTANT(ox_one_string, "1");
+OX_STRING_CONSTANT(ox_array_string, "Array");
+
+/* Result of ox_compare when the operands are unordered (NAN) */
+#define OX_UNORDERED 2
+
+typedef struct ox_bucket {
+    ox_value key; /* Always an int or a string */
+    ox_value value;
+} ox_bucket;
+
+typedef struct ox_array {
+    int64_t refcount;   /* Number of values holding the array, it is copied on write when shared */
+    uint32_t count;     /* Number of elements */
+    uint32_t capacity;  /* Allocated buckets, a power of two */
+    int64_t next_index; /* Key used by the next append */
+    int has_next_index; /* Whether next_index is set, it is not until an int key is used */
+    ox_bucket *buckets; /* Elements in insertion order */
+    uint32_t *slots;    /* Open-addressing index into buckets, 0 = empty, else bucket + 1 */
+} ox_array;
+
+/*
+ * A closure: the code compiled from its body and the values it captured when
+ * it was created. The code finds the closure in r10 and binds the captured
+ * values before its parameters, so the layout must match asmgen.
+ */
+typedef struct ox_closure {
+    int64_t refcount;       /* Number of values holding the closure */
+    void *code;
+    uint32_t param_count;   /* Arguments a call must pass at least */
+    uint32_t capture_count; /* Number of captured values */
+    ox_value captures[];    /* In the order of the function's captured variables */
+} ox_closure;
+
+/* A method, emitted by asmgen */
+typedef struct ox_method {
+    void *code;
+    int64_t param_count; /* Arguments a call must pass at least, not counting the object */
+} ox_method;
+
+/* The declared type of a property, emitted by asmgen */
+typedef struct ox_property_type {
+    int64_t type;          /* Type tag of the values the property holds */
+    const char *name;      /* As PHP writes the type, or the class of an object type; NULL when any value is allowed */
+    int64_t uninitialized; /* Whether the property starts out uninitialized, as typed ones without a default do */
+    int64_t nullable;      /* Whether the type is ?T, which also accepts null; name starts with the ? */
+} ox_property_type;
+
+/*
+ * A class, emitted by asmgen. Properties and methods are referred to by
+ * member numbers, which are indexes into ox_member_names and the same for
+ * every class of the program.
+ */
+typedef struct ox_class {
+    const char *name;
+    int64_t property_count;
+    const int64_t *properties;               /* Member number of each property, in declaration order */
+    const ox_property_type *property_types;  /* Declared type of each property, in declaration order */
+    const ox_method *const *methods;         /* Method of each member number, NULL when the class has none */
+} ox_class;
+
+typedef struct ox_object {
+    int64_t refcount;      /* Number of values holding the object */
+    const ox_class *class;
+    ox_array *dynamic;     /* Properties the class does not declare, by name; NULL until one is assigned */
+    ox_value properties[]; /* In the order of class->properties */
+} ox_object;
+
+/* Names of all members, emitted by asmgen as string literals */
+extern const char *const ox_member_names[];
+
+int64_t ox_compare(const ox_value *a, const ox_value *b);
+static void ox_add_ref(const ox_value *v);
+void ox_release(ox_value *v);
+
+static const char *ox_type_name(const ox_value *v)
+{
+    switch (v->type) {
+    case OX_NULL: return "null";
+    case OX_BOOL: return "bool";
+    case OX_INT: return "int";
+    case OX_FLOAT: return "float";
+    case OX_STRING: return "string";
+    case OX_ARRAY: return "array";
+    case OX_CLOSURE: return "Closure";
+    case OX_OBJECT: return ((const ox_object *)v->u.p)->class->name;
+    default: return "unknown";
+    }
+}
+
+/* Abort the program with an uncaught PHP error */
+static void ox_fatal(const char *error_class, const char *fmt, ...)
+{
+    va_list args;
+
+    fflush(stdout);
+    fprintf(stderr, "PHP Fatal error:  Uncaught %s: ", error_class);
+    va_start(args, fmt);
+    vfprintf(stderr, fmt, args);
+    va_end(args);
+    fputc('\n', stderr);
+    exit(255);
+}
+
+static void ox_warning(const char *fmt, ...)
+{
+    va_list args;
+
+    fflush(stdout);
+    fprintf(stderr, "PHP Warning:  ");
+    va_start(args, fmt);
+    vfprintf(stderr, fmt, args);
+    va_end(args);
+    fputc('\n', stderr);
+}
+
+static void ox_deprecated(const char *fmt, ...)
+{
+    va_list args;
+
+    fflush(stdout);
+    fprintf(stderr, "PHP Deprecated:  ");
+    va_start(args, fmt);
+    vfprintf(stderr, fmt, args);
+    va_end(args);
+    fputc('\n', stderr);
+}
+
+static void ox_set_null(ox_value *out)
+{
+    out->type = OX_NULL;
+    out->aux = 0;
+    out->u.i = 0;
+}
+
+static void ox_set_int(ox_value *out, int64_t i)
+{
+    out->type = OX_INT;
+    out->aux = 0;
+    out->u.i = i;
+}
+
+static void ox_set_float(ox_value *out, double f)
+{
+    out->type = OX_FLOAT;
+    out->aux = 0;
+    out->u.f = f;
+}
+
+static void ox_set_string(ox_value *out, const char *s, size_t len)
+{
+    out->type = OX_STRING;
+    out->aux = (uint32_t)len;
+    out->u.s = s;
+}
+
+/* Strings */
+
+static ox_string_header *ox_string_header_of(const char *s)
+{
+    return (ox_string_header *)s - 1;
+}
+
+/* Allocate a string buffer with room for `capacity` bytes, of which `len` are in use */
+static char *ox_string_alloc(size_t len, size_t capacity)
+{
+    ox_string_header *header;
+
+    if (capacity > UINT32_MAX) {
+        ox_fatal("Error", "String size overflow");
+    }
+    /* A capacity of 0 would mark the buffer as a literal */
+    if (capacity == 0) {
+        capacity = 1;
+    }
+    header = malloc(sizeof *header + capacity);
+    if (header == NULL) {
+        ox_fatal("Error", "Out of memory");
+    }
+    header->refcount = 1; /* Held by its creator */
+    header->capacity = capacity;
+    header->used = len;
+    return (char *)(header + 1);
+}
+
+/* Copy bytes into a new string value */
+static void ox_new_string(ox_value *out, const char *s, size_t len)
+{
+    char *data = ox_string_alloc(len, len);
+
+    memcpy(data, s, len);
+    ox_set_string(out, data, len);
+}
+
+static void ox_format_int(ox_value *out, int64_t i)
+{
+    char buf[32];
+    int len = snprintf(buf, sizeof buf, "%" PRId64, i);
+
+    ox_new_string(out, buf, (size_t)len);
+}
+
+/* Significant digits used when converting floats to strings, PHP's default precision */
+#define OX_PRECISION 14
+
+/*
+ * Format a float the way PHP does with its default precision: up to 14
+ * significant digits, without trailing zeros, switching to exponential form
+ * like 1.0E+25 or 1.5E-7 for very large or small magnitudes.
+ */
+static void ox_format_float(ox_value *out, double f)
+{
+    char sci[64];
+    char digits[OX_PRECISION + 1];
+    char buf[64];
+    int ndigits = 0;
+    int decpt;
+    int len = 0;
+    const char *p;
+
+    if (isnan(f)) {
+        ox_new_string(out, "NAN", 3);
+        return;
+    }
+    if (isinf(f)) {
+        ox_new_string(out, f < 0 ? "-INF" : "INF", f < 0 ? 4 : 3);
+        return;
+    }
+
+    /* Round to the significant digits, e.g. "-1.2345000000000E+02" */
+    snprintf(sci, sizeof sci, "%.*E", OX_PRECISION - 1, f);
+    p = sci;
+    if (*p == '-') {
+        buf[len++] = '-';
+        p++;
+    }
+    for (; *p != 'E'; p++) {
+        if (*p != '.') {
+            digits[ndigits++] = *p;
+        }
+    }
+    decpt = atoi(p + 1) + 1;
+    while (ndigits > 1 && digits[ndigits - 1] == '0') {
+        ndigits--;
+    }
+    if (f == 0.0) {
+        decpt = 1;
+    }
+
+    if (decpt < -3 || decpt > OX_PRECISION) {
+        /* Exponential format, always with a fractional part */
+        buf[len++] = digits[0];
+        buf[len++] = '.';
+        if (ndigits == 1) {
+            buf[len++] = '0';
+        } else {
+            memcpy(buf + len, digits + 1, (size_t)ndigits - 1);
+            len += ndigits - 1;
+        }
+        len += snprintf(buf + len, sizeof buf - (size_t)len, "E%c%d", decpt - 1 < 0 ? '-' : '+', abs(decpt - 1));
+    } else if (decpt <= 0) {
+        /* 0.000ddd */
+        buf[len++] = '0';
+        buf[len++] = '.';
+        for (; decpt < 0; decpt++) {
+            buf[len++] = '0';
+        }
+        memcpy(buf + len, digits, (size_t)ndigits);
+        len += ndigits;
+    } else {
+        /* ddd.ddd, padding the integer part with zeros */
+        for (int i = 0; i < decpt; i++) {
+            buf[len++] = i < ndigits ? digits[i] : '0';
+        }
+        if (ndigits > decpt) {
+            buf[len++] = '.';
+            memcpy(buf + len, digits + decpt, (size_t)(ndigits - decpt));
+            len += ndigits - decpt;
+        }
+    }
+
+    ox_new_string(out, buf, (size_t)len);
+}
+
+/* Convert a value to a string value that the caller holds, as PHP's (string) cast does; out may alias v */
+static void ox_to_string(ox_value *out, const ox_value *v)
+{
+    switch (v->type) {
+    case OX_NULL:
+        ox_set_string(out, ox_empty_string.data, 0);
+        break;
+    case OX_BOOL:
+        if (v->u.i) {
+            ox_set_string(out, ox_one_string.data, 1);
+        } else {
+            ox_set_string(out, ox_empty_string.data, 0);
+        }
+        break;
+    case OX_INT:
+        ox_format_int(out, v->u.i);
+        break;
+    case OX_FLOAT:
+        ox_format_float(out, v->u.f);
+        break;
+    case OX_STRING:
+        *out = *v;
+        ox_add_ref(out);
+        break;
+    case OX_ARRAY:
+        ox_warning("Array to string conversion");
+        ox_set_string(out, ox_array_string.data, 5);
+        break;
+    case OX_CLOSURE:
+    case OX_OBJECT:
+        ox_fatal("Error", "Object of class %s could not be converted to string", ox_type_name(v));
+        break;
+    default:
+        ox_set_string(out, ox_empty_string.data, 0);
+        break;
+    }
+}
+
+static int ox_strings_equal(const ox_value *a, const ox_value *b)
+{
+    return a->aux == b->aux && memcmp(a->u.s, b->u.s, a->aux) == 0;
+}
+
+static int ox_compare_strings(const ox_value *a, const ox_value *b)
+{
+    size_t len = a->aux < b->aux ? a->aux : b->aux;
+    int r = memcmp(a->u.s, b->u.s, len);
+
+    if (r == 0) {
+        return (a->aux > b->aux) - (a->aux < b->aux);
+    }
+    return (r > 0) - (r < 0);
+}
+
+/* Numeric conversion */
+
+enum { OX_NOT_NUMERIC, OX_LEADING_NUMERIC, OX_NUMERIC };
+
+static int ox_is_space(char c)
+{
+    return c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\v' || c == '\f';
+}
+
+/*
+ * Parse a PHP numeric string. Leading and trailing whitespace is allowed;
+ * anything else after the number makes it only leading-numeric.
+ */
+static int ox_parse_numeric(const ox_value *str, ox_value *out)
+{
+    const char *p = str->u.s;
+    const char *end = p + str->aux;
+    const char *start;
+    char buf[64];
+    char *number;
+    size_t number_len;
+    int is_float = 0;
+    int digits = 0;
+
+    while (p < end && ox_is_space(*p)) {
+        p++;
+    }
+    start = p;
+    if (p < end && (*p == '+' || *p == '-')) {
+        p++;
+    }
+    while (p < end && *p >= '0' && *p <= '9') {
+        p++;
+        digits++;
+    }
+    if (p < end && *p == '.') {
+        p++;
+        is_float = 1;
+        while (p < end && *p >= '0' && *p <= '9') {
+            p++;
+            digits++;
+        }
+    }
+    if (digits == 0) {
+        return OX_NOT_NUMERIC;
+    }
+    if (p < end && (*p == 'e' || *p == 'E')) {
+        const char *q = p + 1;
+        if (q < end && (*q == '+' || *q == '-')) {
+            q++;
+        }
+        if (q < end && *q >= '0' && *q <= '9') {
+            while (q < end && *q >= '0' && *q <= '9') {
+                q++;
+            }
+            p = q;
+            is_float = 1;
+        }
+    }
+
+    /* strtoll and strtod need a NUL-terminated copy of the number */
+    number_len = (size_t)(p - start);
+    number = number_len < sizeof buf ? buf : malloc(number_len + 1);
+    memcpy(number, start, number_len);
+    number[number_len] = '\0';
+
+    if (!is_float) {
+        long long i;
+
+        errno = 0;
+        i = strtoll(number, NULL, 10);
+        if (errno == ERANGE) {
+            /* Out of range integers become floats */
+            is_float = 1;
+        } else {
+            ox_set_int(out, (int64_t)i);
+        }
+    }
+    if (is_float) {
+        ox_set_float(out, strtod(number, NULL));
+    }
+    if (number != buf) {
+        free(number);
+    }
+
+    while (p < end && ox_is_space(*p)) {
+        p++;
+    }
+    return p == end ? OX_NUMERIC : OX_LEADING_NUMERIC;
+}
+
+/* Convert an arithmetic operand to an int or float value */
+static void ox_to_number(ox_value *out, const ox_value *v, const ox_value *a, const ox_value *b, const char *op)
+{
+    switch (v->type) {
+    case OX_NULL:
+        ox_set_int(out, 0);
+        return;
+    case OX_BOOL:
+        ox_set_int(out, v->u.i);
+        return;
+    case OX_INT:
+    case OX_FLOAT:
+        *out = *v;
+        return;
+    case OX_STRING:
+        switch (ox_parse_numeric(v, out)) {
+        case OX_NUMERIC:
+            return;
+        case OX_LEADING_NUMERIC:
+            ox_warning("A non-numeric value encountered");
+            return;
+        default:
+            break;
+        }
+        break;
+    default:
+        break;
+    }
+    ox_fatal("TypeError", "Unsupported operand types: %s %s %s", ox_type_name(a), op, ox_type_name(b));
+}
+
+static double ox_as_float(const ox_value *v)
+{
+    return v->type == OX_FLOAT ? v->u.f : (double)v->u.i;
+}
+
+/* Convert a value to an integer, as PHP's (int) cast does */
+static int64_t ox_to_int(const ox_value *v)
+{
+    ox_value n;
+
+    switch (v->type) {
+    case OX_BOOL:
+    case OX_INT:
+        return v->u.i;
+    case OX_FLOAT:
+        if (!(v->u.f >= -9223372036854775808.0 && v->u.f < 9223372036854775808.0)) {
+            return 0;
+        }
+        return (int64_t)v->u.f;
+    case OX_STRING:
+        if (ox_parse_numeric(v, &n) == OX_NOT_NUMERIC) {
+            return 0;
+        }
+        return ox_to_int(&n);
+    case OX_ARRAY:
+        return ((ox_array *)v->u.p)->count != 0;
+    case OX_CLOSURE:
+    case OX_OBJECT:
+        return 1;
+    default:
+        return 0;
+    }
+}
+
+/* Reference counting */
+
+/* Count one more holder of a value */
+static void ox_add_ref(const ox_value *v)
+{
+    switch (v->type) {
+    case OX_STRING:
+        ox_string_header_of(v->u.s)->refcount++;
+        break;
+    case OX_ARRAY:
+    case OX_CLOSURE:
+    case OX_OBJECT:
+        /* The count is the first field of each */
+        (*(int64_t *)v->u.p)++;
+        break;
+    default:
+        break;
+    }
+}
+
+static void ox_free_array(ox_array *arr)
+{
+    for (uint32_t i = 0; i < arr->count; i++) {
+        ox_release(&arr->buckets[i].key);
+        ox_release(&arr->buckets[i].value);
+    }
+    free(arr->buckets);
+    free(arr->slots);
+    free(arr);
+}
+
+static void ox_free_closure(ox_closure *closure)
+{
+    for (uint32_t i = 0; i < closure->capture_count; i++) {
+        ox_release(&closure->captures[i]);
+    }
+    free(closure);
+}
+
+static void ox_free_object(ox_object *object)
+{
+    for (int64_t i = 0; i < object->class->property_count; i++) {
+        ox_release(&object->properties[i]);
+    }
+    if (object->dynamic != NULL && --object->dynamic->refcount == 0) {
+        ox_free_array(object->dynamic);
+    }
+    free(object);
+}
+
+/*
+ * Let go of a value, freeing it when nothing else holds it. Values that
+ * refer to each other, such as an object stored in its own property, keep
+ * each other alive and are never freed.
+ */
+void ox_release(ox_value *v)
+{
+    ox_string_header *header;
+
+    switch (v->type) {
+    case OX_STRING:
+        header = ox_string_header_of(v->u.s);
+        if (header->capacity != 0 && --header->refcount == 0) {
+            free(header);
+        }
+        break;
+    case OX_ARRAY:
+        if (--((ox_array *)v->u.p)->refcount == 0) {
+            ox_free_array(v->u.p);
+        }
+        break;
+    case OX_CLOSURE:
+        if (--((ox_closure *)v->u.p)->refcount == 0) {
+            ox_free_closure(v->u.p);
+        }
+        break;
+    case OX_OBJECT:
+        if (--((ox_object *)v->u.p)->refcount == 0) {
+            ox_free_object(v->u.p);
+        }
+        break;
+    default:
+        break;
+    }
+}
+
+/* Let go of consecutive values, as the generated code does with those it pops */
+void ox_release_values(ox_value *values, int64_t count)
+{
+    for (int64_t i = 0; i < count; i++) {
+        ox_release(&values[i]);
+    }
+}
+
+/* Arrays */
+
+static uint64_t ox_hash_key(const ox_value *key)
+{
+    uint64_t h;
+
+    if (key->type == OX_INT) {
+        h = (uint64_t)key->u.i * 0x9e3779b97f4a7c15ULL;
+        return h ^ (h >> 32);
+    }
+    /* FNV-1a */
+    h = 0xcbf29ce484222325ULL;
+    for (uint32_t i = 0; i < key->aux; i++) {
+        h = (h ^ (unsigned char)key->u.s[i]) * 0x100000001b3ULL;
+    }
+    return h;
+}
+
+static int ox_keys_equal(const ox_value *a, const ox_value *b)
+{
+    if (a->type != b->type) {
+        return 0;
+    }
+    if (a->type == OX_INT) {
+        return a->u.i == b->u.i;
+    }
+    return ox_strings_equal(a, b);
+}
+
+/* Whether a string is the canonical decimal form of an int, e.g. "42" but not "042" or "+42" */
+static int ox_is_integer_key(const ox_value *str, int64_t *out)
+{
+    const char *s = str->u.s;
+    uint32_t len = str->aux;
+    uint32_t i = 0;
+    char buf[24];
+    long long n;
+
+    if (i < len && s[i] == '-') {
+        i++;
+    }
+    if (i == len || len >= sizeof buf || (s[i] == '0' && len != 1)) {
+        return 0;
+    }
+    for (; i < len; i++) {
+        if (s[i] < '0' || s[i] > '9') {
+            return 0;
+        }
+    }
+    memcpy(buf, s, len);
+    buf[len] = '\0';
+    errno = 0;
+    n = strtoll(buf, NULL, 10);
+    if (errno == ERANGE) {
+        return 0;
+    }
+    *out = (int64_t)n;
+    return 1;
+}
+
+/* Convert a value to an array key, which is always an int or a string */
+static void ox_to_key(ox_value *out, const ox_value *v, const char *container)
+{
+    int64_t i;
+
+    switch (v->type) {
+    case OX_NULL:
+        ox_set_string(out, ox_empty_string.data, 0);
+        return;
+    case OX_BOOL:
+    case OX_INT:
+        ox_set_int(out, v->u.i);
+        return;
+    case OX_FLOAT:
+        ox_set_int(out, ox_to_int(v));
+        return;
+    case OX_STRING:
+        if (ox_is_integer_key(v, &i)) {
+            ox_set_int(out, i);
+        } else {
+            *out = *v;
+        }
+        return;
+    default:
+        ox_fatal("TypeError", "Cannot access offset of type %s on %s", ox_type_name(v), container);
+    }
+}
+
+static ox_array *ox_array_alloc(uint32_t capacity)
+{
+    ox_array *arr = malloc(sizeof *arr);
+
+    arr->refcount = 1; /* Held by its creator */
+    arr->count = 0;
+    arr->capacity = capacity;
+    arr->next_index = 0;
+    arr->has_next_index = 0;
+    arr->buckets = malloc(capacity * sizeof *arr->buckets);
+    arr->slots = calloc(capacity * 2, sizeof *arr->slots);
+    return arr;
+}
+
+/* Slot holding key, or the empty slot where it would be inserted */
+static uint32_t *ox_array_slot(const ox_array *arr, const ox_value *key)
+{
+    uint32_t mask = arr->capacity * 2 - 1;
+    uint32_t i = (uint32_t)ox_hash_key(key) & mask;
+
+    while (arr->slots[i] != 0 && !ox_keys_equal(&arr->buckets[arr->slots[i] - 1].key, key)) {
+        i = (i + 1) & mask;
+    }
+    return &arr->slots[i];
+}
+
+static ox_value *ox_array_find(const ox_array *arr, const ox_value *key)
+{
+    uint32_t slot = *ox_array_slot(arr, key);
+
+    return slot == 0 ? NULL : &arr->buckets[slot - 1].value;
+}
+
+static void ox_array_grow(ox_array *arr)
+{
+    uint32_t i;
+
+    arr->capacity *= 2;
+    arr->buckets = realloc(arr->buckets, arr->capacity * sizeof *arr->buckets);
+    free(arr->slots);
+    arr->slots = calloc(arr->capacity * 2, sizeof *arr->slots);
+    for (i = 0; i < arr->count; i++) {
+        *ox_array_slot(arr, &arr->buckets[i].key) = i + 1;
+    }
+}
+
+/* Store a value into an element or property slot, which holds it from then on */
+static void ox_assign(ox_value *slot, const ox_value *value)
+{
+    ox_value old = *slot;
+
+    ox_add_ref(value);
+    *slot = *value;
+    ox_release(&old);
+}
+
+/* Find the element with the given (normalized) key, adding it as null when it is missing */
+static ox_value *ox_array_lookup_or_add(ox_array *arr, const ox_value *key)
+{
+    uint32_t *slot = ox_array_slot(arr, key);
+    ox_bucket *bucket;
+
+    if (*slot != 0) {
+        return &arr->buckets[*slot - 1].value;
+    }
+    if (arr->count == arr->capacity) {
+        ox_array_grow(arr);
+        slot = ox_array_slot(arr, key);
+    }
+    bucket = &arr->buckets[arr->count];
+    bucket->key = *key;
+    ox_add_ref(key);
+    ox_set_null(&bucket->value);
+    arr->count++;
+    *slot = arr->count;
+
+    if (key->type == OX_INT && (!arr->has_next_index || key->u.i >= arr->next_index)) {
+        arr->next_index = key->u.i == INT64_MAX ? INT64_MAX : key->u.i + 1;
+        arr->has_next_index = 1;
+    }
+    return &bucket->value;
+}
+
+/* Add a null element after the largest int key; returns NULL when that key is taken */
+static ox_value *ox_array_append(ox_array *arr)
+{
+    ox_value key;
+
+    ox_set_int(&key, arr->next_index);
+    if (arr->next_index == INT64_MAX && ox_array_find(arr, &key) != NULL) {
+        ox_warning("Cannot add element to the array as the next element is already occupied");
+        return NULL;
+    }
+    return ox_array_lookup_or_add(arr, &key);
+}
+
+/* Insert or overwrite the element with the given (normalized) key */
+static void ox_array_store(ox_array *arr, const ox_value *key, const ox_value *value)
+{
+    ox_assign(ox_array_lookup_or_add(arr, key), value);
+}
+
+/* Make the array in a slot safe to modify, copying it if it is shared */
+static ox_array *ox_array_separate(ox_value *slot)
+{
+    ox_array *arr = slot->u.p;
+    ox_array *copy;
+    uint32_t i;
+
+    if (arr->refcount <= 1) {
+        return arr;
+    }
+    copy = ox_array_alloc(arr->capacity);
+    memcpy(copy->buckets, arr->buckets, arr->count * sizeof *arr->buckets);
+    memcpy(copy->slots, arr->slots, arr->capacity * 2 * sizeof *arr->slots);
+    copy->count = arr->count;
+    copy->next_index = arr->next_index;
+    copy->has_next_index = arr->has_next_index;
+    for (i = 0; i < copy->count; i++) {
+        /* The elements are now shared with the copy */
+        ox_add_ref(&copy->buckets[i].key);
+        ox_add_ref(&copy->buckets[i].value);
+    }
+    arr->refcount--;
+    slot->u.p = copy;
+    return copy;
+}
+
+void ox_array_new(ox_value *out)
+{
+    out->type = OX_ARRAY;
+    out->aux = 0;
+    out->u.p = ox_array_alloc(8);
+}
+
+/* Append to an array literal under construction */
+void ox_array_push(ox_value *array, const ox_value *value)
+{
+    ox_value *element = ox_array_append(array->u.p);
+
+    if (element != NULL) {
+        ox_assign(element, value);
+    }
+}
+
+/* Set an element of an array literal under construction */
+void ox_array_set(ox_value *array, const ox_value *key, const ox_value *value)
+{
+    ox_value k;
+
+    ox_to_key(&k, key, "array");
+    ox_array_store(array->u.p, &k, value);
+}
+
+/* Read container[key] */
+void ox_array_get(ox_value *out, const ox_value *container, const ox_value *key)
+{
+    ox_value k;
+    const ox_value *found;
+
+    switch (container->type) {
+    case OX_ARRAY:
+        ox_to_key(&k, key, "array");
+        found = ox_array_find(container->u.p, &k);
+        if (found != NULL) {
+            *out = *found;
+            ox_add_ref(out);
+            return;
+        }
+        if (k.type == OX_INT) {
+            ox_warning("Undefined array key %" PRId64, k.u.i);
+        } else {
+            ox_warning("Undefined array key \"%.*s\"", (int)k.aux, k.u.s);
+        }
+        break;
+    case OX_STRING: {
+        const char *s = container->u.s;
+        int64_t len = container->aux;
+        int64_t i;
+
+        if (key->type != OX_INT && key->type != OX_BOOL && key->type != OX_FLOAT
+            && !(key->type == OX_STRING && ox_is_integer_key(key, &i))) {
+            ox_fatal("TypeError", "Cannot access offset of type %s on string", ox_type_name(key));
+        }
+        i = ox_to_int(key);
+        if (i < 0) {
+            i += len;
+        }
+        if (i < 0 || i >= len) {
+            ox_warning("Uninitialized string offset %" PRId64, ox_to_int(key));
+            ox_set_string(out, ox_empty_string.data, 0);
+            return;
+        }
+        ox_new_string(out, s + i, 1);
+        return;
+    }
+    case OX_OBJECT:
+        ox_fatal("Error", "Cannot use object of type %s as array", ox_type_name(container));
+        break;
+    default:
+        ox_warning("Trying to access array offset on value of type %s", ox_type_name(container));
+        break;
+    }
+    ox_set_null(out);
+}
+
+/*
+ * Read container[key] for ??, like isset() does: a missing element, a string
+ * offset that is out of range or not an integer, and an offset of any other
+ * type of value read as null without a warning.
+ */
+void ox_array_get_quiet(ox_value *out, const ox_value *container, const ox_value *key)
+{
+    ox_value k;
+    const ox_value *found;
+    int64_t i;
+
+    switch (container->type) {
+    case OX_ARRAY:
+        ox_to_key(&k, key, "array");
+        found = ox_array_find(container->u.p, &k);
+        if (found != NULL) {
+            *out = *found;
+            ox_add_ref(out);
+            return;
+        }
+        break;
+    case OX_STRING:
+        if (key->type == OX_INT || (key->type == OX_STRING && ox_is_integer_key(key, &i))) {
+            i = ox_to_int(key);
+            if (i < 0) {
+                i += container->aux;
+            }
+            if (i >= 0 && i < container->aux) {
+                ox_new_string(out, container->u.s + i, 1);
+                return;
+            }
+        }
+        break;
+    case OX_OBJECT:
+        ox_fatal("Error", "Cannot use object of type %s as array", ox_type_name(container));
+        break;
+    default:
+        break;
+    }
+    ox_set_null(out);
+}
+
+/*
+ * Element assignment. The keys of $var[k1]...[kn] are on the operand stack
+ * below a working slot: stack[0] is the value (or the result of a load),
+ * and the key of the last dimension that has one is stack[1]. Bit i of
+ * `keyed` is set when dimension i, counting from the variable, has a key;
+ * the others are appends ([]).
+ */
+
+static const ox_value *ox_dimension_key(const ox_value *stack, int64_t dims, int64_t keyed, int64_t dim)
+{
+    int64_t after = 0;
+
+    /* Keys of later dimensions were pushed after this one */
+    for (int64_t i = dim + 1; i < dims; i++) {
+        after += (keyed >> i) & 1;
+    }
+    return &stack[1 + after];
+}
+
+/* Write a single byte of a string in place of the string in a slot */
+static void ox_store_string_offset(ox_value *slot, const ox_value *key, const ox_value *value)
+{
+    ox_value s;
+    int64_t len = slot->aux;
+    int64_t offset;
+    int64_t i;
+    char *data;
+
+    if (key->type != OX_INT && key->type != OX_BOOL && key->type != OX_FLOAT
+        && !(key->type == OX_STRING && ox_is_integer_key(key, &offset))) {
+        ox_fatal("TypeError", "Cannot access offset of type %s on string", ox_type_name(key));
+    }
+    offset = ox_to_int(key);
+    i = offset < 0 ? offset + len : offset;
+    if (i < 0) {
+        ox_warning("Illegal string offset %" PRId64, offset);
+        return;
+    }
+    ox_to_string(&s, value);
+    if (s.aux == 0) {
+        ox_fatal("Error", "Cannot assign an empty string to a string offset");
+    }
+    if (s.aux > 1) {
+        ox_warning("Only the first byte will be assigned to the string offset");
+    }
+
+    /* Strings share buffers, so the result is a new string, padded with spaces */
+    data = ox_string_alloc((size_t)(i < len ? len : i + 1), (size_t)(i < len ? len : i + 1));
+    memcpy(data, slot->u.s, (size_t)len);
+    if (i > len) {
+        memset(data + len, ' ', (size_t)(i - len));
+    }
+    data[i] = s.u.s[0];
+    ox_release(&s);
+    ox_release(slot);
+    ox_set_string(slot, data, (size_t)(i < len ? len : i + 1));
+}
+
+/* $var[k1]...[kn] = value */
+void ox_store_element(ox_value *var, ox_value *stack, int64_t dims, int64_t keyed)
+{
+    ox_value *slot = var;
+
+    for (int64_t dim = 0; dim < dims; dim++) {
+        int has_key = (keyed >> dim) & 1;
+        const ox_value *key = has_key ? ox_dimension_key(stack, dims, keyed, dim) : NULL;
+        ox_array *arr;
+        ox_value k;
+
+        switch (slot->type) {
+        case OX_NULL:
+            /* Writing to an element of null creates the array */
+            ox_array_new(slot);
+            break;
+        case OX_BOOL:
+            if (slot->u.i) {
+                ox_fatal("Error", "Cannot use a scalar value as an array");
+            }
+            ox_deprecated("Automatic conversion of false to array is deprecated");
+            ox_array_new(slot);
+            break;
+        case OX_STRING:
+            if (!has_key) {
+                ox_fatal("Error", "[] operator not supported for strings");
+            }
+            if (dim != dims - 1) {
+                ox_fatal("Error", "Cannot use string offset as an array");
+            }
+            ox_store_string_offset(slot, key, &stack[0]);
+            return;
+        case OX_ARRAY:
+            break;
+        case OX_OBJECT:
+            ox_fatal("Error", "Cannot use object of type %s as array", ox_type_name(slot));
+            break;
+        default:
+            ox_fatal("Error", "Cannot use a scalar value as an array");
+        }
+
+        arr = ox_array_separate(slot);
+        if (has_key) {
+            ox_to_key(&k, key, "array");
+            slot = ox_array_lookup_or_add(arr, &k);
+        } else {
+            slot = ox_array_append(arr);
+            if (slot == NULL) {
+                return;
+            }
+        }
+    }
+    ox_assign(slot, &stack[0]);
+}
+
+/* Read $var[k1]...[kn] into stack[0] for a compound assignment, replacing what it held; [] reads null */
+void ox_load_element(ox_value *var, ox_value *stack, int64_t dims, int64_t keyed)
+{
+    ox_value current = *var;
+    ox_value element;
+
+    ox_add_ref(&current);
+    for (int64_t dim = 0; dim < dims; dim++) {
+        /* Missing containers are created by the store that follows */
+        if (!((keyed >> dim) & 1) || current.type == OX_NULL) {
+            ox_release(&current);
+            ox_set_null(&current);
+            break;
+        }
+        ox_array_get(&element, &current, ox_dimension_key(stack, dims, keyed, dim));
+        ox_release(&current);
+        current = element;
+    }
+    /* var may be stack[0] itself, which current holds on to */
+    ox_release(&stack[0]);
+    stack[0] = current;
+}
+
+/* Iteration */
+
+/*
+ * Turn the value of a foreach into an iterator in place. An iterator is the
+ * array itself, with the position of the next element in the aux field.
+ * An object iterates over its properties, as they were when the loop started.
+ */
+void ox_iter_init(ox_value *v)
+{
+    if (v->type == OX_OBJECT) {
+        ox_value held = *v;
+        const ox_object *object = held.u.p;
+        ox_value key;
+
+        ox_array_new(v);
+        for (int64_t i = 0; i < object->class->property_count; i++) {
+            const char *name = ox_member_names[object->class->properties[i]];
+
+            if (object->properties[i].type == OX_UNINITIALIZED) {
+                continue;
+            }
+            ox_set_string(&key, name, strlen(name));
+            ox_array_store(v->u.p, &key, &object->properties[i]);
+        }
+        if (object->dynamic != NULL) {
+            for (uint32_t i = 0; i < object->dynamic->count; i++) {
+                ox_array_store(v->u.p, &object->dynamic->buckets[i].key, &object->dynamic->buckets[i].value);
+            }
+        }
+        ox_release(&held);
+    } else if (v->type != OX_ARRAY) {
+        ox_warning("foreach() argument must be of type array|object, %s given", ox_type_name(v));
+        ox_release(v);
+        ox_array_new(v);
+    }
+    /* Holding the array, the loop iterates over it as it was, even if the variable is modified */
+    v->aux = 0;
+}
+
+/* Fetch the next element of an iterator; returns 0 and lets go of the iterator when there are none left */
+int64_t ox_iter_next(ox_value *iter, ox_value *value, ox_value *key)
+{
+    ox_array *arr = iter->u.p;
+
+    if (iter->aux >= arr->count) {
+        ox_release(iter);
+        return 0;
+    }
+    *key = arr->buckets[iter->aux].key;
+    *value = arr->buckets[iter->aux].value;
+    ox_add_ref(key);
+    ox_add_ref(value);
+    iter->aux++;
+    return 1;
+}
+
+/* Truthiness */
+
+int64_t ox_truthy(const ox_value *v)
+{
+    switch (v->type) {
+    case OX_BOOL:
+    case OX_INT:
+        return v->u.i != 0;
+    case OX_FLOAT:
+        return v->u.f != 0.0;
+    case OX_STRING:
+        return !(v->aux == 0 || (v->aux == 1 && v->u.s[0] == '0'));
+    case OX_ARRAY:
+        return ((ox_array *)v->u.p)->count != 0;
+    case OX_CLOSURE:
+    case OX_OBJECT:
+        return 1;
+    default:
+        return 0;
+    }
+}
+
+/* Arithmetic */
+
+/* The union of two arrays: the elements of a, then those of b whose keys a does not have */
+static void ox_array_union(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    const ox_array *x = a->u.p;
+    const ox_array *y = b->u.p;
+    ox_array *result = ox_array_alloc(x->capacity);
+    uint32_t i;
+
+    for (i = 0; i < x->count; i++) {
+        ox_array_store(result, &x->buckets[i].key, &x->buckets[i].value);
+    }
+    for (i = 0; i < y->count; i++) {
+        if (ox_array_find(result, &y->buckets[i].key) == NULL) {
+            ox_array_store(result, &y->buckets[i].key, &y->buckets[i].value);
+        }
+    }
+    out->type = OX_ARRAY;
+    out->aux = 0;
+    out->u.p = result;
+}
+
+void ox_add(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    ox_value x, y;
+    int64_t r;
+
+    if (a->type == OX_ARRAY && b->type == OX_ARRAY) {
+        ox_array_union(out, a, b);
+        return;
+    }
+    ox_to_number(&x, a, a, b, "+");
+    ox_to_number(&y, b, a, b, "+");
+    if (x.type == OX_INT && y.type == OX_INT && !__builtin_add_overflow(x.u.i, y.u.i, &r)) {
+        ox_set_int(out, r);
+    } else {
+        ox_set_float(out, ox_as_float(&x) + ox_as_float(&y));
+    }
+}
+
+void ox_subtract(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    ox_value x, y;
+    int64_t r;
+
+    ox_to_number(&x, a, a, b, "-");
+    ox_to_number(&y, b, a, b, "-");
+    if (x.type == OX_INT && y.type == OX_INT && !__builtin_sub_overflow(x.u.i, y.u.i, &r)) {
+        ox_set_int(out, r);
+    } else {
+        ox_set_float(out, ox_as_float(&x) - ox_as_float(&y));
+    }
+}
+
+void ox_multiply(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    ox_value x, y;
+    int64_t r;
+
+    ox_to_number(&x, a, a, b, "*");
+    ox_to_number(&y, b, a, b, "*");
+    if (x.type == OX_INT && y.type == OX_INT && !__builtin_mul_overflow(x.u.i, y.u.i, &r)) {
+        ox_set_int(out, r);
+    } else {
+        ox_set_float(out, ox_as_float(&x) * ox_as_float(&y));
+    }
+}
+
+void ox_divide(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    ox_value x, y;
+
+    ox_to_number(&x, a, a, b, "/");
+    ox_to_number(&y, b, a, b, "/");
+    if (ox_as_float(&y) == 0.0) {
+        ox_fatal("DivisionByZeroError", "Division by zero");
+    }
+    /* Integer division only stays an integer when it is exact */
+    if (x.type == OX_INT && y.type == OX_INT && !(x.u.i == INT64_MIN && y.u.i == -1) && x.u.i % y.u.i == 0) {
+        ox_set_int(out, x.u.i / y.u.i);
+    } else {
+        ox_set_float(out, ox_as_float(&x) / ox_as_float(&y));
+    }
+}
+
+/* Convert an operand of %, a bitwise operator or a shift to an int, as PHP does for floats that are not whole */
+static int64_t ox_to_checked_int(const ox_value *v, const ox_value *a, const ox_value *b, const char *op)
+{
+    ox_value n, text;
+
+    ox_to_number(&n, v, a, b, op);
+    if (n.type == OX_FLOAT && !(n.u.f >= -9223372036854775808.0 && n.u.f < 9223372036854775808.0 && (double)(int64_t)n.u.f == n.u.f)) {
+        if (v->type == OX_STRING) {
+            ox_deprecated("Implicit conversion from float-string \"%.*s\" to int loses precision", (int)v->aux, v->u.s);
+        } else {
+            ox_format_float(&text, n.u.f);
+            ox_deprecated("Implicit conversion from float %.*s to int loses precision", (int)text.aux, text.u.s);
+            ox_release(&text);
+        }
+    }
+    return ox_to_int(&n);
+}
+
+void ox_modulo(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    /* Both operands are converted to integers first */
+    int64_t x = ox_to_checked_int(a, a, b, "%");
+    int64_t divisor = ox_to_checked_int(b, a, b, "%");
+
+    if (divisor == 0) {
+        ox_fatal("DivisionByZeroError", "Modulo by zero");
+    }
+    ox_set_int(out, divisor == -1 ? 0 : x % divisor);
+}
+
+/* An int raised to a non-negative int power stays an int unless it overflows */
+void ox_power(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    ox_value x, y;
+
+    ox_to_number(&x, a, a, b, "**");
+    ox_to_number(&y, b, a, b, "**");
+    if (x.type == OX_INT && y.type == OX_INT && y.u.i >= 0) {
+        int64_t result = 1;
+        int64_t base = x.u.i;
+        int64_t exponent = y.u.i;
+        int overflow = 0;
+
+        /* Exponentiation by squaring */
+        while (exponent > 0 && !overflow) {
+            if (exponent & 1) {
+                overflow = __builtin_mul_overflow(result, base, &result);
+            }
+            exponent >>= 1;
+            if (exponent > 0 && !overflow) {
+                overflow = __builtin_mul_overflow(base, base, &base);
+            }
+        }
+        if (!overflow) {
+            ox_set_int(out, result);
+            return;
+        }
+    }
+    ox_set_float(out, pow(ox_as_float(&x), ox_as_float(&y)));
+}
+
+void ox_negate(ox_value *out, const ox_value *a)
+{
+    ox_value zero, x;
+
+    ox_set_int(&zero, 0);
+    ox_to_number(&x, a, a, &zero, "*");
+    if (x.type == OX_INT && x.u.i != INT64_MIN) {
+        ox_set_int(out, -x.u.i);
+    } else {
+        ox_set_float(out, -ox_as_float(&x));
+    }
+}
+
+/* Bitwise operations */
+
+/*
+ * Apply &, | or ^ to two strings byte by byte. The result is as long as the
+ * shorter string, except for | which keeps the rest of the longer one.
+ */
+static void ox_bitwise_strings(ox_value *out, const ox_value *a, const ox_value *b, char op)
+{
+    const ox_value *longer = a->aux >= b->aux ? a : b;
+    size_t common = a->aux < b->aux ? a->aux : b->aux;
+    size_t len = op == '|' ? longer->aux : common;
+    char *data = ox_string_alloc(len, len);
+    size_t i;
+
+    for (i = 0; i < common; i++) {
+        char x = a->u.s[i];
+        char y = b->u.s[i];
+
+        data[i] = op == '&' ? x & y : op == '|' ? x | y : x ^ y;
+    }
+    memcpy(data + common, longer->u.s + common, len - common);
+    ox_set_string(out, data, len);
+}
+
+void ox_bitwise_and(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    if (a->type == OX_STRING && b->type == OX_STRING) {
+        ox_bitwise_strings(out, a, b, '&');
+        return;
+    }
+    ox_set_int(out, ox_to_checked_int(a, a, b, "&") & ox_to_checked_int(b, a, b, "&"));
+}
+
+void ox_bitwise_or(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    if (a->type == OX_STRING && b->type == OX_STRING) {
+        ox_bitwise_strings(out, a, b, '|');
+        return;
+    }
+    ox_set_int(out, ox_to_checked_int(a, a, b, "|") | ox_to_checked_int(b, a, b, "|"));
+}
+
+void ox_bitwise_xor(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    if (a->type == OX_STRING && b->type == OX_STRING) {
+        ox_bitwise_strings(out, a, b, '^');
+        return;
+    }
+    ox_set_int(out, ox_to_checked_int(a, a, b, "^") ^ ox_to_checked_int(b, a, b, "^"));
+}
+
+void ox_bitwise_not(ox_value *out, const ox_value *a)
+{
+    ox_value zero;
+    char *data;
+    size_t i;
+
+    switch (a->type) {
+    case OX_INT:
+    case OX_FLOAT:
+        ox_set_int(&zero, 0);
+        ox_set_int(out, ~ox_to_checked_int(a, a, &zero, "~"));
+        break;
+    case OX_STRING:
+        data = ox_string_alloc(a->aux, a->aux);
+        for (i = 0; i < a->aux; i++) {
+            data[i] = (char)~a->u.s[i];
+        }
+        ox_set_string(out, data, a->aux);
+        break;
+    default:
+        ox_fatal("TypeError", "Cannot perform bitwise not on %s", ox_type_name(a));
+        break;
+    }
+}
+
+/* Shifts by 64 bits or more shift every bit out, negative ones throw */
+static int64_t ox_shift_amount(const ox_value *a, const ox_value *b, const char *op)
+{
+    int64_t amount = ox_to_checked_int(b, a, b, op);
+
+    if (amount < 0) {
+        ox_fatal("ArithmeticError", "Bit shift by negative number");
+    }
+    return amount;
+}
+
+void ox_shift_left(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    int64_t x = ox_to_checked_int(a, a, b, "<<");
+    int64_t amount = ox_shift_amount(a, b, "<<");
+
+    ox_set_int(out, amount >= 64 ? 0 : (int64_t)((uint64_t)x << amount));
+}
+
+void ox_shift_right(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    int64_t x = ox_to_checked_int(a, a, b, ">>");
+    int64_t amount = ox_shift_amount(a, b, ">>");
+
+    ox_set_int(out, x >> (amount >= 64 ? 63 : amount));
+}
+
+/* Increment and decrement */
+
+/*
+ * Increment a string that is not numeric like a counter: "a" becomes "b",
+ * "Az" becomes "Ba", "a9" becomes "b0" and "zz" becomes "aaa". Only letters
+ * and digits are incremented; the carry stops at any other character.
+ */
+static void ox_increment_string(ox_value *v)
+{
+    size_t len = v->aux;
+    char carry = 0; /* Character prepended when the carry goes past the first one */
+    char *data;
+    size_t i;
+
+    if (len == 0) {
+        ox_release(v);
+        ox_set_string(v, ox_one_string.data, 1);
+        return;
+    }
+    data = ox_string_alloc(len, len + 1);
+    memcpy(data, v->u.s, len);
+    for (i = len; i-- > 0;) {
+        char c = data[i];
+
+        if (c >= 'a' && c <= 'z') {
+            data[i] = c == 'z' ? 'a' : c + 1;
+            carry = c == 'z' ? 'a' : 0;
+        } else if (c >= 'A' && c <= 'Z') {
+            data[i] = c == 'Z' ? 'A' : c + 1;
+            carry = c == 'Z' ? 'A' : 0;
+        } else if (c >= '0' && c <= '9') {
+            data[i] = c == '9' ? '0' : c + 1;
+            carry = c == '9' ? '1' : 0;
+        } else {
+            carry = 0;
+        }
+        if (carry == 0) {
+            break;
+        }
+    }
+    if (carry != 0) {
+        memmove(data + 1, data, len);
+        data[0] = carry;
+        len++;
+        ox_string_header_of(data)->used = len;
+    }
+    ox_release(v);
+    ox_set_string(v, data, len);
+}
+
+/* Add 1 or -1 to a number; integers that overflow become floats */
+static void ox_step_number(ox_value *v, int64_t delta)
+{
+    int64_t r;
+
+    if (v->type == OX_INT && !__builtin_add_overflow(v->u.i, delta, &r)) {
+        ox_set_int(v, r);
+    } else {
+        ox_set_float(v, ox_as_float(v) + (double)delta);
+    }
+}
+
+/* ++ in place: null becomes 1, bools are unchanged and non-numeric strings count up */
+void ox_increment(ox_value *v)
+{
+    ox_value n;
+
+    switch (v->type) {
+    case OX_NULL:
+        ox_set_int(v, 1);
+        break;
+    case OX_BOOL:
+        break;
+    case OX_STRING:
+        if (ox_parse_numeric(v, &n) == OX_NUMERIC) {
+            ox_step_number(&n, 1);
+            ox_release(v);
+            *v = n;
+        } else {
+            ox_increment_string(v);
+        }
+        break;
+    case OX_ARRAY:
+    case OX_CLOSURE:
+    case OX_OBJECT:
+        ox_fatal("TypeError", "Cannot increment %s", ox_type_name(v));
+        break;
+    default:
+        ox_step_number(v, 1);
+        break;
+    }
+}
+
+/* -- in place: null, bools and non-numeric strings are unchanged, except "" which becomes -1 */
+void ox_decrement(ox_value *v)
+{
+    ox_value n;
+
+    switch (v->type) {
+    case OX_NULL:
+    case OX_BOOL:
+        break;
+    case OX_STRING:
+        if (v->aux == 0) {
+            ox_release(v);
+            ox_set_int(v, -1);
+        } else if (ox_parse_numeric(v, &n) == OX_NUMERIC) {
+            ox_step_number(&n, -1);
+            ox_release(v);
+            *v = n;
+        }
+        break;
+    case OX_ARRAY:
+    case OX_CLOSURE:
+    case OX_OBJECT:
+        ox_fatal("TypeError", "Cannot decrement %s", ox_type_name(v));
+        break;
+    default:
+        ox_step_number(v, -1);
+        break;
+    }
+}
+
+/* Comparison */
+
+static int ox_sign(double d)
+{
+    if (d != d) {
+        return OX_UNORDERED;
+    }
+    return (d > 0) - (d < 0);
+}
+
+static int ox_compare_numbers(const ox_value *x, const ox_value *y)
+{
+    if (x->type == OX_INT && y->type == OX_INT) {
+        return (x->u.i > y->u.i) - (x->u.i < y->u.i);
+    }
+    return ox_sign(ox_as_float(x) - ox_as_float(y));
+}
+
+/*
+ * Arrays with fewer elements are smaller. Otherwise they are compared
+ * element by element, and are unordered when a key is missing in b.
+ */
+static int ox_compare_arrays(const ox_array *a, const ox_array *b)
+{
+    uint32_t i;
+
+    if (a->count != b->count) {
+        return a->count < b->count ? -1 : 1;
+    }
+    for (i = 0; i < a->count; i++) {
+        const ox_value *other = ox_array_find(b, &a->buckets[i].key);
+        int r;
+
+        if (other == NULL) {
+            return OX_UNORDERED;
+        }
+        r = (int)ox_compare(&a->buckets[i].value, other);
+        if (r != 0) {
+            return r;
+        }
+    }
+    return 0;
+}
+
+/*
+ * Objects of the same class are compared property by property, in
+ * declaration order; objects of different classes are unordered.
+ */
+static int ox_compare_objects(const ox_object *a, const ox_object *b)
+{
+    if (a->class != b->class) {
+        return OX_UNORDERED;
+    }
+    if (a == b) {
+        return 0;
+    }
+    for (int64_t i = 0; i < a->class->property_count; i++) {
+        const ox_value *x = &a->properties[i], *y = &b->properties[i];
+        int r;
+
+        /* An uninitialized property only equals another uninitialized one */
+        if (x->type == OX_UNINITIALIZED || y->type == OX_UNINITIALIZED) {
+            r = x->type == y->type ? 0 : OX_UNORDERED;
+        } else {
+            r = (int)ox_compare(x, y);
+        }
+        if (r != 0) {
+            return r;
+        }
+    }
+
+    /* Dynamic properties are compared like the elements of arrays */
+    if (a->dynamic != NULL || b->dynamic != NULL) {
+        static const ox_array none;
+
+        return ox_compare_arrays(a->dynamic != NULL ? a->dynamic : &none, b->dynamic != NULL ? b->dynamic : &none);
+    }
+    return 0;
+}
+
+/*
+ * PHP 8 loose comparison. Returns -1, 0 or 1, or OX_UNORDERED when the
+ * operands cannot be ordered (NAN).
+ */
+int64_t ox_compare(const ox_value *a, const ox_value *b)
+{
+    ox_value x, y;
+    int r;
+
+    /* null <=> string compares the empty string */
+    if (a->type == OX_NULL && b->type == OX_STRING) {
+        return b->aux == 0 ? 0 : -1;
+    }
+    if (a->type == OX_STRING && b->type == OX_NULL) {
+        return a->aux == 0 ? 0 : 1;
+    }
+    /* bool or null against anything compares truthiness */
+    if (a->type == OX_BOOL || a->type == OX_NULL || b->type == OX_BOOL || b->type == OX_NULL) {
+        int64_t l = ox_truthy(a);
+        int64_t r = ox_truthy(b);
+        return (l > r) - (l < r);
+    }
+    /*
+     * a closure only equals itself, objects of one class compare their
+     * properties, and both are greater than anything else
+     */
+    if (a->type == OX_CLOSURE || a->type == OX_OBJECT || b->type == OX_CLOSURE || b->type == OX_OBJECT) {
+        if (a->type != OX_CLOSURE && a->type != OX_OBJECT) {
+            return -1;
+        }
+        if (b->type != OX_CLOSURE && b->type != OX_OBJECT) {
+            return 1;
+        }
+        if (a->type != b->type) {
+            return OX_UNORDERED;
+        }
+        if (a->type == OX_OBJECT) {
+            return ox_compare_objects(a->u.p, b->u.p);
+        }
+        return a->u.p == b->u.p ? 0 : OX_UNORDERED;
+    }
+    /* arrays are greater than any scalar */
+    if (a->type == OX_ARRAY || b->type == OX_ARRAY) {
+        if (a->type != OX_ARRAY) {
+            return -1;
+        }
+        if (b->type != OX_ARRAY) {
+            return 1;
+        }
+        return ox_compare_arrays(a->u.p, b->u.p);
+    }
+    if (a->type == OX_STRING && b->type == OX_STRING) {
+        if (ox_parse_numeric(a, &x) == OX_NUMERIC && ox_parse_numeric(b, &y) == OX_NUMERIC) {
+            return ox_compare_numbers(&x, &y);
+        }
+        return ox_compare_strings(a, b);
+    }
+    /* number <=> string is numeric only when the string is numeric */
+    if (a->type == OX_STRING) {
+        if (ox_parse_numeric(a, &x) == OX_NUMERIC) {
+            return ox_compare_numbers(&x, b);
+        }
+        ox_to_string(&y, b);
+        r = ox_compare_strings(a, &y);
+        ox_release(&y);
+        return r;
+    }
+    if (b->type == OX_STRING) {
+        if (ox_parse_numeric(b, &y) == OX_NUMERIC) {
+            return ox_compare_numbers(a, &y);
+        }
+        ox_to_string(&x, a);
+        r = ox_compare_strings(&x, b);
+        ox_release(&x);
+        return r;
+    }
+    return ox_compare_numbers(a, b);
+}
+
+/*
+ * PHP strict comparison: the same type and the same value. Arrays are
+ * identical when they have identical elements under the same keys in the
+ * same order. Returns 0 or 1.
+ */
+int64_t ox_identical(const ox_value *a, const ox_value *b)
+{
+    const ox_array *x, *y;
+    uint32_t i;
+
+    if (a->type != b->type) {
+        return 0;
+    }
+    switch (a->type) {
+    case OX_NULL:
+        return 1;
+    case OX_FLOAT:
+        return a->u.f == b->u.f;
+    case OX_STRING:
+        return ox_strings_equal(a, b);
+    case OX_ARRAY:
+        x = a->u.p;
+        y = b->u.p;
+        if (x == y) {
+            return 1;
+        }
+        if (x->count != y->count) {
+            return 0;
+        }
+        for (i = 0; i < x->count; i++) {
+            if (!ox_keys_equal(&x->buckets[i].key, &y->buckets[i].key)
+                || !ox_identical(&x->buckets[i].value, &y->buckets[i].value)) {
+                return 0;
+            }
+        }
+        return 1;
+    default:
+        return a->u.i == b->u.i;
+    }
+}
+
+/* Longest part of a string shown in an UnhandledMatchError */
+#define OX_MATCH_CASE_MAX_LEN 15
+
+/*
+ * Throw an UnhandledMatchError for a match subject, showing scalars as PHP
+ * code, with strings escaped and cut after 15 bytes, and anything else by
+ * its type.
+ */
+void ox_unhandled_match(const ox_value *v)
+{
+    ox_value text;
+    char buf[OX_MATCH_CASE_MAX_LEN * 4 + 8];
+    size_t len = 0;
+    uint32_t i;
+
+    switch (v->type) {
+    case OX_NULL:
+        ox_fatal("UnhandledMatchError", "Unhandled match case NULL");
+        break;
+    case OX_BOOL:
+        ox_fatal("UnhandledMatchError", "Unhandled match case %s", v->u.i ? "true" : "false");
+        break;
+    case OX_INT:
+        ox_fatal("UnhandledMatchError", "Unhandled match case %" PRId64, v->u.i);
+        break;
+    case OX_FLOAT:
+        ox_format_float(&text, v->u.f);
+        ox_fatal("UnhandledMatchError", "Unhandled match case %.*s", (int)text.aux, text.u.s);
+        break;
+    case OX_STRING:
+        buf[len++] = '\'';
+        for (i = 0; i < v->aux && i < OX_MATCH_CASE_MAX_LEN; i++) {
+            unsigned char c = (unsigned char)v->u.s[i];
+
+            switch (c) {
+            case '\n': len += (size_t)sprintf(buf + len, "\\n"); break;
+            case '\r': len += (size_t)sprintf(buf + len, "\\r"); break;
+            case '\t': len += (size_t)sprintf(buf + len, "\\t"); break;
+            case '\v': len += (size_t)sprintf(buf + len, "\\v"); break;
+            case '\f': len += (size_t)sprintf(buf + len, "\\f"); break;
+            case 27: len += (size_t)sprintf(buf + len, "\\e"); break;
+            case '\\': len += (size_t)sprintf(buf + len, "\\\\"); break;
+            default:
+                if (c >= 32 && c <= 126) {
+                    buf[len++] = (char)c;
+                } else {
+                    len += (size_t)sprintf(buf + len, "\\x%02X", c);
+                }
+            }
+        }
+        if (v->aux > OX_MATCH_CASE_MAX_LEN) {
+            len += (size_t)sprintf(buf + len, "...");
+        }
+        buf[len++] = '\'';
+        ox_fatal("UnhandledMatchError", "Unhandled match case %.*s", (int)len, buf);
+        break;
+    default:
+        ox_fatal("UnhandledMatchError", "Unhandled match case of type %s", ox_type_name(v));
+    }
+}
+
+/* <=>: -1, 0 or 1, with operands that cannot be ordered counting as greater */
+void ox_spaceship(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    int64_t r = ox_compare(a, b);
+
+    ox_set_int(out, r == OX_UNORDERED ? 1 : r);
+}
+
+/* Concatenation */
+
+/*
+ * Appending to the string that last extended its buffer reuses the buffer
+ * when it has room, and otherwise moves to a buffer twice the size, so a
+ * loop of `.=` takes amortized linear time. The buffer a string outgrows is
+ * freed once nothing holds the strings in it.
+ */
+void ox_concat(ox_value *out, const ox_value *a, const ox_value *b)
+{
+    ox_value l, r;
+    ox_string_header *header;
+    size_t len;
+    char *data;
+
+    ox_to_string(&l, a);
+    ox_to_string(&r, b);
+    len = (size_t)l.aux + r.aux;
+    if (len > UINT32_MAX) {
+        ox_fatal("Error", "String size overflow");
+    }
+
+    header = ox_string_header_of(l.u.s);
+    if (header->capacity != 0 && header->capacity >= len && header->used == l.aux) {
+        /* l ends its buffer, append in place; the result takes over l's reference */
+        data = (char *)l.u.s;
+        memmove(data + l.aux, r.u.s, r.aux);
+        header->used = len;
+    } else {
+        size_t capacity = len < 16 ? 16 : len * 2;
+
+        data = ox_string_alloc(len, capacity > UINT32_MAX ? UINT32_MAX : capacity);
+        memcpy(data, l.u.s, l.aux);
+        memcpy(data + l.aux, r.u.s, r.aux);
+        ox_release(&l);
+    }
+    ox_release(&r);
+    ox_set_string(out, data, len);
+}
+
+/* Built-in functions, which take their arguments from the operand stack with the first one on top */
+
+static const ox_value ox_null_value = { OX_NULL, 0, { 0 } };
+
+/* The argument at `index`, or null when fewer were passed */
+static const ox_value *ox_builtin_arg(const ox_value *args, int64_t arg_count, int64_t index)
+{
+    return index < arg_count ? &args[index] : &ox_null_value;
+}
+
+void ox_strlen(ox_value *out, const ox_value *args, int64_t arg_count)
+{
+    ox_value s;
+
+    ox_to_string(&s, ox_builtin_arg(args, arg_count, 0));
+    ox_set_int(out, s.aux);
+    ox_release(&s);
+}
+
+void ox_substr(ox_value *out, const ox_value *args, int64_t arg_count)
+{
+    const ox_value *length_arg = ox_builtin_arg(args, arg_count, 2);
+    ox_value s;
+    int64_t len, offset, length;
+
+    ox_to_string(&s, ox_builtin_arg(args, arg_count, 0));
+    len = s.aux;
+    offset = ox_to_int(ox_builtin_arg(args, arg_count, 1));
+    if (offset > len) {
+        ox_release(&s);
+        ox_set_string(out, ox_empty_string.data, 0);
+        return;
+    }
+    if (offset < 0) {
+        offset = len + offset < 0 ? 0 : len + offset;
+    }
+    if (length_arg->type == OX_NULL) {
+        length = len - offset;
+    } else {
+        length = ox_to_int(length_arg);
+        if (length < 0) {
+            length = len - offset + length < 0 ? 0 : len - offset + length;
+        } else if (length > len - offset) {
+            length = len - offset;
+        }
+    }
+    ox_new_string(out, s.u.s + offset, (size_t)length);
+    ox_release(&s);
+}
+
+/* Closures */
+
+/* Create a closure, copying its captured values from the operand stack, where the first one is on top */
+void *ox_make_closure(void *code, int64_t param_count, int64_t capture_count, const ox_value *captures)
+{
+    ox_closure *closure = malloc(sizeof(ox_closure) + (size_t)capture_count * sizeof(ox_value));
+    int64_t i;
+
+    if (!closure) {
+        ox_fatal("Error", "Out of memory");
+    }
+    closure->refcount = 1;
+    closure->code = code;
+    closure->param_count = (uint32_t)param_count;
+    closure->capture_count = (uint32_t)capture_count;
+    for (i = 0; i < capture_count; i++) {
+        closure->captures[i] = captures[i];
+        ox_add_ref(&captures[i]);
+    }
+    return closure;
+}
+
+/* The closure that a call through a value goes to, checking that it can be called with the arguments */
+void *ox_callable(const ox_value *callee, int64_t arg_count)
+{
+    ox_closure *closure;
+
+    if (callee->type != OX_CLOSURE) {
+        ox_fatal("Error", "Value not callable");
+    }
+    closure = callee->u.p;
+    if (arg_count < closure->param_count) {
+        ox_fatal("ArgumentCountError", "Too few arguments to function {closure}(), %" PRId64
+                 " passed and exactly %" PRIu32 " expected", arg_count, closure->param_count);
+    }
+    return closure;
+}
+
+/* Objects */
+
+/* Replace a value the caller holds with a converted one, letting go of the original */
+static void ox_replace(ox_value *value, const ox_value *converted)
+{
+    ox_value original = *value;
+
+    *value = *converted;
+    ox_release(&original);
+}
+
+/*
+ * Convert a value stored into a property to the property's declared type, as
+ * PHP does outside strict_types mode: scalars convert to each other, anything
+ * else must match. The caller holds the value.
+ */
+static void ox_property_value(ox_value *value, const ox_class *class, int64_t index)
+{
+    const ox_property_type *declared = &class->property_types[index];
+    ox_value n, text;
+
+    if (declared->name == NULL || (declared->nullable && value->type == OX_NULL)) {
+        return;
+    }
+    if (value->type == (uint32_t)declared->type) {
+        /* The class name follows the ? of a nullable type */
+        if (value->type != OX_OBJECT
+            || strcmp(((const ox_object *)value->u.p)->class->name, declared->name + declared->nullable) == 0) {
+            return;
+        }
+    } else {
+        switch (declared->type) {
+        case OX_INT:
+            if (value->type == OX_BOOL) {
+                ox_set_int(value, value->u.i);
+                return;
+            }
+            if (value->type != OX_FLOAT && value->type != OX_STRING) {
+                break;
+            }
+            n = *value;
+            if (value->type == OX_STRING && ox_parse_numeric(value, &n) != OX_NUMERIC) {
+                break;
+            }
+            if (n.type == OX_INT) {
+                ox_replace(value, &n);
+                return;
+            }
+            /* Floats out of the range of int cannot be stored, fractions are cut off */
+            if (!(n.u.f >= -9223372036854775808.0 && n.u.f < 9223372036854775808.0)) {
+                break;
+            }
+            if ((double)(int64_t)n.u.f != n.u.f) {
+                if (value->type == OX_STRING) {
+                    ox_deprecated("Implicit conversion from float-string \"%.*s\" to int loses precision", (int)value->aux, value->u.s);
+                } else {
+                    ox_format_float(&text, n.u.f);
+                    ox_deprecated("Implicit conversion from float %.*s to int loses precision", (int)text.aux, text.u.s);
+                    ox_release(&text);
+                }
+            }
+            ox_set_int(&n, (int64_t)n.u.f);
+            ox_replace(value, &n);
+            return;
+        case OX_FLOAT:
+            if (value->type == OX_INT || value->type == OX_BOOL) {
+                ox_set_float(value, (double)value->u.i);
+                return;
+            }
+            if (value->type == OX_STRING && ox_parse_numeric(value, &n) == OX_NUMERIC) {
+                ox_set_float(&n, ox_as_float(&n));
+                ox_replace(value, &n);
+                return;
+            }
+            break;
+        case OX_STRING:
+            if (value->type == OX_INT || value->type == OX_FLOAT || value->type == OX_BOOL) {
+                ox_to_string(value, value);
+                return;
+            }
+            break;
+        case OX_BOOL:
+            if (value->type == OX_INT || value->type == OX_FLOAT || value->type == OX_STRING) {
+                n.type = OX_BOOL;
+                n.aux = 0;
+                n.u.i = ox_truthy(value);
+                ox_replace(value, &n);
+                return;
+            }
+            break;
+        default:
+            break;
+        }
+    }
+    ox_fatal("TypeError", "Cannot assign %s to property %s::$%s of type %s", ox_type_name(value), class->name,
+             ox_member_names[class->properties[index]], declared->name);
+}
+
+/* Create an object, copying the default values of its properties from the operand stack, where the first one is on top */
+void *ox_new_object(const ox_class *class, const ox_value *defaults)
+{
+    ox_object *object = malloc(sizeof(ox_object) + (size_t)class->property_count * sizeof(ox_value));
+    ox_value value;
+
+    if (!object) {
+        ox_fatal("Error", "Out of memory");
+    }
+    object->refcount = 1;
+    object->class = class;
+    object->dynamic = NULL;
+    for (int64_t i = 0; i < class->property_count; i++) {
+        ox_set_null(&object->properties[i]);
+        if (class->property_types[i].uninitialized) {
+            object->properties[i].type = OX_UNINITIALIZED;
+            continue;
+        }
+        /* Defaults are checked when compiling, but ints still widen to floats */
+        value = defaults[i];
+        ox_add_ref(&value);
+        if (value.type != OX_NULL) {
+            ox_property_value(&value, class, i);
+        }
+        object->properties[i] = value;
+    }
+    return object;
+}
+
+/* A property name as an array key of the dynamic properties */
+static void ox_member_key(ox_value *out, int64_t member)
+{
+    ox_set_string(out, ox_member_names[member], strlen(ox_member_names[member]));
+}
+
+/* The slot of a property of an object, NULL when it has no such property */
+static ox_value *ox_find_property(ox_object *object, int64_t member)
+{
+    ox_value key;
+
+    for (int64_t i = 0; i < object->class->property_count; i++) {
+        if (object->class->properties[i] == member) {
+            return &object->properties[i];
+        }
+    }
+    if (object->dynamic == NULL) {
+        return NULL;
+    }
+    ox_member_key(&key, member);
+    return ox_array_find(object->dynamic, &key);
+}
+
+/* Read $object->member, warning when there is no such property or no object */
+void ox_load_property(ox_value *out, const ox_value *object, int64_t member)
+{
+    const ox_value *found;
+
+    if (object->type != OX_OBJECT) {
+        ox_warning("Attempt to read property \"%s\" on %s", ox_member_names[member], ox_type_name(object));
+        ox_set_null(out);
+        return;
+    }
+    found = ox_find_property(object->u.p, member);
+    if (found == NULL) {
+        ox_warning("Undefined property: %s::$%s", ox_type_name(object), ox_member_names[member]);
+        ox_set_null(out);
+        return;
+    }
+    if (found->type == OX_UNINITIALIZED) {
+        ox_fatal("Error", "Typed property %s::$%s must not be accessed before initialization", ox_type_name(object),
+                 ox_member_names[member]);
+    }
+    *out = *found;
+    ox_add_ref(out);
+}
+
+/*
+ * Read $object->member for ??, like isset() does: a missing or uninitialized
+ * property or no object reads as null without a warning
+ */
+void ox_load_property_quiet(ox_value *out, const ox_value *object, int64_t member)
+{
+    const ox_value *found = object->type == OX_OBJECT ? ox_find_property(object->u.p, member) : NULL;
+
+    if (found == NULL || found->type == OX_UNINITIALIZED) {
+        ox_set_null(out);
+        return;
+    }
+    *out = *found;
+    ox_add_ref(out);
+}
+
+/*
+ * The slot of a property that is assigned to, creating it as null when the
+ * class does not declare it. Sets *index to the position of the property in
+ * the class, or -1 for a dynamic property, which has no type.
+ */
+static ox_value *ox_assigned_property(const ox_value *object, int64_t member, int64_t *index)
+{
+    ox_object *obj;
+    ox_value key;
+
+    if (object->type != OX_OBJECT) {
+        ox_fatal("Error", "Attempt to assign property \"%s\" on %s", ox_member_names[member], ox_type_name(object));
+    }
+    obj = object->u.p;
+    for (int64_t i = 0; i < obj->class->property_count; i++) {
+        if (obj->class->properties[i] == member) {
+            *index = i;
+            return &obj->properties[i];
+        }
+    }
+
+    *index = -1;
+    ox_member_key(&key, member);
+    if (obj->dynamic == NULL) {
+        obj->dynamic = ox_array_alloc(8);
+    }
+    if (ox_array_find(obj->dynamic, &key) == NULL) {
+        ox_deprecated("Creation of dynamic property %s::$%s is deprecated", obj->class->name, ox_member_names[member]);
+    }
+    return ox_array_lookup_or_add(obj->dynamic, &key);
+}
+
+/* Store into $object->member, converting the value to the property's type in place */
+void ox_store_property(const ox_value *object, int64_t member, ox_value *value)
+{
+    int64_t index;
+    ox_value *slot = ox_assigned_property(object, member, &index);
+
+    if (index >= 0) {
+        ox_property_value(value, ((const ox_object *)object->u.p)->class, index);
+    }
+    ox_assign(slot, value);
+}
+
+/*
+ * The slot that an assignment to an element of $object->member stores into.
+ * Only properties that can hold arrays are auto-initialized from null, false
+ * or uninitialized.
+ */
+ox_value *ox_property_slot(const ox_value *object, int64_t member)
+{
+    int64_t index;
+    ox_value *slot = ox_assigned_property(object, member, &index);
+    const ox_class *class = ((const ox_object *)object->u.p)->class;
+    const ox_property_type *declared = index >= 0 ? &class->property_types[index] : NULL;
+
+    if (declared != NULL && declared->name != NULL && declared->type != OX_ARRAY
+        && (slot->type == OX_NULL || slot->type == OX_UNINITIALIZED || (slot->type == OX_BOOL && !slot->u.i))) {
+        ox_fatal("Error", "Cannot auto-initialize an array inside property %s::$%s of type %s", class->name,
+                 ox_member_names[member], declared->name);
+    }
+    if (slot->type == OX_UNINITIALIZED) {
+        ox_set_null(slot);
+    }
+    return slot;
+}
+
+/* The code of the method that $object->member() calls, checking that it can be called with the arguments */
+void *ox_method_code(const ox_value *object, int64_t member, int64_t arg_count)
+{
+    const ox_method *method;
+
+    if (object->type != OX_OBJECT) {
+        ox_fatal("Error", "Call to a member function %s() on %s", ox_member_names[member], ox_type_name(object));
+    }
+    method = ((const ox_object *)object->u.p)->class->methods[member];
+    if (method == NULL) {
+        ox_fatal("Error", "Call to undefined method %s::%s()", ox_type_name(object), ox_member_names[member]);
+    }
+    if (arg_count < method->param_count) {
+        ox_fatal("ArgumentCountError", "Too few arguments to function %s::%s(), %" PRId64
+                 " passed and exactly %" PRId64 " expected", ox_type_name(object), ox_member_names[member],
+                 arg_count, method->param_count);
+    }
+    return method->code;
+}
+
+/* Output */
+
+void ox_echo(const ox_value *v)
+{
+    ox_value s;
+
+    switch (v->type) {
+    case OX_INT:
+        printf("%" PRId64, v->u.i);
+        break;
+    case OX_STRING:
+        fwrite(v->u.s, 1, v->aux, stdout);
+        break;
+    default:
+        ox_to_string(&s, v);
+        fwrite(s.u.s, 1, s.aux, stdout);
+        ox_release(&s);
+        break;
+    }
+}
+
+void ox_echo_line(const ox_value *v)
+{
+    ox_echo(v);
+    putchar('\n');
+}
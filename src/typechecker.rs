@@ -1,12 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::ast::{BinaryOp, Location, Node, Type, UnaryOp};
-use crate::error::{CompilerError, type_error, Result};
+use crate::error::{CompilerError, CompilerWarning, type_error, Result};
+
+/// What the type checker knows about a class
+struct ClassInfo {
+    constants: HashMap<String, Type>,           // Type of each constant, Mixed unless it is a literal
+    properties: HashMap<String, Option<Type>>,  // Declared type of each property
+    methods: HashMap<String, (Vec<Type>, bool)>, // (param_types, is_static)
+}
 
 /// Type checker for PHP code
 pub struct TypeChecker {
     variables: HashMap<String, Type>,
+    flow_dependent: HashSet<String>, // Variables of the current scope assigned in a branch or loop
     functions: HashMap<String, (Vec<Type>, Type)>, // (param_types, return_type)
+    classes: HashMap<String, ClassInfo>,
+    warnings: Vec<CompilerWarning>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeChecker {
@@ -15,18 +31,37 @@ impl TypeChecker {
 
         // Add built-in functions
         functions.insert("strlen".to_string(), (vec![Type::String], Type::Integer));
-        functions.insert("substr".to_string(), (vec![Type::String, Type::Integer, Type::Integer], Type::String));
+        // Only the required parameters are listed; substr's length is optional
+        functions.insert("substr".to_string(), (vec![Type::String, Type::Integer], Type::String));
 
         Self {
             variables: HashMap::new(),
+            flow_dependent: HashSet::new(),
             functions,
+            classes: HashMap::new(),
+            warnings: Vec::new(),
         }
     }
 
+    /// Warnings found by the checks so far
+    pub fn warnings(&self) -> &[CompilerWarning] {
+        &self.warnings
+    }
+
     /// Check a program
     pub fn check_program(&mut self, node: &Node) -> Result<Type> {
         match node {
             Node::Program(statements) => {
+                // PHP hoists top-level functions and classes, so they can be used before their declaration
+                for stmt in statements {
+                    match stmt {
+                        Node::FunctionDecl { name, params, .. } => self.declare_function(name, params),
+                        Node::ClassDecl { .. } => self.declare_class(stmt)?,
+                        _ => {}
+                    }
+                }
+
+                self.flow_dependent = scope_assignments(node);
                 for stmt in statements {
                     self.check_node(stmt)?;
                 }
@@ -60,7 +95,7 @@ impl TypeChecker {
             }
             Node::IfStmt { condition, then_branch, else_branch, .. } => {
                 // Check condition
-                let cond_type = self.check_node(condition)?;
+                self.check_node(condition)?;
 
                 // PHP is loosely typed, so we don't need to check if condition is boolean
 
@@ -75,7 +110,7 @@ impl TypeChecker {
             }
             Node::WhileStmt { condition, body, .. } => {
                 // Check condition
-                let cond_type = self.check_node(condition)?;
+                self.check_node(condition)?;
 
                 // PHP is loosely typed, so we don't need to check if condition is boolean
 
@@ -84,6 +119,15 @@ impl TypeChecker {
 
                 Ok(Type::Null)
             }
+            Node::DoWhileStmt { body, condition, .. } => {
+                // Check body
+                self.check_node(body)?;
+
+                // Check condition
+                self.check_node(condition)?;
+
+                Ok(Type::Null)
+            }
             Node::ForStmt { init, condition, increment, body, .. } => {
                 // Check initializer
                 if let Some(init) = init {
@@ -92,7 +136,7 @@ impl TypeChecker {
 
                 // Check condition
                 if let Some(condition) = condition {
-                    let cond_type = self.check_node(condition)?;
+                    self.check_node(condition)?;
                     // PHP is loosely typed, so we don't need to check if condition is boolean
                 }
 
@@ -108,7 +152,7 @@ impl TypeChecker {
             }
             Node::ForeachStmt { array, value_var, key_var, body, .. } => {
                 // Check array
-                let array_type = self.check_node(array)?;
+                self.check_node(array)?;
 
                 // PHP is loosely typed, so we don't need to check if array is actually an array
 
@@ -125,6 +169,23 @@ impl TypeChecker {
 
                 Ok(Type::Null)
             }
+            Node::SwitchStmt { subject, cases, .. } => {
+                // Check subject
+                self.check_node(subject)?;
+
+                // Check each case value and its statements
+                for (value, statements) in cases {
+                    if let Some(value) = value {
+                        self.check_node(value)?;
+                    }
+                    for stmt in statements {
+                        self.check_node(stmt)?;
+                    }
+                }
+
+                Ok(Type::Null)
+            }
+            Node::BreakStmt(..) | Node::ContinueStmt(..) => Ok(Type::Null),
             Node::ReturnStmt(value, _) => {
                 if let Some(value) = value {
                     self.check_node(value)
@@ -147,38 +208,209 @@ impl TypeChecker {
                     Type::Null
                 };
 
-                self.variables.insert(name.clone(), var_type.clone());
+                self.assign_variable(name, var_type.clone());
 
                 Ok(var_type)
             }
             Node::FunctionDecl { name, params, body, .. } => {
+                // Add function to scope before checking the body so it can recurse
+                self.declare_function(name, params);
+
                 // Create a new scope
                 let old_variables = self.variables.clone();
+                let old_flow_dependent = std::mem::replace(&mut self.flow_dependent, scope_assignments(body));
 
                 // Add parameters to scope
-                let mut param_types = Vec::new();
                 for (param_name, param_type) in params {
                     let type_ = param_type.clone().unwrap_or(Type::Mixed);
-                    self.variables.insert(param_name.clone(), type_.clone());
-                    param_types.push(type_);
+                    self.assign_variable(param_name, type_);
                 }
 
                 // Check body
                 self.check_node(body)?;
 
-                // Add function to scope
-                self.functions.insert(name.clone(), (param_types, Type::Mixed));
-
                 // Restore the old scope
                 self.variables = old_variables;
+                self.flow_dependent = old_flow_dependent;
+
+                Ok(Type::Null)
+            }
+            Node::ClassDecl { name, constants, properties, methods, location } => {
+                // Classes are declared by check_program, which only sees the top level
+                if !self.classes.contains_key(name) {
+                    return Err(type_error(
+                        location,
+                        format!("Class {} must be declared at the top level", name),
+                    ));
+                }
+
+                // Constants and default values are evaluated where they are used, so they must not depend on variables
+                for (_, value) in constants {
+                    self.check_constant_expression(value)?;
+                }
+                for property in properties {
+                    if let Some(default) = &property.default {
+                        let default_type = self.check_constant_expression(default)?;
+                        if let Some(type_) = &property.type_ {
+                            if !property_accepts(type_, &default_type, false) {
+                                return Err(type_error(
+                                    &property.location,
+                                    format!(
+                                        "Cannot use {:?} as default value for property {}::${} of type {:?}",
+                                        default_type, name, property.name, type_
+                                    ),
+                                ));
+                            }
+                        }
+                    }
+                }
+
+                for method in methods {
+                    // Create a new scope, where instance methods have $this
+                    let old_variables = std::mem::take(&mut self.variables);
+                    let old_flow_dependent = std::mem::replace(&mut self.flow_dependent, scope_assignments(&method.body));
+                    if !method.is_static {
+                        self.variables.insert("this".to_string(), Type::Object(name.clone()));
+                    }
+
+                    // Add parameters to scope
+                    for (param_name, param_type) in &method.params {
+                        let type_ = param_type.clone().unwrap_or(Type::Mixed);
+                        self.assign_variable(param_name, type_);
+                    }
+
+                    // Check body
+                    let result = self.check_node(&method.body);
+
+                    // Restore the old scope
+                    self.variables = old_variables;
+                    self.flow_dependent = old_flow_dependent;
+                    result?;
+                }
 
                 Ok(Type::Null)
             }
+            Node::BinaryExpr { op: BinaryOp::Assign, left, right, .. } if !matches!(**left, Node::Variable(_, _)) => {
+                let right_type = self.check_node(right)?;
+                if let Node::PropertyAccess { object, property, location } = &**left {
+                    // Assignment to a property
+                    self.check_property_assignment(object, property, &right_type, right, location)?;
+                } else {
+                    // Assignment to an array element
+                    self.check_assignment_target(left)?;
+                }
+                Ok(right_type)
+            }
+            Node::Match { subject, arms, .. } => {
+                self.check_node(subject)?;
+
+                // Only a literal subject has a known value. The type of a variable is whatever
+                // was assigned last, which ignores control flow, so any other subject may match
+                // any arm. Once an arm always matches, the arms after it and default never run.
+                let literal_subject = literal_type(subject).is_some();
+                let always_matches = arms.iter().position(|(conditions, _)| {
+                    literal_subject && conditions.iter().any(|c| identical_literals(subject, c) == Some(true))
+                });
+                let mut earlier: Vec<&Node> = Vec::new(); // Conditions of the reachable arms so far
+
+                let mut result_type: Option<Type> = None;
+                for (i, (conditions, result)) in arms.iter().enumerate() {
+                    for condition in conditions {
+                        self.check_node(condition)?;
+                    }
+
+                    let unreachable = match always_matches {
+                        Some(_) if conditions.is_empty() => Some("another arm always matches the subject".to_string()),
+                        Some(first) if i > first => Some("an earlier arm always matches the subject".to_string()),
+                        _ if literal_subject
+                            && !conditions.is_empty()
+                            && conditions.iter().all(|c| identical_literals(subject, c) == Some(false)) =>
+                        {
+                            Some(format!(
+                                "{} is never identical to {}",
+                                literal_source(subject),
+                                conditions.iter().map(literal_source).collect::<Vec<_>>().join(" or ")
+                            ))
+                        }
+                        _ => None,
+                    };
+
+                    if let Some(reason) = unreachable {
+                        let location = conditions.first().map_or_else(|| self.get_location(result), |c| self.get_location(c));
+                        self.warnings.push(CompilerWarning {
+                            location,
+                            message: format!("Unreachable match arm: {}", reason),
+                        });
+                    } else {
+                        // A condition identical to an earlier one is never the one that matches
+                        for condition in conditions {
+                            if earlier.iter().any(|&e| identical_literals(e, condition) == Some(true)) {
+                                self.warnings.push(CompilerWarning {
+                                    location: self.get_location(condition),
+                                    message: format!(
+                                        "Unreachable match condition: {} is identical to an earlier condition",
+                                        literal_source(condition)
+                                    ),
+                                });
+                            }
+                            earlier.push(condition);
+                        }
+                    }
+
+                    // The match has the union of the types of its arms
+                    let arm_type = self.check_node(result)?;
+                    result_type = Some(match result_type {
+                        Some(type_) => union_type(type_, arm_type),
+                        None => arm_type,
+                    });
+                }
+
+                // Without arms, every subject is unhandled
+                Ok(result_type.unwrap_or(Type::Mixed))
+            }
+            Node::CompoundAssign { target, value, .. } => {
+                self.check_node(value)?;
+                self.check_assignment_target(target)?;
+                Ok(Type::Mixed)
+            }
+            Node::IncDec { target, .. } => {
+                // Incrementing can turn an int into a float, or null into an int
+                match &**target {
+                    Node::Variable(name, _) => {
+                        self.variables.insert(name.clone(), Type::Mixed);
+                    }
+                    _ => self.check_assignment_target(target)?,
+                }
+                Ok(Type::Mixed)
+            }
+            Node::Ternary { condition, then_branch, else_branch, .. } => {
+                let condition_type = self.check_node(condition)?;
+                let then_type = match then_branch {
+                    Some(then_branch) => self.check_node(then_branch)?,
+                    None => condition_type,
+                };
+                let else_type = self.check_node(else_branch)?;
+                Ok(union_type(then_type, else_type))
+            }
             Node::BinaryExpr { op, left, right, .. } => {
-                let left_type = self.check_node(left)?;
+                let left_type = if *op == BinaryOp::Coalesce {
+                    self.check_isset_operand(left)?
+                } else {
+                    self.check_node(left)?
+                };
                 let right_type = self.check_node(right)?;
 
                 match op {
+                    BinaryOp::Add if left_type == Type::Array && right_type == Type::Array => {
+                        // Adding two arrays gives their union
+                        Ok(Type::Array)
+                    }
+                    BinaryOp::Add
+                        if (left_type == Type::Array && right_type == Type::Mixed)
+                            || (left_type == Type::Mixed && right_type == Type::Array) =>
+                    {
+                        Ok(Type::Mixed)
+                    }
                     BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => {
                         // PHP is loosely typed, so we'll just return a numeric type
                         if left_type == Type::Float || right_type == Type::Float {
@@ -187,17 +419,58 @@ impl TypeChecker {
                             Ok(Type::Integer)
                         }
                     }
-                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                    BinaryOp::Power => {
+                        // Negative exponents and overflowing results make floats out of integers
+                        if left_type == Type::Float || right_type == Type::Float {
+                            Ok(Type::Float)
+                        } else {
+                            Ok(Type::Mixed)
+                        }
+                    }
+                    BinaryOp::Spaceship => {
+                        // Three-way comparison returns -1, 0 or 1
+                        Ok(Type::Integer)
+                    }
+                    BinaryOp::BitwiseAnd | BinaryOp::BitwiseOr | BinaryOp::BitwiseXor => {
+                        // Two strings are combined byte by byte, anything else as integers
+                        if left_type == Type::String && right_type == Type::String {
+                            Ok(Type::String)
+                        } else {
+                            Ok(Type::Integer)
+                        }
+                    }
+                    BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+                        // Shifts always work on integers
+                        Ok(Type::Integer)
+                    }
+                    BinaryOp::Equal
+                    | BinaryOp::NotEqual
+                    | BinaryOp::Identical
+                    | BinaryOp::NotIdentical
+                    | BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
                         // Comparison operators return boolean
                         Ok(Type::Boolean)
                     }
-                    BinaryOp::LogicalAnd | BinaryOp::LogicalOr => {
+                    BinaryOp::LogicalAnd | BinaryOp::LogicalOr | BinaryOp::LogicalXor => {
                         // Logical operators return boolean
                         Ok(Type::Boolean)
                     }
+                    BinaryOp::Coalesce => {
+                        // The left operand is only the result when it is not null
+                        if left_type == Type::Null {
+                            Ok(right_type)
+                        } else {
+                            Ok(union_type(left_type, right_type))
+                        }
+                    }
                     BinaryOp::Assign => {
+                        let name = self.get_variable_name(left)?;
+                        if name == "this" {
+                            return Err(type_error(&self.get_location(left), "Cannot re-assign $this"));
+                        }
+
                         // Assignment returns the assigned value
-                        self.variables.insert(self.get_variable_name(left)?, right_type.clone());
+                        self.assign_variable(&name, right_type.clone());
                         Ok(right_type)
                     }
                     BinaryOp::Concat => {
@@ -205,9 +478,13 @@ impl TypeChecker {
                         Ok(Type::String)
                     }
                     BinaryOp::ArrayAccess => {
-                        // Array access returns the element type, which is Mixed in PHP
-                        if left_type == Type::Array {
+                        // Array access returns the element type, which is Mixed in PHP;
+                        // the runtime checks values whose type is only known then
+                        if left_type == Type::Array || left_type == Type::Mixed {
                             Ok(Type::Mixed)
+                        } else if left_type == Type::String {
+                            // String offsets read a single character
+                            Ok(Type::String)
                         } else {
                             // Get the location from the left node
                             let location = match left.as_ref() {
@@ -238,12 +515,31 @@ impl TypeChecker {
                         // Logical not returns boolean
                         Ok(Type::Boolean)
                     }
+                    UnaryOp::BitwiseNot => {
+                        // Bitwise not inverts the bytes of a string, or the bits of an integer
+                        if expr_type == Type::String {
+                            Ok(Type::String)
+                        } else {
+                            Ok(Type::Integer)
+                        }
+                    }
                 }
             }
+            Node::ArrayAppend(_, location) => Err(type_error(
+                location,
+                "Cannot use [] for reading",
+            )),
+            Node::Variable(name, location) if name == "this" && !self.variables.contains_key(name) => Err(type_error(
+                location,
+                "Using $this when not in object context",
+            )),
             Node::Variable(name, _) => {
                 // Look up variable in scope
                 if let Some(type_) = self.variables.get(name) {
                     Ok(type_.clone())
+                } else if self.flow_dependent.contains(name) {
+                    // Whether it has been assigned yet depends on control flow
+                    Ok(Type::Mixed)
                 } else {
                     // In PHP, using an undefined variable is allowed (it's treated as null)
                     self.variables.insert(name.clone(), Type::Null);
@@ -260,7 +556,18 @@ impl TypeChecker {
                 // Look up function in scope
                 if let Some((param_types, return_type)) = self.functions.get(name) {
                     // PHP is loosely typed, so we don't need to check if argument types match parameter types
-                    // But we could add some basic checks here
+                    // Missing arguments are an error, extra arguments are ignored
+                    if args.len() < param_types.len() {
+                        return Err(type_error(
+                            location,
+                            format!(
+                                "Too few arguments to function {}(), {} passed and {} expected",
+                                name,
+                                args.len(),
+                                param_types.len()
+                            ),
+                        ));
+                    }
 
                     Ok(return_type.clone())
                 } else {
@@ -271,6 +578,124 @@ impl TypeChecker {
                     ))
                 }
             }
+            Node::Closure { params, uses, body, .. } => {
+                // The body only sees what it captures, which for an arrow function is the whole scope
+                let old_variables = self.variables.clone();
+                let old_flow_dependent = self.flow_dependent.clone();
+                self.flow_dependent.extend(scope_assignments(body));
+                if let Some(uses) = uses {
+                    self.variables = uses
+                        .iter()
+                        .map(|name| (name.clone(), old_variables.get(name).cloned().unwrap_or(Type::Null)))
+                        .collect();
+
+                    // Closures in a method are bound to its object
+                    if let Some(this) = old_variables.get("this") {
+                        self.variables.insert("this".to_string(), this.clone());
+                    }
+                }
+
+                // Add parameters to scope
+                for (param_name, param_type) in params {
+                    let type_ = param_type.clone().unwrap_or(Type::Mixed);
+                    self.assign_variable(param_name, type_);
+                }
+
+                // Check body
+                self.check_node(body)?;
+
+                // Restore the old scope
+                self.variables = old_variables;
+                self.flow_dependent = old_flow_dependent;
+
+                Ok(Type::Closure)
+            }
+            Node::IndirectCall { callee, args, .. } => {
+                // Whether the callee is a closure, and how many arguments it takes, is checked when it is called
+                self.check_node(callee)?;
+                for arg in args {
+                    self.check_node(arg)?;
+                }
+                Ok(Type::Mixed)
+            }
+            Node::New { class, args, location } => {
+                for arg in args {
+                    self.check_node(arg)?;
+                }
+                let Some(info) = self.classes.get(class) else {
+                    return Err(type_error(location, format!("Class \"{}\" not found", class)));
+                };
+
+                // The arguments go to the constructor, if there is one
+                if let Some((param_types, _)) = info.methods.get("__construct") {
+                    if args.len() < param_types.len() {
+                        return Err(type_error(
+                            location,
+                            format!(
+                                "Too few arguments to function {}::__construct(), {} passed and {} expected",
+                                class,
+                                args.len(),
+                                param_types.len()
+                            ),
+                        ));
+                    }
+                }
+
+                Ok(Type::Object(class.clone()))
+            }
+            Node::PropertyAccess { object, property, .. } => {
+                // Only properties of objects whose class is known have a known type
+                let object_type = self.check_node(object)?;
+                let Type::Object(class) = object_type else {
+                    return Ok(Type::Mixed);
+                };
+                match self.classes.get(&class).map(|info| info.properties.get(property)) {
+                    // A nullable property may hold either type
+                    Some(Some(Some(Type::Nullable(_)) | None)) => Ok(Type::Mixed),
+                    Some(Some(type_)) => Ok(type_.clone().unwrap_or(Type::Mixed)),
+                    // A property the class does not declare may have been created by an assignment
+                    Some(None) | None => Ok(Type::Mixed),
+                }
+            }
+            Node::MethodCall { object, method, args, location } => {
+                let object_type = self.check_node(object)?;
+                for arg in args {
+                    self.check_node(arg)?;
+                }
+
+                // Methods can only be looked up when the class of the object is known
+                if let Type::Object(class) = object_type {
+                    self.check_method_call(&class, method, args.len(), location)?;
+                }
+                Ok(Type::Mixed)
+            }
+            Node::StaticCall { class, method, args, location } => {
+                for arg in args {
+                    self.check_node(arg)?;
+                }
+                if !self.classes.contains_key(class) {
+                    return Err(type_error(location, format!("Class \"{}\" not found", class)));
+                }
+
+                // A non-static method can be called like this from a method of the same object
+                let is_static = self.check_method_call(class, method, args.len(), location)?;
+                if !is_static && !self.variables.contains_key("this") {
+                    return Err(type_error(
+                        location,
+                        format!("Non-static method {}::{}() cannot be called statically", class, method),
+                    ));
+                }
+                Ok(Type::Mixed)
+            }
+            Node::ClassConstant { class, name, location } => {
+                let Some(info) = self.classes.get(class) else {
+                    return Err(type_error(location, format!("Class \"{}\" not found", class)));
+                };
+                match info.constants.get(name) {
+                    Some(type_) => Ok(type_.clone()),
+                    None => Err(type_error(location, format!("Undefined constant {}::{}", class, name))),
+                }
+            }
             Node::IntLiteral(_, _) => Ok(Type::Integer),
             Node::FloatLiteral(_, _) => Ok(Type::Float),
             Node::StringLiteral(_, _) => Ok(Type::String),
@@ -280,6 +705,159 @@ impl TypeChecker {
         }
     }
 
+    /// Register a user-defined function signature
+    fn declare_function(&mut self, name: &str, params: &[(String, Option<Type>)]) {
+        let param_types = params
+            .iter()
+            .map(|(_, param_type)| param_type.clone().unwrap_or(Type::Mixed))
+            .collect();
+        self.functions.insert(name.to_string(), (param_types, Type::Mixed));
+    }
+
+    /// Register a user-defined class with its members
+    fn declare_class(&mut self, node: &Node) -> Result<()> {
+        let Node::ClassDecl { name, constants, properties, methods, location } = node else {
+            return Ok(());
+        };
+        if self.classes.contains_key(name) {
+            return Err(type_error(
+                location,
+                format!("Cannot declare class {}, because the name is already in use", name),
+            ));
+        }
+
+        let info = ClassInfo {
+            constants: constants
+                .iter()
+                .map(|(constant, value)| (constant.clone(), literal_type(value).unwrap_or(Type::Mixed)))
+                .collect(),
+            properties: properties
+                .iter()
+                .map(|property| (property.name.clone(), property.type_.clone()))
+                .collect(),
+            methods: methods
+                .iter()
+                .map(|method| {
+                    let param_types = method
+                        .params
+                        .iter()
+                        .map(|(_, param_type)| param_type.clone().unwrap_or(Type::Mixed))
+                        .collect();
+                    (method.name.clone(), (param_types, method.is_static))
+                })
+                .collect(),
+        };
+        self.classes.insert(name.clone(), info);
+        Ok(())
+    }
+
+    /// Record the type of a variable after an assignment. The checks ignore control flow,
+    /// so a variable that is also assigned in a branch or loop may have any type.
+    fn assign_variable(&mut self, name: &str, type_: Type) {
+        let type_ = if self.flow_dependent.contains(name) { Type::Mixed } else { type_ };
+        self.variables.insert(name.to_string(), type_);
+    }
+
+    /// Check a call of a method of a known class, returning whether the method is static
+    fn check_method_call(&self, class: &str, method: &str, arg_count: usize, location: &Location) -> Result<bool> {
+        let Some((param_types, is_static)) = self.classes.get(class).and_then(|info| info.methods.get(method)) else {
+            return Err(type_error(location, format!("Call to undefined method {}::{}()", class, method)));
+        };
+
+        // Missing arguments are an error, extra arguments are ignored
+        if arg_count < param_types.len() {
+            return Err(type_error(
+                location,
+                format!(
+                    "Too few arguments to function {}::{}(), {} passed and {} expected",
+                    class,
+                    method,
+                    arg_count,
+                    param_types.len()
+                ),
+            ));
+        }
+        Ok(*is_static)
+    }
+
+    /// Check an assignment to a property, whose declared type the value must fit
+    fn check_property_assignment(
+        &mut self,
+        object: &Node,
+        property: &str,
+        value_type: &Type,
+        value: &Node,
+        location: &Location,
+    ) -> Result<()> {
+        let Type::Object(class) = self.check_node(object)? else {
+            return Ok(());
+        };
+        let Some(info) = self.classes.get(&class) else {
+            return Ok(());
+        };
+        // Assigning a property the class does not declare creates it, which only is deprecated
+        let Some(declared) = info.properties.get(property) else {
+            return Ok(());
+        };
+
+        // Variables read before their first assignment are typed null, so only a literal null is trusted
+        let untrusted_null = *value_type == Type::Null && literal_type(value).is_none();
+        if let Some(declared) = declared {
+            if !untrusted_null && !property_accepts(declared, value_type, true) {
+                return Err(type_error(
+                    location,
+                    format!(
+                        "Cannot assign {:?} to property {}::${} of type {:?}",
+                        value_type, class, property, declared
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a class constant or a default value of a property, which may only
+    /// combine literals and class constants
+    fn check_constant_expression(&mut self, node: &Node) -> Result<Type> {
+        let constant = match node {
+            Node::ArrayLiteral(elements, _) => {
+                for (key, value) in elements {
+                    if let Some(key) = key {
+                        self.check_constant_expression(key)?;
+                    }
+                    self.check_constant_expression(value)?;
+                }
+                true
+            }
+            Node::BinaryExpr { op, left, right, .. } => {
+                self.check_constant_expression(left)?;
+                self.check_constant_expression(right)?;
+                *op != BinaryOp::Assign
+            }
+            Node::UnaryExpr { expr, .. } => {
+                self.check_constant_expression(expr)?;
+                true
+            }
+            Node::Ternary { condition, then_branch, else_branch, .. } => {
+                self.check_constant_expression(condition)?;
+                if let Some(then_branch) = then_branch {
+                    self.check_constant_expression(then_branch)?;
+                }
+                self.check_constant_expression(else_branch)?;
+                true
+            }
+            Node::ClassConstant { .. } => true,
+            _ => literal_type(node).is_some(),
+        };
+        if !constant {
+            return Err(type_error(
+                &self.get_location(node),
+                "Constant expression contains invalid operations",
+            ));
+        }
+        self.check_node(node)
+    }
+
     /// Get the location of a node
     fn get_location(&self, node: &Node) -> crate::ast::Location {
         match node {
@@ -292,15 +870,32 @@ impl TypeChecker {
             Node::BlockStmt(_, location) => location.clone(),
             Node::IfStmt { location, .. } => location.clone(),
             Node::WhileStmt { location, .. } => location.clone(),
+            Node::DoWhileStmt { location, .. } => location.clone(),
             Node::ForStmt { location, .. } => location.clone(),
             Node::ForeachStmt { location, .. } => location.clone(),
             Node::ReturnStmt(_, location) => location.clone(),
+            Node::SwitchStmt { location, .. } => location.clone(),
+            Node::BreakStmt(_, location) => location.clone(),
+            Node::ContinueStmt(_, location) => location.clone(),
             Node::EchoStmt(_, location) => location.clone(),
             Node::VarDecl { location, .. } => location.clone(),
             Node::FunctionDecl { location, .. } => location.clone(),
+            Node::ClassDecl { location, .. } => location.clone(),
             Node::BinaryExpr { location, .. } => location.clone(),
             Node::UnaryExpr { location, .. } => location.clone(),
+            Node::Ternary { location, .. } => location.clone(),
+            Node::Match { location, .. } => location.clone(),
+            Node::CompoundAssign { location, .. } => location.clone(),
+            Node::IncDec { location, .. } => location.clone(),
+            Node::Closure { location, .. } => location.clone(),
+            Node::IndirectCall { location, .. } => location.clone(),
+            Node::New { location, .. } => location.clone(),
+            Node::PropertyAccess { location, .. } => location.clone(),
+            Node::MethodCall { location, .. } => location.clone(),
+            Node::StaticCall { location, .. } => location.clone(),
+            Node::ClassConstant { location, .. } => location.clone(),
             Node::Variable(_, location) => location.clone(),
+            Node::ArrayAppend(_, location) => location.clone(),
             Node::FunctionCall { location, .. } => location.clone(),
             Node::IntLiteral(_, location) => location.clone(),
             Node::FloatLiteral(_, location) => location.clone(),
@@ -311,6 +906,52 @@ impl TypeChecker {
         }
     }
 
+    /// Check the target of an assignment to an array element, such as `$a[$k]['x']` or `$a[]`
+    fn check_assignment_target(&mut self, node: &Node) -> Result<()> {
+        match node {
+            Node::BinaryExpr { op: BinaryOp::ArrayAccess, left, right, .. } => {
+                self.check_node(right)?;
+                self.check_assignment_target(left)
+            }
+            Node::ArrayAppend(array, _) => self.check_assignment_target(array),
+            Node::PropertyAccess { object, .. } => {
+                // Writing to an element of a property changes the object, not a variable
+                self.check_node(object)?;
+                Ok(())
+            }
+            Node::Variable(name, _) => {
+                // Writing to an element of an undefined, null or false variable creates an array
+                match self.variables.get(name) {
+                    None | Some(Type::Null) | Some(Type::Boolean) => {
+                        self.assign_variable(name, Type::Array);
+                    }
+                    _ => {}
+                }
+                Ok(())
+            }
+            _ => Err(type_error(
+                &self.get_location(node),
+                "Invalid assignment target",
+            )),
+        }
+    }
+
+    /// Check the left operand of `??`, whose elements may be read from anything without an error
+    fn check_isset_operand(&mut self, node: &Node) -> Result<Type> {
+        match node {
+            Node::BinaryExpr { op: BinaryOp::ArrayAccess, left, right, .. } => {
+                self.check_isset_operand(left)?;
+                self.check_node(right)?;
+                Ok(Type::Mixed)
+            }
+            Node::PropertyAccess { object, .. } => {
+                self.check_isset_operand(object)?;
+                Ok(Type::Mixed)
+            }
+            _ => self.check_node(node),
+        }
+    }
+
     /// Get the name of a variable from a node
     fn get_variable_name(&self, node: &Node) -> Result<String> {
         match node {
@@ -322,3 +963,236 @@ impl TypeChecker {
         }
     }
 }
+
+/// Variables assigned in a branch or loop of a program, function or closure body.
+/// Their type after the branch, or on the next pass of the loop, depends on control flow.
+fn scope_assignments(body: &Node) -> HashSet<String> {
+    let mut names = HashSet::new();
+    match body {
+        Node::Program(statements) | Node::BlockStmt(statements, _) => {
+            for stmt in statements {
+                collect_assignments(stmt, false, &mut names);
+            }
+        }
+        _ => collect_assignments(body, false, &mut names),
+    }
+    names
+}
+
+/// Collect the variables assigned by a node, when it is `nested` in a branch or loop
+fn collect_assignments(node: &Node, nested: bool, names: &mut HashSet<String>) {
+    let assigned = |target: &Node, names: &mut HashSet<String>| {
+        if nested {
+            if let Some(name) = assigned_variable(target) {
+                names.insert(name.to_string());
+            }
+        }
+    };
+
+    match node {
+        Node::Program(statements) | Node::BlockStmt(statements, _) => {
+            for stmt in statements {
+                collect_assignments(stmt, true, names);
+            }
+        }
+        Node::ExpressionStmt(expr) => collect_assignments(expr, nested, names),
+        Node::IfStmt { condition, then_branch, else_branch, .. } => {
+            collect_assignments(condition, nested, names);
+            collect_assignments(then_branch, true, names);
+            if let Some(else_branch) = else_branch {
+                collect_assignments(else_branch, true, names);
+            }
+        }
+        Node::WhileStmt { condition, body, .. } | Node::DoWhileStmt { body, condition, .. } => {
+            collect_assignments(condition, true, names);
+            collect_assignments(body, true, names);
+        }
+        Node::ForStmt { init, condition, increment, body, .. } => {
+            if let Some(init) = init {
+                collect_assignments(init, nested, names);
+            }
+            for part in [condition, increment].into_iter().flatten() {
+                collect_assignments(part, true, names);
+            }
+            collect_assignments(body, true, names);
+        }
+        Node::ForeachStmt { array, body, .. } => {
+            collect_assignments(array, nested, names);
+            collect_assignments(body, true, names);
+        }
+        Node::SwitchStmt { subject, cases, .. } => {
+            collect_assignments(subject, nested, names);
+            for (value, statements) in cases {
+                if let Some(value) = value {
+                    collect_assignments(value, true, names);
+                }
+                for stmt in statements {
+                    collect_assignments(stmt, true, names);
+                }
+            }
+        }
+        Node::ReturnStmt(value, _) => {
+            if let Some(value) = value {
+                collect_assignments(value, nested, names);
+            }
+        }
+        Node::EchoStmt(expressions, _) => {
+            for expr in expressions {
+                collect_assignments(expr, nested, names);
+            }
+        }
+        Node::VarDecl { name, initializer, .. } => {
+            if let Some(initializer) = initializer {
+                collect_assignments(initializer, nested, names);
+            }
+            if nested {
+                names.insert(name.clone());
+            }
+        }
+        // Functions, classes and closures have scopes of their own
+        Node::FunctionDecl { .. } | Node::ClassDecl { .. } | Node::Closure { .. } => {}
+        Node::BinaryExpr { op, left, right, .. } => {
+            if *op == BinaryOp::Assign {
+                assigned(left, names);
+            }
+            collect_assignments(left, nested, names);
+            // The right operand of a short-circuiting operator is only evaluated sometimes
+            let conditional = matches!(op, BinaryOp::LogicalAnd | BinaryOp::LogicalOr | BinaryOp::Coalesce);
+            collect_assignments(right, nested || conditional, names);
+        }
+        Node::UnaryExpr { expr, .. } => collect_assignments(expr, nested, names),
+        Node::Ternary { condition, then_branch, else_branch, .. } => {
+            collect_assignments(condition, nested, names);
+            if let Some(then_branch) = then_branch {
+                collect_assignments(then_branch, true, names);
+            }
+            collect_assignments(else_branch, true, names);
+        }
+        Node::Match { subject, arms, .. } => {
+            collect_assignments(subject, nested, names);
+            for (conditions, result) in arms {
+                for condition in conditions {
+                    collect_assignments(condition, true, names);
+                }
+                collect_assignments(result, true, names);
+            }
+        }
+        Node::CompoundAssign { target, value, .. } => {
+            assigned(target, names);
+            collect_assignments(target, nested, names);
+            collect_assignments(value, nested, names);
+        }
+        Node::IncDec { target, .. } => {
+            assigned(target, names);
+            collect_assignments(target, nested, names);
+        }
+        Node::ArrayAppend(array, _) => collect_assignments(array, nested, names),
+        Node::PropertyAccess { object, .. } => collect_assignments(object, nested, names),
+        Node::FunctionCall { args, .. } | Node::New { args, .. } | Node::StaticCall { args, .. } => {
+            for arg in args {
+                collect_assignments(arg, nested, names);
+            }
+        }
+        Node::IndirectCall { callee: object, args, .. } | Node::MethodCall { object, args, .. } => {
+            collect_assignments(object, nested, names);
+            for arg in args {
+                collect_assignments(arg, nested, names);
+            }
+        }
+        Node::ArrayLiteral(elements, _) => {
+            for (key, value) in elements {
+                if let Some(key) = key {
+                    collect_assignments(key, nested, names);
+                }
+                collect_assignments(value, nested, names);
+            }
+        }
+        Node::BreakStmt(..)
+        | Node::ContinueStmt(..)
+        | Node::Variable(..)
+        | Node::ClassConstant { .. }
+        | Node::IntLiteral(..)
+        | Node::FloatLiteral(..)
+        | Node::StringLiteral(..)
+        | Node::BooleanLiteral(..)
+        | Node::NullLiteral(..) => {}
+    }
+}
+
+/// The variable changed by an assignment to a target such as `$a`, `$a[$k]` or `$a[]`
+fn assigned_variable(target: &Node) -> Option<&str> {
+    match target {
+        Node::Variable(name, _) => Some(name),
+        Node::BinaryExpr { op: BinaryOp::ArrayAccess, left, .. } | Node::ArrayAppend(left, _) => assigned_variable(left),
+        _ => None,
+    }
+}
+
+/// Type of a literal, known without running the program
+fn literal_type(node: &Node) -> Option<Type> {
+    match node {
+        Node::IntLiteral(..) => Some(Type::Integer),
+        Node::FloatLiteral(..) => Some(Type::Float),
+        Node::StringLiteral(..) => Some(Type::String),
+        Node::BooleanLiteral(..) => Some(Type::Boolean),
+        Node::NullLiteral(..) => Some(Type::Null),
+        Node::ArrayLiteral(..) => Some(Type::Array),
+        _ => None,
+    }
+}
+
+/// Whether two literals are identical (===), None when either is not a literal or
+/// both are arrays, whose keys and elements are not compared
+fn identical_literals(a: &Node, b: &Node) -> Option<bool> {
+    Some(match (a, b) {
+        (Node::IntLiteral(x, _), Node::IntLiteral(y, _)) => x == y,
+        (Node::FloatLiteral(x, _), Node::FloatLiteral(y, _)) => x == y,
+        (Node::StringLiteral(x, _), Node::StringLiteral(y, _)) => x == y,
+        (Node::BooleanLiteral(x, _), Node::BooleanLiteral(y, _)) => x == y,
+        (Node::NullLiteral(_), Node::NullLiteral(_)) => true,
+        (Node::ArrayLiteral(..), Node::ArrayLiteral(..)) => return None,
+        // Literals of different types are never identical
+        _ => {
+            literal_type(a)?;
+            literal_type(b)?;
+            false
+        }
+    })
+}
+
+/// A literal as it would be written in PHP, for warnings
+fn literal_source(node: &Node) -> String {
+    match node {
+        Node::IntLiteral(i, _) => i.to_string(),
+        Node::FloatLiteral(f, _) => format!("{:?}", f),
+        Node::StringLiteral(s, _) => format!("\"{}\"", s.escape_debug()),
+        Node::BooleanLiteral(b, _) => b.to_string(),
+        Node::NullLiteral(_) => "null".to_string(),
+        Node::ArrayLiteral(..) => "an array".to_string(),
+        _ => "an expression".to_string(),
+    }
+}
+
+/// Whether a property of a declared type can hold a value of a type. Default values
+/// must match exactly, but ints widen to floats; with `coerce`, scalars also convert
+/// to each other, as they do when a property is assigned outside strict_types mode.
+fn property_accepts(declared: &Type, value: &Type, coerce: bool) -> bool {
+    if let Type::Nullable(inner) = declared {
+        return *value == Type::Null || property_accepts(inner, value, coerce);
+    }
+    let scalar = |type_: &Type| matches!(type_, Type::Integer | Type::Float | Type::String | Type::Boolean);
+    declared == value
+        || *declared == Type::Mixed
+        || *value == Type::Mixed
+        || (*declared == Type::Float && *value == Type::Integer)
+        || (coerce && scalar(declared) && scalar(value))
+}
+
+/// Type of a value that comes from either of two expressions
+fn union_type(a: Type, b: Type) -> Type {
+    if a == b {
+        a
+    } else {
+        Type::Mixed
+    }
+}
@@ -0,0 +1,1710 @@
+// vm is responsible for executing bytecode instructions directly, without asmgen or a C toolchain.
+// It follows the semantics of the native runtime (runtime.c) and serves as a reference for it.
+
+use std::cell::{Ref, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::ast::Type;
+use crate::cfg;
+use crate::codegen::{Class, Instruction, LabelId, Module};
+use crate::error::{CompilerError, Result};
+
+/// Significant digits used when converting floats to strings, PHP's default precision
+const PRECISION: usize = 14;
+
+/// Longest part of a string shown in an UnhandledMatchError
+const MATCH_CASE_MAX_LEN: usize = 15;
+
+/// A PHP string
+///
+/// As in the native runtime, a string is a prefix of a shared buffer and
+/// only the string that ends its buffer may append to it in place, so a
+/// loop of `.=` takes amortized linear time.
+#[derive(Clone)]
+struct PhpString {
+    buffer: Rc<RefCell<Vec<u8>>>,
+    len: usize,
+}
+
+impl PhpString {
+    fn new(bytes: &[u8]) -> Self {
+        Self {
+            buffer: Rc::new(RefCell::new(bytes.to_vec())),
+            len: bytes.len(),
+        }
+    }
+
+    fn bytes(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.buffer.borrow(), |buffer| &buffer[..self.len])
+    }
+
+    /// Concatenate two strings, appending in place when this string ends its buffer
+    fn concat(&self, other: &PhpString) -> PhpString {
+        let len = self.len + other.len;
+        if self.buffer.borrow().len() == self.len && !Rc::ptr_eq(&self.buffer, &other.buffer) {
+            self.buffer.borrow_mut().extend_from_slice(&other.bytes());
+            return PhpString { buffer: self.buffer.clone(), len };
+        }
+
+        let mut bytes = Vec::with_capacity((len * 2).max(16));
+        bytes.extend_from_slice(&self.bytes());
+        bytes.extend_from_slice(&other.bytes());
+        PhpString {
+            buffer: Rc::new(RefCell::new(bytes)),
+            len,
+        }
+    }
+}
+
+/// An array key, which is always an int or a string
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Int(i64),
+    String(Rc<[u8]>),
+}
+
+impl Key {
+    fn to_value(&self) -> Value {
+        match self {
+            Key::Int(i) => Value::Int(*i),
+            Key::String(s) => Value::String(PhpString::new(s)),
+        }
+    }
+}
+
+/// An insertion-ordered PHP array, copied on write when it is shared
+#[derive(Clone, Default)]
+struct PhpArray {
+    entries: Vec<(Key, Value)>, // Elements in insertion order
+    index: HashMap<Key, usize>, // Position of each key in entries
+    next_index: Option<i64>,    // Key used by the next append, unset until an int key is used
+}
+
+impl PhpArray {
+    fn get(&self, key: &Key) -> Option<&Value> {
+        self.index.get(key).map(|&position| &self.entries[position].1)
+    }
+
+    /// Find the element with the given key, adding it as null when it is missing
+    fn lookup_or_add(&mut self, key: Key) -> &mut Value {
+        let position = match self.index.get(&key) {
+            Some(&position) => position,
+            None => {
+                if let Key::Int(i) = key {
+                    if !matches!(self.next_index, Some(next) if i < next) {
+                        self.next_index = Some(i.saturating_add(1));
+                    }
+                }
+                let position = self.entries.len();
+                self.entries.push((key.clone(), Value::Null));
+                self.index.insert(key, position);
+                position
+            }
+        };
+        &mut self.entries[position].1
+    }
+
+    /// Add the elements of another array whose keys this one does not have, as `+` does
+    fn union(&mut self, other: &PhpArray) {
+        for (key, value) in &other.entries {
+            if !self.index.contains_key(key) {
+                *self.lookup_or_add(key.clone()) = value.clone();
+            }
+        }
+    }
+
+    /// Add a null element after the largest int key; returns None when that key is taken
+    fn append(&mut self) -> Option<&mut Value> {
+        let key = Key::Int(self.next_index.unwrap_or(0));
+        if self.next_index == Some(i64::MAX) && self.index.contains_key(&key) {
+            return None;
+        }
+        Some(self.lookup_or_add(key))
+    }
+}
+
+/// A value on the operand stack or in a variable
+#[derive(Clone)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(PhpString),
+    Array(Rc<PhpArray>),
+    Iterator(Rc<PhpArray>, usize), // Array of a foreach and the position of the next element, only on the stack
+    Closure(Rc<Closure>),
+    Object(Rc<PhpObject>),
+}
+
+/// An anonymous or arrow function together with the values it captured when it was created
+struct Closure {
+    function: String,     // Name of the function its body was compiled to
+    captured: Vec<Value>, // Values of the function's captured variables, in the same order
+}
+
+/// An instance of a user-defined class; values are handles to it, so copies share its properties
+struct PhpObject {
+    class: String,
+    properties: RefCell<Vec<Option<Value>>>, // In the order the class declares them, None while uninitialized
+    dynamic: RefCell<PhpArray>,               // Properties the class does not declare, created by assigning them
+}
+
+impl PhpObject {
+    /// Index of a property in `properties`, None when the class does not declare it
+    fn slot(&self, name: &str, module: &Module) -> Option<usize> {
+        module.classes.get(&self.class)?.properties.iter().position(|property| property == name)
+    }
+
+    /// Value of a property the class does not declare, None when it has not been created
+    fn dynamic_property(&self, name: &str) -> Option<Value> {
+        self.dynamic.borrow().get(&Key::String(Rc::from(name.as_bytes()))).cloned()
+    }
+}
+
+impl Value {
+    fn string(bytes: &[u8]) -> Value {
+        Value::String(PhpString::new(bytes))
+    }
+
+    fn type_name(&self) -> &str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Array(_) | Value::Iterator(..) => "array",
+            Value::Closure(_) => "Closure",
+            Value::Object(obj) => &obj.class,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Null | Value::Iterator(..) => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::String(s) => !matches!(&*s.bytes(), b"" | b"0"),
+            Value::Array(arr) => !arr.entries.is_empty(),
+            Value::Closure(_) | Value::Object(_) => true,
+        }
+    }
+
+    fn as_float(&self) -> f64 {
+        match self {
+            Value::Float(f) => *f,
+            Value::Int(i) => *i as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// Convert to an integer, as PHP's (int) cast does
+    fn to_int(&self) -> i64 {
+        match self {
+            Value::Bool(b) => *b as i64,
+            Value::Int(i) => *i,
+            Value::Float(f) => {
+                if *f >= -9223372036854775808.0 && *f < 9223372036854775808.0 {
+                    *f as i64
+                } else {
+                    0
+                }
+            }
+            Value::String(s) => parse_numeric(&s.bytes()).map_or(0, |(n, _)| n.to_int()),
+            Value::Array(arr) => !arr.entries.is_empty() as i64,
+            Value::Closure(_) | Value::Object(_) => 1,
+            Value::Null | Value::Iterator(..) => 0,
+        }
+    }
+
+    /// Convert to a string, as PHP's (string) cast does; arrays become "Array"
+    /// and objects, which string_value rejects, their class name
+    fn to_php_string(&self) -> PhpString {
+        match self {
+            Value::Null | Value::Bool(false) | Value::Iterator(..) => PhpString::new(b""),
+            Value::Bool(true) => PhpString::new(b"1"),
+            Value::Int(i) => PhpString::new(i.to_string().as_bytes()),
+            Value::Float(f) => PhpString::new(format_float(*f).as_bytes()),
+            Value::String(s) => s.clone(),
+            Value::Array(_) => PhpString::new(b"Array"),
+            Value::Closure(_) => PhpString::new(b"Closure"),
+            Value::Object(obj) => PhpString::new(obj.class.as_bytes()),
+        }
+    }
+}
+
+/// Format a float the way PHP does with its default precision, e.g. 0.1, 1.0E+25 or 1.5E-7
+fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        return "NAN".to_string();
+    }
+    if f.is_infinite() {
+        return if f < 0.0 { "-INF" } else { "INF" }.to_string();
+    }
+
+    // Round to the significant digits, e.g. "-1.2345000000000E2"
+    let sci = format!("{:.*E}", PRECISION - 1, f);
+    let (mantissa, exponent) = sci.split_once('E').unwrap();
+    let mut out = String::new();
+    let mantissa = match mantissa.strip_prefix('-') {
+        Some(rest) => {
+            out.push('-');
+            rest
+        }
+        None => mantissa,
+    };
+    let mut digits: Vec<char> = mantissa.chars().filter(|&c| c != '.').collect();
+    while digits.len() > 1 && digits.last() == Some(&'0') {
+        digits.pop();
+    }
+    let decpt = if f == 0.0 { 1 } else { exponent.parse::<i32>().unwrap() + 1 };
+
+    if decpt < -3 || decpt > PRECISION as i32 {
+        // Exponential format, always with a fractional part
+        out.push(digits[0]);
+        out.push('.');
+        if digits.len() == 1 {
+            out.push('0');
+        } else {
+            out.extend(&digits[1..]);
+        }
+        let sign = if decpt - 1 < 0 { '-' } else { '+' };
+        out.push_str(&format!("E{}{}", sign, (decpt - 1).abs()));
+    } else if decpt <= 0 {
+        // 0.000ddd
+        out.push_str("0.");
+        out.extend(std::iter::repeat_n('0', (-decpt) as usize));
+        out.extend(&digits);
+    } else {
+        // ddd.ddd, padding the integer part with zeros
+        let decpt = decpt as usize;
+        for i in 0..decpt {
+            out.push(digits.get(i).copied().unwrap_or('0'));
+        }
+        if digits.len() > decpt {
+            out.push('.');
+            out.extend(&digits[decpt..]);
+        }
+    }
+    out
+}
+
+/// Parse a PHP numeric string
+///
+/// Returns the number and whether the whole string is numeric, rather than
+/// only a leading part of it. Leading and trailing whitespace is allowed.
+fn parse_numeric(s: &[u8]) -> Option<(Value, bool)> {
+    let is_space = |c: u8| matches!(c, b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c);
+    let mut p = 0;
+    while p < s.len() && is_space(s[p]) {
+        p += 1;
+    }
+    let start = p;
+    if p < s.len() && (s[p] == b'+' || s[p] == b'-') {
+        p += 1;
+    }
+    let mut digits = 0;
+    let mut is_float = false;
+    while p < s.len() && s[p].is_ascii_digit() {
+        p += 1;
+        digits += 1;
+    }
+    if p < s.len() && s[p] == b'.' {
+        p += 1;
+        is_float = true;
+        while p < s.len() && s[p].is_ascii_digit() {
+            p += 1;
+            digits += 1;
+        }
+    }
+    if digits == 0 {
+        return None;
+    }
+    if p < s.len() && (s[p] == b'e' || s[p] == b'E') {
+        let mut q = p + 1;
+        if q < s.len() && (s[q] == b'+' || s[q] == b'-') {
+            q += 1;
+        }
+        if q < s.len() && s[q].is_ascii_digit() {
+            while q < s.len() && s[q].is_ascii_digit() {
+                q += 1;
+            }
+            p = q;
+            is_float = true;
+        }
+    }
+
+    // The number is plain ASCII
+    let number = std::str::from_utf8(&s[start..p]).unwrap();
+    let value = match number.parse::<i64>() {
+        // Out of range integers become floats
+        Ok(i) if !is_float => Value::Int(i),
+        _ => Value::Float(number.parse::<f64>().unwrap_or(0.0)),
+    };
+
+    while p < s.len() && is_space(s[p]) {
+        p += 1;
+    }
+    Some((value, p == s.len()))
+}
+
+/// Whether a string is the canonical decimal form of an int, e.g. "42" but not "042" or "+42"
+fn integer_key(s: &[u8]) -> Option<i64> {
+    let digits = s.strip_prefix(b"-").unwrap_or(s);
+    if digits.is_empty() || s.len() >= 24 || (digits[0] == b'0' && s.len() != 1) {
+        return None;
+    }
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(s).ok()?.parse().ok()
+}
+
+/// Convert a value to an array key
+fn to_key(v: &Value, container: &str) -> Result<Key> {
+    match v {
+        Value::Null => Ok(Key::String(Rc::from(&b""[..]))),
+        Value::Bool(b) => Ok(Key::Int(*b as i64)),
+        Value::Int(i) => Ok(Key::Int(*i)),
+        Value::Float(_) => Ok(Key::Int(v.to_int())),
+        Value::String(s) => {
+            let bytes = s.bytes();
+            Ok(match integer_key(&bytes) {
+                Some(i) => Key::Int(i),
+                None => Key::String(Rc::from(&*bytes)),
+            })
+        }
+        _ => Err(fatal(
+            "TypeError",
+            format!("Cannot access offset of type {} on {}", v.type_name(), container),
+        )),
+    }
+}
+
+/// Offset of a string element, which must be an int or an int-like string
+fn string_offset(key: &Value) -> Result<i64> {
+    let valid = match key {
+        Value::Int(_) | Value::Bool(_) | Value::Float(_) => true,
+        Value::String(s) => integer_key(&s.bytes()).is_some(),
+        _ => false,
+    };
+    if !valid {
+        return Err(fatal(
+            "TypeError",
+            format!("Cannot access offset of type {} on string", key.type_name()),
+        ));
+    }
+    Ok(key.to_int())
+}
+
+/// An integer raised to a non-negative integer power, unless it overflows
+fn int_power(base: i64, exponent: i64) -> Option<i64> {
+    let exponent = u32::try_from(exponent).ok()?;
+    base.checked_pow(exponent)
+}
+
+/// Add 1 or -1 to a number; integers that overflow become floats
+fn step_number(n: Value, delta: i64) -> Value {
+    match n {
+        Value::Int(i) => match i.checked_add(delta) {
+            Some(result) => Value::Int(result),
+            None => Value::Float(i as f64 + delta as f64),
+        },
+        n => Value::Float(n.as_float() + delta as f64),
+    }
+}
+
+/// Increment a string that is not numeric like a counter: "a" becomes "b",
+/// "Az" becomes "Ba", "a9" becomes "b0" and "zz" becomes "aaa". Only letters
+/// and digits are incremented; the carry stops at any other character.
+fn increment_string(s: &[u8]) -> Vec<u8> {
+    if s.is_empty() {
+        return b"1".to_vec();
+    }
+    let mut bytes = s.to_vec();
+    for i in (0..bytes.len()).rev() {
+        let (first, last, carry) = match bytes[i] {
+            b'a'..=b'z' => (b'a', b'z', b'a'),
+            b'A'..=b'Z' => (b'A', b'Z', b'A'),
+            b'0'..=b'9' => (b'0', b'9', b'1'),
+            _ => break,
+        };
+        if bytes[i] != last {
+            bytes[i] += 1;
+            break;
+        }
+        bytes[i] = first;
+        if i == 0 {
+            bytes.insert(0, carry);
+        }
+    }
+    bytes
+}
+
+/// ++: null becomes 1, bools are unchanged and non-numeric strings count up
+fn increment(a: Value) -> Result<Value> {
+    Ok(match a {
+        Value::Null => Value::Int(1),
+        Value::String(s) => match parse_numeric(&s.bytes()) {
+            Some((n, true)) => step_number(n, 1),
+            _ => Value::string(&increment_string(&s.bytes())),
+        },
+        Value::Array(_) | Value::Closure(_) | Value::Object(_) => {
+            return Err(fatal("TypeError", format!("Cannot increment {}", a.type_name())))
+        }
+        Value::Int(_) | Value::Float(_) => step_number(a, 1),
+        a => a,
+    })
+}
+
+/// --: null, bools and non-numeric strings are unchanged, except "" which becomes -1
+fn decrement(a: Value) -> Result<Value> {
+    Ok(match a {
+        Value::String(s) if s.len == 0 => Value::Int(-1),
+        Value::String(s) => {
+            let numeric = parse_numeric(&s.bytes());
+            match numeric {
+                Some((n, true)) => step_number(n, -1),
+                _ => Value::String(s),
+            }
+        }
+        Value::Array(_) | Value::Closure(_) | Value::Object(_) => {
+            return Err(fatal("TypeError", format!("Cannot decrement {}", a.type_name())))
+        }
+        Value::Int(_) | Value::Float(_) => step_number(a, -1),
+        a => a,
+    })
+}
+
+/// Read container[key] for ??, like isset() does: a missing element, a string
+/// offset that is out of range or not an integer, and an offset of any other
+/// type of value read as null without a warning
+fn array_get_quiet(container: &Value, key: &Value) -> Result<Value> {
+    match container {
+        Value::Array(arr) => Ok(arr.get(&to_key(key, "array")?).cloned().unwrap_or(Value::Null)),
+        Value::String(s) => {
+            let offset = match key {
+                Value::Int(i) => *i,
+                Value::String(k) => match integer_key(&k.bytes()) {
+                    Some(i) => i,
+                    None => return Ok(Value::Null),
+                },
+                _ => return Ok(Value::Null),
+            };
+            let bytes = s.bytes();
+            let i = if offset < 0 { offset + bytes.len() as i64 } else { offset };
+            if i < 0 || i >= bytes.len() as i64 {
+                return Ok(Value::Null);
+            }
+            Ok(Value::string(&bytes[i as usize..i as usize + 1]))
+        }
+        Value::Object(obj) => Err(fatal("Error", format!("Cannot use object of type {} as array", obj.class))),
+        _ => Ok(Value::Null),
+    }
+}
+
+fn compare_numbers(x: &Value, y: &Value) -> Option<Ordering> {
+    match (x, y) {
+        (Value::Int(x), Value::Int(y)) => Some(x.cmp(y)),
+        _ => x.as_float().partial_cmp(&y.as_float()),
+    }
+}
+
+/// PHP strict comparison: the same type and the same value, arrays with
+/// the same elements in the same order
+fn identical(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Null, Value::Null) => true,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::Int(x), Value::Int(y)) => x == y,
+        (Value::Float(x), Value::Float(y)) => x == y,
+        (Value::String(x), Value::String(y)) => *x.bytes() == *y.bytes(),
+        (Value::Array(x), Value::Array(y)) => {
+            Rc::ptr_eq(x, y)
+                || (x.entries.len() == y.entries.len()
+                    && x.entries.iter().zip(&y.entries).all(|((xk, xv), (yk, yv))| xk == yk && identical(xv, yv)))
+        }
+        (Value::Closure(x), Value::Closure(y)) => Rc::ptr_eq(x, y),
+        (Value::Object(x), Value::Object(y)) => Rc::ptr_eq(x, y),
+        _ => false,
+    }
+}
+
+/// A value as an UnhandledMatchError shows it: scalars as PHP code, with
+/// strings escaped and cut after 15 bytes, and anything else by its type
+fn match_case(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => format_float(*f),
+        Value::String(s) => {
+            let bytes = s.bytes();
+            let mut out = String::from("'");
+            for &byte in bytes.iter().take(MATCH_CASE_MAX_LEN) {
+                match byte {
+                    b'\n' => out.push_str("\\n"),
+                    b'\r' => out.push_str("\\r"),
+                    b'\t' => out.push_str("\\t"),
+                    0x0b => out.push_str("\\v"),
+                    0x0c => out.push_str("\\f"),
+                    0x1b => out.push_str("\\e"),
+                    b'\\' => out.push_str("\\\\"),
+                    32..=126 => out.push(byte as char),
+                    _ => out.push_str(&format!("\\x{:02X}", byte)),
+                }
+            }
+            if bytes.len() > MATCH_CASE_MAX_LEN {
+                out.push_str("...");
+            }
+            out.push('\'');
+            out
+        }
+        Value::Array(_) | Value::Iterator(..) | Value::Closure(_) | Value::Object(_) => {
+            format!("of type {}", value.type_name())
+        }
+    }
+}
+
+/// Arrays with fewer elements are smaller. Otherwise they are compared
+/// element by element, and are unordered when a key is missing in b.
+fn compare_arrays(a: &PhpArray, b: &PhpArray) -> Option<Ordering> {
+    if a.entries.len() != b.entries.len() {
+        return Some(a.entries.len().cmp(&b.entries.len()));
+    }
+    for (key, value) in &a.entries {
+        match compare(value, b.get(key)?)? {
+            Ordering::Equal => {}
+            ordering => return Some(ordering),
+        }
+    }
+    Some(Ordering::Equal)
+}
+
+/// Objects of the same class are compared property by property, in declaration
+/// order; objects of different classes are unordered.
+fn compare_objects(a: &PhpObject, b: &PhpObject) -> Option<Ordering> {
+    if a.class != b.class {
+        return None;
+    }
+    if std::ptr::eq(a, b) {
+        return Some(Ordering::Equal);
+    }
+    for (x, y) in a.properties.borrow().iter().zip(b.properties.borrow().iter()) {
+        // An uninitialized property only equals another uninitialized one
+        let ordering = match (x, y) {
+            (Some(x), Some(y)) => compare(x, y)?,
+            (None, None) => Ordering::Equal,
+            _ => return None,
+        };
+        match ordering {
+            Ordering::Equal => {}
+            ordering => return Some(ordering),
+        }
+    }
+    // Dynamic properties are compared like the elements of arrays
+    compare_arrays(&a.dynamic.borrow(), &b.dynamic.borrow())
+}
+
+/// PHP 8 loose comparison, None when the operands cannot be ordered (NAN)
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    let numeric = |s: &PhpString| match parse_numeric(&s.bytes()) {
+        Some((n, true)) => Some(n),
+        _ => None,
+    };
+
+    match (a, b) {
+        // null <=> string compares the empty string
+        (Value::Null, Value::String(s)) => Some(0.cmp(&s.len)),
+        (Value::String(s), Value::Null) => Some(s.len.cmp(&0)),
+        // bool or null against anything compares truthiness
+        (Value::Null | Value::Bool(_), _) | (_, Value::Null | Value::Bool(_)) => Some(a.truthy().cmp(&b.truthy())),
+        // a closure only equals itself, objects of one class compare their properties,
+        // and both are greater than anything else
+        (Value::Closure(x), Value::Closure(y)) => Rc::ptr_eq(x, y).then_some(Ordering::Equal),
+        (Value::Object(x), Value::Object(y)) => compare_objects(x, y),
+        (Value::Closure(_), Value::Object(_)) | (Value::Object(_), Value::Closure(_)) => None,
+        (Value::Closure(_) | Value::Object(_), _) => Some(Ordering::Greater),
+        (_, Value::Closure(_) | Value::Object(_)) => Some(Ordering::Less),
+        // arrays are greater than any scalar
+        (Value::Array(x), Value::Array(y)) => compare_arrays(x, y),
+        (Value::Array(_), _) => Some(Ordering::Greater),
+        (_, Value::Array(_)) => Some(Ordering::Less),
+        (Value::String(x), Value::String(y)) => match (numeric(x), numeric(y)) {
+            (Some(x), Some(y)) => compare_numbers(&x, &y),
+            _ => Some(x.bytes().cmp(&y.bytes())),
+        },
+        // number <=> string is numeric only when the string is numeric
+        (Value::String(x), _) => match numeric(x) {
+            Some(x) => compare_numbers(&x, b),
+            None => Some((*x.bytes()).cmp(&*b.to_php_string().bytes())),
+        },
+        (_, Value::String(y)) => match numeric(y) {
+            Some(y) => compare_numbers(a, &y),
+            None => Some((*a.to_php_string().bytes()).cmp(&*y.bytes())),
+        },
+        _ => compare_numbers(a, b),
+    }
+}
+
+/// Check that storing an element into a property may turn it into an array: only
+/// properties that can hold arrays are auto-initialized from null, false or uninitialized
+fn check_array_property(class: &Class, slot: usize, current: Option<&Value>) -> Result<()> {
+    if let Some(Some(declared)) = class.property_types.get(slot) {
+        let holds_arrays = match declared {
+            Type::Nullable(inner) => **inner == Type::Array,
+            _ => matches!(declared, Type::Array | Type::Mixed),
+        };
+        if matches!(current, None | Some(Value::Null | Value::Bool(false))) && !holds_arrays {
+            return Err(fatal(
+                "Error",
+                format!(
+                    "Cannot auto-initialize an array inside property {}::${} of type {}",
+                    class.name,
+                    class.properties[slot],
+                    declared.name()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// An uncaught PHP error, which ends the program
+fn fatal(error_class: &str, message: impl Into<String>) -> CompilerError {
+    CompilerError::RuntimeError {
+        message: format!("{}: {}", error_class, message.into()),
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Result<Value> {
+    stack.pop().ok_or_else(stack_underflow)
+}
+
+/// Pop the top `count` values, keeping their order
+fn pop_many(stack: &mut Vec<Value>, count: usize) -> Result<Vec<Value>> {
+    let start = stack.len().checked_sub(count).ok_or_else(stack_underflow)?;
+    Ok(stack.split_off(start))
+}
+
+/// The top `count` values, leaving them on the stack
+fn peek_many(stack: &[Value], count: usize) -> Result<&[Value]> {
+    let start = stack.len().checked_sub(count).ok_or_else(stack_underflow)?;
+    Ok(&stack[start..])
+}
+
+fn stack_underflow() -> CompilerError {
+    CompilerError::RuntimeError {
+        message: "Operand stack underflow".to_string(),
+    }
+}
+
+/// A call to a user-defined function or the top-level code that is executing
+struct Frame<'m> {
+    instructions: &'m [Instruction],
+    pc: usize,                       // Position of the next instruction
+    stack: Vec<Value>,               // Operand stack
+    variables: HashMap<String, Value>,
+}
+
+impl<'m> Frame<'m> {
+    fn new(instructions: &'m [Instruction], variables: HashMap<String, Value>) -> Self {
+        Self {
+            instructions,
+            pc: 0,
+            stack: Vec::new(),
+            variables,
+        }
+    }
+}
+
+/// Result of a call: the value a built-in function returned, or the frame of a user-defined one
+enum Call<'m> {
+    Done(Value),
+    Enter(Frame<'m>),
+}
+
+/// Leave the current frame, passing its return value to the caller's operand stack;
+/// returns the value when the top-level code itself returned
+fn return_from(frames: &mut Vec<Frame>, value: Value) -> Option<Value> {
+    frames.pop();
+    match frames.last_mut() {
+        Some(caller) => {
+            caller.stack.push(value);
+            None
+        }
+        None => Some(value),
+    }
+}
+
+/// Bytecode interpreter
+pub struct Vm<W: Write> {
+    out: W,
+    constants: Vec<PhpString>, // The module's constant pool, shared by every PushString of a constant
+    labels: HashMap<LabelId, usize>, // Position of every label in the body that defines it
+}
+
+impl<W: Write> Vm<W> {
+    /// Create an interpreter that writes the program's output to `out`
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            constants: Vec::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Run the top-level code of a module
+    ///
+    /// An uncaught PHP error is returned as a `CompilerError::RuntimeError`,
+    /// after the output written so far has been flushed.
+    pub fn run(&mut self, module: &Module) -> Result<()> {
+        self.constants = module.constants.strings().iter().map(|s| PhpString::new(s.as_bytes())).collect();
+        // Labels are unique across the module, so one map serves every body
+        self.labels = cfg::label_positions(&module.main)?;
+        for function in module.functions.values() {
+            self.labels.extend(cfg::label_positions(&function.instructions)?);
+        }
+        let result = self.execute(&module.main, module, HashMap::new());
+        self.out.flush()?;
+        result.map(|_| ())
+    }
+
+    /// Execute the top-level code, returning the value it returns
+    ///
+    /// Calls to user-defined functions push a frame onto `frames` instead of
+    /// recursing, so deep PHP recursion does not use up the native stack.
+    fn execute<'m>(
+        &mut self,
+        instructions: &'m [Instruction],
+        module: &'m Module,
+        variables: HashMap<String, Value>,
+    ) -> Result<Value> {
+        let mut frames = vec![Frame::new(instructions, variables)];
+
+        loop {
+            let frame = frames.last_mut().expect("the top-level frame is only popped on return");
+            let instructions = frame.instructions;
+            let Frame { pc, stack, variables, .. } = frame;
+
+            // Falling off the end of a body returns null
+            let Some(instruction) = instructions.get(*pc) else {
+                if let Some(value) = return_from(&mut frames, Value::Null) {
+                    return Ok(value);
+                }
+                continue;
+            };
+            *pc += 1;
+            match instruction {
+                Instruction::PushInt(value) => stack.push(Value::Int(*value)),
+                Instruction::PushFloat(value) => stack.push(Value::Float(*value)),
+                Instruction::PushString(index) => stack.push(Value::String(self.constants[*index].clone())),
+                Instruction::PushBool(value) => stack.push(Value::Bool(*value)),
+                Instruction::PushNull => stack.push(Value::Null),
+                Instruction::Pop => {
+                    pop(stack)?;
+                }
+                Instruction::Dup => {
+                    let top = pop(stack)?;
+                    stack.push(top.clone());
+                    stack.push(top);
+                }
+                Instruction::LoadVar(name) => {
+                    // Undefined variables read as null
+                    stack.push(variables.get(name).cloned().unwrap_or(Value::Null));
+                }
+                Instruction::StoreVar(name) => {
+                    let value = pop(stack)?;
+                    variables.insert(name.clone(), value);
+                }
+                Instruction::CreateArray => stack.push(Value::Array(Rc::default())),
+                Instruction::ArrayPush => {
+                    let value = pop(stack)?;
+                    if let Some(Value::Array(arr)) = stack.last_mut() {
+                        match Rc::make_mut(arr).append() {
+                            Some(element) => *element = value,
+                            None => self.warning("Cannot add element to the array as the next element is already occupied")?,
+                        }
+                    }
+                }
+                Instruction::ArraySet => {
+                    let key = pop(stack)?;
+                    let value = pop(stack)?;
+                    let key = to_key(&key, "array")?;
+                    if let Some(Value::Array(arr)) = stack.last_mut() {
+                        *Rc::make_mut(arr).lookup_or_add(key) = value;
+                    }
+                }
+                Instruction::ArrayGet => {
+                    let key = pop(stack)?;
+                    let container = pop(stack)?;
+                    let value = self.array_get(&container, &key)?;
+                    stack.push(value);
+                }
+                Instruction::ArrayGetQuiet => {
+                    let key = pop(stack)?;
+                    let container = pop(stack)?;
+                    stack.push(array_get_quiet(&container, &key)?);
+                }
+                Instruction::LoadElement(name, dims) => {
+                    let key_count = dims.iter().filter(|&&has_key| has_key).count();
+                    let keys = peek_many(stack, key_count)?;
+                    let var = variables.get(name).cloned().unwrap_or(Value::Null);
+                    let value = self.load_element(var, dims, keys)?;
+                    stack.push(value);
+                }
+                Instruction::StoreElement(name, dims) => {
+                    let value = pop(stack)?;
+                    let key_count = dims.iter().filter(|&&has_key| has_key).count();
+                    let keys = pop_many(stack, key_count)?;
+                    let var = variables.entry(name.clone()).or_insert(Value::Null);
+                    self.store_element(var, dims, &keys, value.clone())?;
+                    stack.push(value);
+                }
+                Instruction::Add
+                | Instruction::Subtract
+                | Instruction::Multiply
+                | Instruction::Divide
+                | Instruction::Modulo
+                | Instruction::Power => {
+                    let b = pop(stack)?;
+                    let a = pop(stack)?;
+                    let result = self.arithmetic(instruction, &a, &b)?;
+                    stack.push(result);
+                }
+                Instruction::Negate => {
+                    let a = pop(stack)?;
+                    let result = match self.number_value(&a, &a, &Value::Int(0), "*")? {
+                        Value::Int(i) if i != i64::MIN => Value::Int(-i),
+                        x => Value::Float(-x.as_float()),
+                    };
+                    stack.push(result);
+                }
+                Instruction::BitwiseAnd | Instruction::BitwiseOr | Instruction::BitwiseXor => {
+                    let b = pop(stack)?;
+                    let a = pop(stack)?;
+                    let result = self.bitwise(instruction, &a, &b)?;
+                    stack.push(result);
+                }
+                Instruction::ShiftLeft | Instruction::ShiftRight => {
+                    let b = pop(stack)?;
+                    let a = pop(stack)?;
+                    let op = if *instruction == Instruction::ShiftLeft { "<<" } else { ">>" };
+                    let x = self.checked_int(&a, &a, &b, op)?;
+                    let amount = self.checked_int(&b, &a, &b, op)?;
+                    if amount < 0 {
+                        return Err(fatal("ArithmeticError", "Bit shift by negative number"));
+                    }
+                    // Shifts by 64 bits or more shift every bit out
+                    let result = if *instruction == Instruction::ShiftLeft {
+                        if amount >= 64 { 0 } else { x << amount }
+                    } else {
+                        x >> amount.min(63)
+                    };
+                    stack.push(Value::Int(result));
+                }
+                Instruction::BitwiseNot => {
+                    let a = pop(stack)?;
+                    let result = match &a {
+                        Value::Int(_) | Value::Float(_) => Value::Int(!self.checked_int(&a, &a, &Value::Int(0), "~")?),
+                        Value::String(s) => Value::string(&s.bytes().iter().map(|byte| !byte).collect::<Vec<u8>>()),
+                        _ => {
+                            return Err(fatal(
+                                "TypeError",
+                                format!("Cannot perform bitwise not on {}", a.type_name()),
+                            ))
+                        }
+                    };
+                    stack.push(result);
+                }
+                Instruction::Increment => {
+                    let a = pop(stack)?;
+                    stack.push(increment(a)?);
+                }
+                Instruction::Decrement => {
+                    let a = pop(stack)?;
+                    stack.push(decrement(a)?);
+                }
+                Instruction::Equal
+                | Instruction::NotEqual
+                | Instruction::Less
+                | Instruction::LessEqual
+                | Instruction::Greater
+                | Instruction::GreaterEqual => {
+                    let b = pop(stack)?;
+                    let a = pop(stack)?;
+                    let ordering = compare(&a, &b);
+                    let result = match instruction {
+                        Instruction::Equal => ordering == Some(Ordering::Equal),
+                        Instruction::NotEqual => ordering != Some(Ordering::Equal),
+                        Instruction::Less => ordering == Some(Ordering::Less),
+                        Instruction::LessEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                        Instruction::Greater => ordering == Some(Ordering::Greater),
+                        _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                    };
+                    stack.push(Value::Bool(result));
+                }
+                Instruction::Spaceship => {
+                    let b = pop(stack)?;
+                    let a = pop(stack)?;
+                    // Operands that cannot be ordered count as greater
+                    let result = match compare(&a, &b) {
+                        Some(Ordering::Less) => -1,
+                        Some(Ordering::Equal) => 0,
+                        _ => 1,
+                    };
+                    stack.push(Value::Int(result));
+                }
+                Instruction::Identical | Instruction::NotIdentical => {
+                    let b = pop(stack)?;
+                    let a = pop(stack)?;
+                    let result = identical(&a, &b) == (*instruction == Instruction::Identical);
+                    stack.push(Value::Bool(result));
+                }
+                Instruction::LogicalNot => {
+                    let a = pop(stack)?;
+                    stack.push(Value::Bool(!a.truthy()));
+                }
+                Instruction::IterInit => {
+                    // The loop iterates over the array as it was, even if the variable is modified
+                    let iterator = match pop(stack)? {
+                        Value::Array(arr) => Value::Iterator(arr, 0),
+                        Value::Object(obj) => {
+                            // An object iterates over its initialized properties, as they were when the loop started
+                            let mut properties = PhpArray::default();
+                            let names = &module.classes[&obj.class].properties;
+                            for (name, value) in names.iter().zip(obj.properties.borrow().iter()) {
+                                if let Some(value) = value {
+                                    *properties.lookup_or_add(Key::String(Rc::from(name.as_bytes()))) = value.clone();
+                                }
+                            }
+                            properties.union(&obj.dynamic.borrow());
+                            Value::Iterator(Rc::new(properties), 0)
+                        }
+                        v => {
+                            self.warning(&format!(
+                                "foreach() argument must be of type array|object, {} given",
+                                v.type_name()
+                            ))?;
+                            Value::Iterator(Rc::default(), 0)
+                        }
+                    };
+                    stack.push(iterator);
+                }
+                Instruction::IterNext(label) => {
+                    let Some(Value::Iterator(arr, position)) = stack.last_mut() else {
+                        return Err(CompilerError::RuntimeError {
+                            message: "IterNext without an iterator".to_string(),
+                        });
+                    };
+                    match arr.entries.get(*position) {
+                        Some((key, value)) => {
+                            let (key, value) = (key.to_value(), value.clone());
+                            *position += 1;
+                            stack.push(value);
+                            stack.push(key);
+                        }
+                        None => {
+                            stack.pop();
+                            *pc = self.position(*label)?;
+                        }
+                    }
+                }
+                Instruction::Jump(label) => *pc = self.position(*label)?,
+                Instruction::JumpIfFalse(label) => {
+                    if !pop(stack)?.truthy() {
+                        *pc = self.position(*label)?;
+                    }
+                }
+                Instruction::JumpIfTrue(label) => {
+                    if pop(stack)?.truthy() {
+                        *pc = self.position(*label)?;
+                    }
+                }
+                Instruction::UnhandledMatch => {
+                    let value = pop(stack)?;
+                    return Err(fatal("UnhandledMatchError", format!("Unhandled match case {}", match_case(&value))));
+                }
+                Instruction::JumpTable(first, targets, otherwise) => {
+                    let target = match stack.last() {
+                        Some(Value::Int(value)) => value
+                            .checked_sub(*first)
+                            .and_then(|offset| usize::try_from(offset).ok())
+                            .and_then(|offset| targets.get(offset)),
+                        _ => None,
+                    };
+                    *pc = self.position(*target.unwrap_or(otherwise))?;
+                }
+                Instruction::Label(_) => {}
+                Instruction::Call(name, arg_count) => {
+                    // Arguments are pushed in reverse, so the first one is on top
+                    let mut args = Vec::with_capacity(*arg_count);
+                    for _ in 0..*arg_count {
+                        args.push(pop(stack)?);
+                    }
+                    match self.call(name, args, module)? {
+                        Call::Done(result) => stack.push(result),
+                        Call::Enter(callee) => frames.push(callee),
+                    }
+                }
+                Instruction::CallIndirect(arg_count) => {
+                    // Arguments are pushed in reverse, so the first one is on top, above the closure
+                    let mut args = Vec::with_capacity(*arg_count);
+                    for _ in 0..*arg_count {
+                        args.push(pop(stack)?);
+                    }
+                    let callee = pop(stack)?;
+                    match self.call_closure(&callee, args, module)? {
+                        Call::Done(result) => stack.push(result),
+                        Call::Enter(callee) => frames.push(callee),
+                    }
+                }
+                Instruction::MakeClosure(name, capture_count) => {
+                    // The first captured value is on top
+                    let mut captured = Vec::with_capacity(*capture_count);
+                    for _ in 0..*capture_count {
+                        captured.push(pop(stack)?);
+                    }
+                    stack.push(Value::Closure(Rc::new(Closure {
+                        function: name.clone(),
+                        captured,
+                    })));
+                }
+                Instruction::NewObject(class) => {
+                    // The default value of the first property is on top
+                    let Some(class) = module.classes.get(class) else {
+                        return Err(fatal("Error", format!("Class \"{}\" not found", class)));
+                    };
+                    let mut properties = Vec::with_capacity(class.properties.len());
+                    for slot in 0..class.properties.len() {
+                        // Defaults are checked when compiling, but ints still widen to floats
+                        let value = match pop(stack)? {
+                            _ if class.starts_uninitialized(slot) => None,
+                            Value::Null => Some(Value::Null),
+                            value => Some(self.property_value(class, slot, value)?),
+                        };
+                        properties.push(value);
+                    }
+                    stack.push(Value::Object(Rc::new(PhpObject {
+                        class: class.name.clone(),
+                        properties: RefCell::new(properties),
+                        dynamic: RefCell::default(),
+                    })));
+                }
+                Instruction::LoadProperty(name) => {
+                    let object = pop(stack)?;
+                    let value = self.load_property(&object, name, module)?;
+                    stack.push(value);
+                }
+                Instruction::LoadPropertyQuiet(name) => {
+                    let value = match pop(stack)? {
+                        Value::Object(obj) => match obj.slot(name, module) {
+                            Some(slot) => obj.properties.borrow()[slot].clone().unwrap_or(Value::Null),
+                            None => obj.dynamic_property(name).unwrap_or(Value::Null),
+                        },
+                        _ => Value::Null,
+                    };
+                    stack.push(value);
+                }
+                Instruction::LoadPropertyElement(name, dims) => {
+                    let key_count = dims.iter().filter(|&&has_key| has_key).count();
+                    // The object lies below the keys
+                    let (object, keys) = peek_many(stack, key_count + 1)?.split_first().unwrap();
+                    let var = self.load_property(object, name, module)?;
+                    let value = self.load_element(var, dims, keys)?;
+                    stack.push(value);
+                }
+                Instruction::StorePropertyElement(name, dims) => {
+                    let value = pop(stack)?;
+                    let key_count = dims.iter().filter(|&&has_key| has_key).count();
+                    let keys = pop_many(stack, key_count)?;
+                    let object = pop(stack)?;
+                    let Value::Object(obj) = &object else {
+                        return Err(fatal(
+                            "Error",
+                            format!("Attempt to assign property \"{}\" on {}", name, object.type_name()),
+                        ));
+                    };
+                    match obj.slot(name, module) {
+                        Some(slot) => {
+                            let class = &module.classes[&obj.class];
+                            let value = if dims.is_empty() {
+                                self.property_value(class, slot, value)?
+                            } else {
+                                check_array_property(class, slot, obj.properties.borrow()[slot].as_ref())?;
+                                value
+                            };
+                            let mut properties = obj.properties.borrow_mut();
+                            let property = properties[slot].get_or_insert(Value::Null);
+                            self.store_element(property, dims, &keys, value.clone())?;
+                            stack.push(value);
+                        }
+                        None => {
+                            // Properties the class does not declare are created on first assignment, without a type
+                            let key = Key::String(Rc::from(name.as_bytes()));
+                            if obj.dynamic.borrow().get(&key).is_none() {
+                                self.diagnostic(
+                                    "Deprecated",
+                                    &format!("Creation of dynamic property {}::${} is deprecated", obj.class, name),
+                                )?;
+                            }
+                            let mut dynamic = obj.dynamic.borrow_mut();
+                            self.store_element(dynamic.lookup_or_add(key), dims, &keys, value.clone())?;
+                            stack.push(value);
+                        }
+                    }
+                }
+                Instruction::CallMethod(name, arg_count) => {
+                    // Arguments are pushed in reverse, so the first one is on top, above the object
+                    let mut args = Vec::with_capacity(*arg_count + 1);
+                    for _ in 0..*arg_count {
+                        args.push(pop(stack)?);
+                    }
+                    let object = pop(stack)?;
+                    match self.call_method(object, name, args, module)? {
+                        Call::Done(result) => stack.push(result),
+                        Call::Enter(callee) => frames.push(callee),
+                    }
+                }
+                Instruction::Return => {
+                    let value = pop(stack)?;
+                    if let Some(value) = return_from(&mut frames, value) {
+                        return Ok(value);
+                    }
+                }
+                Instruction::Echo => {
+                    let value = pop(stack)?;
+                    self.echo(&value)?;
+                }
+                Instruction::EchoLine => {
+                    let value = pop(stack)?;
+                    self.echo(&value)?;
+                    self.out.write_all(b"\n")?;
+                }
+                Instruction::Concat => {
+                    let b = pop(stack)?;
+                    let a = pop(stack)?;
+                    let result = self.string_value(&a)?.concat(&self.string_value(&b)?);
+                    stack.push(Value::String(result));
+                }
+            }
+        }
+    }
+
+    /// Position to continue at after a jump to a label
+    fn position(&self, label: LabelId) -> Result<usize> {
+        self.labels.get(&label).copied().ok_or_else(|| CompilerError::RuntimeError {
+            message: format!("Jump to undefined label {}", label),
+        })
+    }
+
+    /// Call a user-defined or built-in function
+    fn call<'m>(&mut self, name: &str, args: Vec<Value>, module: &'m Module) -> Result<Call<'m>> {
+        if let Some(function) = module.functions.get(name) {
+            // Missing arguments are null, extra arguments are ignored
+            let mut args = args.into_iter();
+            let variables = function
+                .params
+                .iter()
+                .map(|param| (param.clone(), args.next().unwrap_or(Value::Null)))
+                .collect();
+            return Ok(Call::Enter(Frame::new(&function.instructions, variables)));
+        }
+
+        let result = match name {
+            "strlen" => {
+                let s = self.string_value(args.first().unwrap_or(&Value::Null))?;
+                Value::Int(s.len as i64)
+            }
+            "substr" => {
+                let s = self.string_value(args.first().unwrap_or(&Value::Null))?;
+                let len = s.len as i64;
+                let mut offset = args.get(1).map_or(0, Value::to_int);
+                if offset > len {
+                    return Ok(Call::Done(Value::string(b"")));
+                }
+                if offset < 0 {
+                    offset = (len + offset).max(0);
+                }
+                let length = match args.get(2) {
+                    None | Some(Value::Null) => len - offset,
+                    Some(length) => {
+                        let length = length.to_int();
+                        if length < 0 {
+                            (len - offset + length).max(0)
+                        } else {
+                            length.min(len - offset)
+                        }
+                    }
+                };
+                let bytes = s.bytes();
+                Value::string(&bytes[offset as usize..(offset + length) as usize])
+            }
+            _ => return Err(fatal("Error", format!("Call to undefined function {}()", name))),
+        };
+        Ok(Call::Done(result))
+    }
+
+    /// Call a closure, with its captured variables set to the values it captured
+    fn call_closure<'m>(&mut self, callee: &Value, args: Vec<Value>, module: &'m Module) -> Result<Call<'m>> {
+        let Value::Closure(closure) = callee else {
+            return Err(fatal("Error", "Value not callable"));
+        };
+        let function = module.functions.get(&closure.function).ok_or_else(|| CompilerError::RuntimeError {
+            message: format!("Closure of undefined function {}", closure.function),
+        })?;
+
+        // Unlike direct calls, calls through a value check that every parameter gets an argument
+        if args.len() < function.params.len() {
+            return Err(fatal(
+                "ArgumentCountError",
+                format!(
+                    "Too few arguments to function {{closure}}(), {} passed and exactly {} expected",
+                    args.len(),
+                    function.params.len()
+                ),
+            ));
+        }
+
+        // Extra arguments are ignored
+        let mut args = args.into_iter();
+        let mut variables: HashMap<String, Value> =
+            function.captures.iter().cloned().zip(closure.captured.iter().cloned()).collect();
+        for param in &function.params {
+            variables.insert(param.clone(), args.next().unwrap_or(Value::Null));
+        }
+        Ok(Call::Enter(Frame::new(&function.instructions, variables)))
+    }
+
+    /// Call a method of an object, which the method's function gets as its first argument
+    fn call_method<'m>(&mut self, object: Value, name: &str, args: Vec<Value>, module: &'m Module) -> Result<Call<'m>> {
+        let Value::Object(obj) = &object else {
+            return Err(fatal(
+                "Error",
+                format!("Call to a member function {}() on {}", name, object.type_name()),
+            ));
+        };
+        let class = module.classes.get(&obj.class).filter(|class| class.methods.iter().any(|method| method == name));
+        let Some(class) = class else {
+            return Err(fatal("Error", format!("Call to undefined method {}::{}()", obj.class, name)));
+        };
+        let function_name = class.function_name(name);
+
+        // As with closures, every parameter must get an argument
+        let param_count = module.functions.get(&function_name).map_or(1, |function| function.param_count) - 1;
+        if args.len() < param_count {
+            return Err(fatal(
+                "ArgumentCountError",
+                format!(
+                    "Too few arguments to function {}(), {} passed and exactly {} expected",
+                    function_name,
+                    args.len(),
+                    param_count
+                ),
+            ));
+        }
+
+        let args = std::iter::once(object.clone()).chain(args).collect();
+        self.call(&function_name, args, module)
+    }
+
+    /// Read a property of an object, warning when there is no such property or no object
+    fn load_property(&mut self, object: &Value, name: &str, module: &Module) -> Result<Value> {
+        let Value::Object(obj) = object else {
+            self.warning(&format!("Attempt to read property \"{}\" on {}", name, object.type_name()))?;
+            return Ok(Value::Null);
+        };
+        match obj.slot(name, module) {
+            Some(slot) => obj.properties.borrow()[slot].clone().ok_or_else(|| {
+                fatal(
+                    "Error",
+                    format!("Typed property {}::${} must not be accessed before initialization", obj.class, name),
+                )
+            }),
+            None => match obj.dynamic_property(name) {
+                Some(value) => Ok(value),
+                None => {
+                    self.warning(&format!("Undefined property: {}::${}", obj.class, name))?;
+                    Ok(Value::Null)
+                }
+            },
+        }
+    }
+
+    /// Convert a value stored into a property to the property's declared type, as PHP does
+    /// outside strict_types mode: scalars convert to each other, anything else must match
+    fn property_value(&mut self, class: &Class, slot: usize, value: Value) -> Result<Value> {
+        let Some(Some(declared)) = class.property_types.get(slot) else {
+            return Ok(value);
+        };
+        // ?T takes null and converts anything else as T does
+        let type_ = match declared {
+            Type::Nullable(_) if matches!(value, Value::Null) => return Ok(value),
+            Type::Nullable(inner) => &**inner,
+            _ => declared,
+        };
+        match (type_, &value) {
+            (Type::Mixed, _)
+            | (Type::Integer, Value::Int(_))
+            | (Type::Float, Value::Float(_))
+            | (Type::String, Value::String(_))
+            | (Type::Boolean, Value::Bool(_))
+            | (Type::Array, Value::Array(_))
+            | (Type::Null, Value::Null)
+            | (Type::Closure, Value::Closure(_)) => return Ok(value),
+            (Type::Object(expected), Value::Object(obj)) if obj.class == *expected => return Ok(value),
+            (Type::Integer, Value::Bool(b)) => return Ok(Value::Int(*b as i64)),
+            (Type::Integer, Value::Float(_) | Value::String(_)) => {
+                let number = match &value {
+                    Value::String(s) => parse_numeric(&s.bytes()).filter(|&(_, whole)| whole).map(|(n, _)| n),
+                    _ => Some(value.clone()),
+                };
+                match number {
+                    Some(Value::Int(i)) => return Ok(Value::Int(i)),
+                    // Floats out of the range of int cannot be stored, fractions are cut off
+                    Some(Value::Float(f)) if (-9223372036854775808.0..9223372036854775808.0).contains(&f) => {
+                        if f != f.trunc() {
+                            let message = match &value {
+                                Value::String(s) => format!(
+                                    "Implicit conversion from float-string \"{}\" to int loses precision",
+                                    String::from_utf8_lossy(&s.bytes())
+                                ),
+                                _ => format!("Implicit conversion from float {} to int loses precision", format_float(f)),
+                            };
+                            self.diagnostic("Deprecated", &message)?;
+                        }
+                        return Ok(Value::Int(f as i64));
+                    }
+                    _ => {}
+                }
+            }
+            (Type::Float, Value::Int(_) | Value::Bool(_)) => return Ok(Value::Float(value.to_int() as f64)),
+            (Type::Float, Value::String(s)) => {
+                if let Some((n, true)) = parse_numeric(&s.bytes()) {
+                    return Ok(Value::Float(n.as_float()));
+                }
+            }
+            (Type::String, Value::Int(_) | Value::Float(_) | Value::Bool(_)) => {
+                return Ok(Value::String(value.to_php_string()));
+            }
+            (Type::Boolean, Value::Int(_) | Value::Float(_) | Value::String(_)) => return Ok(Value::Bool(value.truthy())),
+            _ => {}
+        }
+        Err(fatal(
+            "TypeError",
+            format!(
+                "Cannot assign {} to property {}::${} of type {}",
+                value.type_name(),
+                class.name,
+                class.properties[slot],
+                declared.name()
+            ),
+        ))
+    }
+
+    /// Convert a value to a string, warning about arrays
+    fn string_value(&mut self, v: &Value) -> Result<PhpString> {
+        match v {
+            Value::Array(_) => self.warning("Array to string conversion")?,
+            Value::Closure(_) | Value::Object(_) => {
+                return Err(fatal(
+                    "Error",
+                    format!("Object of class {} could not be converted to string", v.type_name()),
+                ));
+            }
+            _ => {}
+        }
+        Ok(v.to_php_string())
+    }
+
+    /// Convert an arithmetic operand to an int or float
+    fn number_value(&mut self, v: &Value, a: &Value, b: &Value, op: &str) -> Result<Value> {
+        match v {
+            Value::Null => return Ok(Value::Int(0)),
+            Value::Bool(b) => return Ok(Value::Int(*b as i64)),
+            Value::Int(_) | Value::Float(_) => return Ok(v.clone()),
+            Value::String(s) => {
+                let parsed = parse_numeric(&s.bytes());
+                match parsed {
+                    Some((n, true)) => return Ok(n),
+                    Some((n, false)) => {
+                        self.warning("A non-numeric value encountered")?;
+                        return Ok(n);
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        Err(fatal(
+            "TypeError",
+            format!("Unsupported operand types: {} {} {}", a.type_name(), op, b.type_name()),
+        ))
+    }
+
+    /// Convert an operand of %, a bitwise operator or a shift to an int, as PHP does for floats that are not whole
+    fn checked_int(&mut self, v: &Value, a: &Value, b: &Value, op: &str) -> Result<i64> {
+        let n = self.number_value(v, a, b, op)?;
+        if let Value::Float(f) = n {
+            if !((-9223372036854775808.0..9223372036854775808.0).contains(&f) && f == f.trunc()) {
+                let message = match v {
+                    Value::String(s) => format!(
+                        "Implicit conversion from float-string \"{}\" to int loses precision",
+                        String::from_utf8_lossy(&s.bytes())
+                    ),
+                    _ => format!("Implicit conversion from float {} to int loses precision", format_float(f)),
+                };
+                self.diagnostic("Deprecated", &message)?;
+            }
+        }
+        Ok(n.to_int())
+    }
+
+    /// Apply &, | or ^. Two strings are combined byte by byte, to the length
+    /// of the shorter one except for | which keeps the rest of the longer one.
+    fn bitwise(&mut self, instruction: &Instruction, a: &Value, b: &Value) -> Result<Value> {
+        let (op, apply): (&str, fn(u8, u8) -> u8) = match instruction {
+            Instruction::BitwiseAnd => ("&", |x, y| x & y),
+            Instruction::BitwiseOr => ("|", |x, y| x | y),
+            _ => ("^", |x, y| x ^ y),
+        };
+
+        if let (Value::String(x), Value::String(y)) = (a, b) {
+            let (x, y) = (x.bytes(), y.bytes());
+            let mut bytes: Vec<u8> = x.iter().zip(y.iter()).map(|(&x, &y)| apply(x, y)).collect();
+            if op == "|" {
+                let longer = if x.len() >= y.len() { &x } else { &y };
+                bytes.extend_from_slice(&longer[bytes.len()..]);
+            }
+            return Ok(Value::string(&bytes));
+        }
+
+        let x = self.checked_int(a, a, b, op)?;
+        let y = self.checked_int(b, a, b, op)?;
+        Ok(Value::Int(match op {
+            "&" => x & y,
+            "|" => x | y,
+            _ => x ^ y,
+        }))
+    }
+
+    fn arithmetic(&mut self, instruction: &Instruction, a: &Value, b: &Value) -> Result<Value> {
+        let op = match instruction {
+            Instruction::Add => "+",
+            Instruction::Subtract => "-",
+            Instruction::Multiply => "*",
+            Instruction::Divide => "/",
+            Instruction::Power => "**",
+            _ => "%",
+        };
+
+        // Adding two arrays keeps the elements of the first, and adds those of the second with other keys
+        if let (Instruction::Add, Value::Array(x), Value::Array(y)) = (instruction, a, b) {
+            let mut result = x.clone();
+            Rc::make_mut(&mut result).union(y);
+            return Ok(Value::Array(result));
+        }
+
+        // Modulo works on integers, converting floats first
+        if let Instruction::Modulo = instruction {
+            let x = self.checked_int(a, a, b, op)?;
+            let divisor = self.checked_int(b, a, b, op)?;
+            if divisor == 0 {
+                return Err(fatal("DivisionByZeroError", "Modulo by zero"));
+            }
+            return Ok(Value::Int(if divisor == -1 { 0 } else { x % divisor }));
+        }
+
+        let x = self.number_value(a, a, b, op)?;
+        let y = self.number_value(b, a, b, op)?;
+
+        // Integer results that overflow become floats
+        let int_result = match (&x, &y) {
+            (Value::Int(x), Value::Int(y)) => match instruction {
+                Instruction::Add => x.checked_add(*y),
+                Instruction::Subtract => x.checked_sub(*y),
+                Instruction::Multiply => x.checked_mul(*y),
+                Instruction::Power => int_power(*x, *y),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(result) = int_result {
+            return Ok(Value::Int(result));
+        }
+
+        let (x, y) = match instruction {
+            Instruction::Add => return Ok(Value::Float(x.as_float() + y.as_float())),
+            Instruction::Subtract => return Ok(Value::Float(x.as_float() - y.as_float())),
+            Instruction::Multiply => return Ok(Value::Float(x.as_float() * y.as_float())),
+            Instruction::Power => return Ok(Value::Float(x.as_float().powf(y.as_float()))),
+            _ => (x, y),
+        };
+
+        if y.as_float() == 0.0 {
+            return Err(fatal("DivisionByZeroError", "Division by zero"));
+        }
+        // Integer division only stays an integer when it is exact
+        if let (Value::Int(x), Value::Int(y)) = (&x, &y) {
+            if let Some(0) = x.checked_rem(*y) {
+                return Ok(Value::Int(x / y));
+            }
+        }
+        Ok(Value::Float(x.as_float() / y.as_float()))
+    }
+
+    /// Read container[key]
+    fn array_get(&mut self, container: &Value, key: &Value) -> Result<Value> {
+        match container {
+            Value::Array(arr) => {
+                let key = to_key(key, "array")?;
+                if let Some(value) = arr.get(&key) {
+                    return Ok(value.clone());
+                }
+                match key {
+                    Key::Int(i) => self.warning(&format!("Undefined array key {}", i))?,
+                    Key::String(s) => {
+                        self.warning(&format!("Undefined array key \"{}\"", String::from_utf8_lossy(&s)))?
+                    }
+                }
+            }
+            Value::String(s) => {
+                // String offsets read a single byte, counting from the end when negative
+                let offset = string_offset(key)?;
+                let bytes = s.bytes();
+                let i = if offset < 0 { offset + bytes.len() as i64 } else { offset };
+                if i < 0 || i >= bytes.len() as i64 {
+                    self.warning(&format!("Uninitialized string offset {}", offset))?;
+                    return Ok(Value::string(b""));
+                }
+                return Ok(Value::string(&bytes[i as usize..i as usize + 1]));
+            }
+            Value::Object(obj) => {
+                return Err(fatal("Error", format!("Cannot use object of type {} as array", obj.class)));
+            }
+            _ => self.warning(&format!(
+                "Trying to access array offset on value of type {}",
+                container.type_name()
+            ))?,
+        }
+        Ok(Value::Null)
+    }
+
+    /// Read $var[k1]...[kn] for a compound assignment; [] reads null
+    fn load_element(&mut self, var: Value, dims: &[bool], keys: &[Value]) -> Result<Value> {
+        let mut current = var;
+        let mut keys = keys.iter();
+
+        for &has_key in dims {
+            // Missing containers are created by the store that follows
+            let key = match keys.next() {
+                Some(key) if has_key && !matches!(current, Value::Null) => key,
+                _ => return Ok(Value::Null),
+            };
+            current = self.array_get(&current, key)?;
+        }
+        Ok(current)
+    }
+
+    /// $var[k1]...[kn] = value, with the keys of the dimensions that have one
+    fn store_element(&mut self, slot: &mut Value, dims: &[bool], keys: &[Value], value: Value) -> Result<()> {
+        let Some((&has_key, inner_dims)) = dims.split_first() else {
+            *slot = value;
+            return Ok(());
+        };
+        let (key, inner_keys) = if has_key { (Some(&keys[0]), &keys[1..]) } else { (None, keys) };
+
+        match slot {
+            Value::Null => {
+                // Writing to an element of null creates the array
+                *slot = Value::Array(Rc::default());
+            }
+            Value::Bool(false) => {
+                self.diagnostic("Deprecated", "Automatic conversion of false to array is deprecated")?;
+                *slot = Value::Array(Rc::default());
+            }
+            Value::String(_) => {
+                let Some(key) = key else {
+                    return Err(fatal("Error", "[] operator not supported for strings"));
+                };
+                if !inner_dims.is_empty() {
+                    return Err(fatal("Error", "Cannot use string offset as an array"));
+                }
+                return self.store_string_offset(slot, key, &value);
+            }
+            Value::Array(_) => {}
+            Value::Object(obj) => {
+                return Err(fatal("Error", format!("Cannot use object of type {} as array", obj.class)));
+            }
+            _ => return Err(fatal("Error", "Cannot use a scalar value as an array")),
+        }
+
+        let Value::Array(arr) = slot else {
+            unreachable!("slot was made an array above");
+        };
+        let arr = Rc::make_mut(arr);
+        let element = match key {
+            Some(key) => arr.lookup_or_add(to_key(key, "array")?),
+            None => match arr.append() {
+                Some(element) => element,
+                None => {
+                    return self.warning("Cannot add element to the array as the next element is already occupied");
+                }
+            },
+        };
+        self.store_element(element, inner_dims, inner_keys, value)
+    }
+
+    /// Replace one byte of the string in a slot, padding it with spaces when the offset is past the end
+    fn store_string_offset(&mut self, slot: &mut Value, key: &Value, value: &Value) -> Result<()> {
+        let offset = string_offset(key)?;
+        let mut bytes = slot.to_php_string().bytes().to_vec();
+        let i = if offset < 0 { offset + bytes.len() as i64 } else { offset };
+        if i < 0 {
+            return self.warning(&format!("Illegal string offset {}", offset));
+        }
+        let s = self.string_value(value)?;
+        let s = s.bytes();
+        if s.is_empty() {
+            return Err(fatal("Error", "Cannot assign an empty string to a string offset"));
+        }
+        if s.len() > 1 {
+            self.warning("Only the first byte will be assigned to the string offset")?;
+        }
+
+        let i = i as usize;
+        if i >= bytes.len() {
+            bytes.resize(i + 1, b' ');
+        }
+        bytes[i] = s[0];
+        *slot = Value::string(&bytes);
+        Ok(())
+    }
+
+    fn echo(&mut self, v: &Value) -> Result<()> {
+        match v {
+            Value::Int(i) => write!(self.out, "{}", i)?,
+            v => {
+                let s = self.string_value(v)?;
+                self.out.write_all(&s.bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn warning(&mut self, message: &str) -> Result<()> {
+        self.diagnostic("Warning", message)
+    }
+
+    /// Report a non-fatal error on stderr, after the output so far
+    fn diagnostic(&mut self, level: &str, message: &str) -> Result<()> {
+        self.out.flush()?;
+        eprintln!("PHP {}:  {}", level, message);
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+<?php
+// Test do-while loops and the alternative syntax of control structures
+echo "Testing do-while and alternative syntax:";
+
+// The body of a do-while runs before its condition is checked
+$i = 10;
+do {
+    echo "do " . $i;
+    $i++;
+} while ($i < 3);
+
+$n = 0;
+do {
+    $n++;
+    if ($n == 2) {
+        continue;
+    }
+    if ($n == 5) {
+        break;
+    }
+    echo "n=" . $n;
+} while ($n < 10);
+
+// if: elseif: else: endif;
+$score = 72;
+if ($score >= 90):
+    echo "A";
+elseif ($score >= 70):
+    echo "C";
+    echo "passed";
+else:
+    echo "F";
+endif;
+
+if ($score < 50):
+    echo "failed";
+endif;
+
+// while: endwhile;
+$count = 3;
+while ($count > 0):
+    echo "count " . $count;
+    $count--;
+endwhile;
+
+// for: endfor;
+for ($j = 0; $j < 5; $j++):
+    if ($j == 1):
+        continue;
+    endif;
+    echo "j=" . $j;
+endfor;
+
+// foreach: endforeach; with a nested alternative if
+$items = ["apple" => 3, "pear" => 0, "plum" => 7];
+foreach ($items as $name => $stock):
+    if ($stock > 0):
+        echo $name . ": " . $stock;
+    else:
+        echo $name . ": sold out";
+    endif;
+endforeach;
+
+// Mixed with regular blocks
+foreach ([1, 2] as $a):
+    for ($b = 1; $b <= 2; $b++) {
+        echo $a * $b;
+    }
+endforeach;
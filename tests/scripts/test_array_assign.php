@@ -0,0 +1,91 @@
+<?php
+// Test assigning to array elements
+echo "Testing element assignment:";
+
+// Overwrite and append
+$arr = [10, 20, 30];
+$arr[1] = 25;
+$arr[] = 40;
+foreach ($arr as $key => $value) {
+    echo $key . " => " . $value;
+}
+
+// Nested targets create the inner arrays
+$config['db']['host'] = "localhost";
+$config['db']['port'] = 5432;
+$config['tags'][] = "a";
+$config['tags'][] = "b";
+echo $config['db']['host'] . ":" . $config['db']['port'];
+echo $config['tags'][1];
+
+// Compound assignment to elements
+$totals = ["x" => 1, "y" => 2];
+$totals["x"] += 10;
+$totals["y"] *= 3;
+echo $totals["x"];
+echo $totals["y"];
+$greeting = ["text" => "Hello"];
+$greeting["text"] .= ", world";
+echo $greeting["text"];
+
+// Assignments are expressions
+$last = ($arr[0] = 7);
+echo $last;
+echo $arr[0] += 5;
+
+// Arrays are copied on write
+$a = [1, 2, 3];
+$b = $a;
+$b[0] = 9;
+echo "a[0] is still:";
+echo $a[0];
+echo $b[0];
+
+$grid = [[1, 2], [3, 4]];
+$copy = $grid;
+$copy[1][0] = 99;
+echo $grid[1][0];
+echo $copy[1][0];
+
+// Functions modify their own copy
+function fill($items) {
+    $items[] = "added";
+    return count_items($items);
+}
+function count_items($items) {
+    $n = 0;
+    foreach ($items as $item) {
+        $n += 1;
+    }
+    return $n;
+}
+$list = ["one"];
+echo fill($list);
+echo count_items($list);
+
+// foreach iterates over the array as it was when the loop started
+$queue = [1, 2, 3];
+foreach ($queue as $item) {
+    $queue[] = $item * 10;
+}
+echo count_items($queue);
+
+// An array appended to itself keeps its old elements
+$nested = [1, 2];
+$nested[] = $nested;
+echo count_items($nested);
+echo count_items($nested[2]);
+
+// Arrays built in a loop and dropped again
+for ($i = 0; $i < 3; $i++) {
+    $row = [$i, [$i * 2]];
+    $rows[] = $row;
+    $row[1][0] = -1;
+}
+echo $rows[2][1][0];
+
+// String offsets
+$word = "cat";
+$word[0] = "b";
+echo $word;
+?>
@@ -0,0 +1,70 @@
+<?php
+// Test heap-allocated arrays with int and string keys
+echo "Testing array keys:";
+
+// A list gets keys 0, 1, 2, ...
+$list = [10, 20, 30];
+echo $list[0];
+echo $list[2];
+
+// Mixed int and string keys
+$person = ["name" => "John", "age" => 30, 5 => "five", "city" => "New York"];
+echo $person["name"];
+echo $person["age"];
+echo $person["city"];
+echo $person[5];
+
+// Appending continues after the largest int key
+$mixed = [5 => "a", "b", "key" => "c", "d"];
+echo $mixed[6];
+echo $mixed[7];
+
+// Numeric string keys are the same as int keys
+$numbers = ["1" => "one", 2 => "two"];
+echo $numbers[1];
+echo $numbers["2"];
+
+// Keys can be any expression
+$prefix = "id";
+$computed = array(-1 => "minus one", $prefix . "_1" => "first", 1 + 1 => "two", "next");
+echo $computed[-1];
+echo $computed["id_1"];
+echo $computed[2];
+echo $computed[3];
+
+// Later keys overwrite earlier ones
+$dup = ["x" => 1, "x" => 2];
+echo $dup["x"];
+
+// Adding arrays keeps the left-hand element for each key
+$union = [1] + [5, 6];
+echo $union[0];
+echo $union[1];
+$union = ["x" => 1] + ["x" => 2, "y" => 3];
+echo $union["x"];
+echo $union["y"];
+$union[] = "appended";
+echo $union[0];
+
+// Arrays outlive the expression that created them
+function make_pair($a, $b) {
+    return [$a, $b];
+}
+$pair = make_pair("left", "right");
+echo $pair[1];
+
+// Arrays can be nested
+$grid = [[1, 2], [3, 4]];
+echo $grid[1][0];
+
+// Many elements
+$big = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19];
+echo $big[19];
+
+// Empty arrays are falsy
+if ([]) {
+    echo "empty array is truthy";
+} else {
+    echo "empty array is falsy";
+}
+?>
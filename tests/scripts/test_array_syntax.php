@@ -0,0 +1,27 @@
+<?php
+// Test the long-form array() literal syntax
+echo "Testing array() syntax:";
+
+$numbers = array(10, 20, 30);
+echo $numbers[2];
+
+// Keyed entries and trailing commas
+$person = array(
+    "name" => "John",
+    "age" => 30,
+);
+echo $person["name"] . " is " . $person["age"];
+
+// Nesting, mixed with short arrays
+$matrix = array(array(1, 2), [3, 4], array("x" => array(5, 6)));
+echo $matrix[0][1];
+echo $matrix[1][0];
+echo $matrix[2]["x"][1];
+
+// An empty array can be filled later
+$list = array();
+$list[] = "first";
+foreach ($list as $i => $item) {
+    echo $i . ": " . $item;
+}
+?>
@@ -0,0 +1,81 @@
+<?php
+// Test bitwise operators, shifts and their compound assignments
+echo "Testing bitwise operators:";
+
+$flags = 6;
+echo $flags & 4;
+echo $flags | 1;
+echo $flags ^ 3;
+echo ~$flags;
+echo 1 << 10;
+echo 1024 >> 3;
+echo -16 >> 2;
+
+// Precedence: shifts bind tighter than comparisons, & tighter than ^ tighter than |
+echo 1 + 1 << 2;
+echo "[" . (1 << 2 > 3) . "]";
+echo 1 | 2 ^ 3 & 4;
+echo "[" . (6 & 3 == 3) . "]";
+echo (6 & 3) == 2 ? "masked" : "not masked";
+
+// Flag checks
+define_flags();
+function define_flags() {
+    $read = 1;
+    $write = 2;
+    $exec = 4;
+    $mode = $read | $exec;
+    if ($mode & $read) {
+        echo "can read";
+    }
+    if (!($mode & $write)) {
+        echo "cannot write";
+    }
+}
+
+// Compound assignments
+$bits = 0;
+$bits |= 8;
+$bits |= 1;
+echo $bits;
+$bits &= ~1;
+echo $bits;
+$bits ^= 15;
+echo $bits;
+$bits <<= 2;
+echo $bits;
+$bits >>= 1;
+echo $bits;
+$masks = [15, 0];
+$masks[1] |= 48;
+$masks[0] &= 3;
+echo $masks[0] . " " . $masks[1];
+
+// Large shifts and sign
+echo 1 << 63;
+echo 1 << 64;
+echo -1 >> 70;
+echo 5 >> 64;
+echo ~0;
+echo 9223372036854775807 & -1;
+
+// Other operand types are converted to ints
+echo true | 2;
+echo null ^ 5;
+echo "12" & 10;
+echo 7.0 & 3;
+echo 7.5 & 3;
+echo "7.5" | 8;
+
+// Two strings are combined byte by byte
+echo "AB" ^ "  ";
+echo "a" | "B";
+echo "abc" & "ab";
+echo "ab" | "abcd";
+echo ~~"abc";
+
+// Concatenation binds more loosely than shifts and arithmetic
+echo "x" . 1 << 2;
+echo "x" . 1 + 2;
+echo 2 + 3 . "y" . 8 >> 1;
+?>
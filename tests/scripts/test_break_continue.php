@@ -0,0 +1,83 @@
+<?php
+// Test break and continue, with and without a loop depth
+echo "Testing break and continue:";
+
+$i = 0;
+while (true) {
+    $i++;
+    if ($i % 2 == 0) {
+        continue;
+    }
+    if ($i > 7) {
+        break;
+    }
+    echo $i;
+}
+
+// continue in a for loop still runs the increment
+for ($j = 0; $j < 6; $j++) {
+    if ($j == 2) {
+        continue;
+    }
+    if ($j == 4) {
+        break;
+    }
+    echo "j=" . $j;
+}
+echo "after for: " . $j;
+
+// Leaving nested loops
+for ($a = 1; $a <= 3; $a++) {
+    for ($b = 1; $b <= 3; $b++) {
+        if ($b == 2) {
+            continue 2;
+        }
+        if ($a == 3) {
+            break 2;
+        }
+        echo $a . "," . $b;
+    }
+}
+echo "a=" . $a;
+
+// foreach loops, nested in each other
+$grid = [[1, 2, 3], [4, 5, 6], [7, 8, 9]];
+foreach ($grid as $row) {
+    foreach ($row as $cell) {
+        if ($cell == 5) {
+            continue 2;
+        }
+        if ($cell == 8) {
+            break 2;
+        }
+        echo $cell;
+    }
+    echo "end of row";
+}
+echo "done with grid";
+
+// break out of a foreach inside a function
+echo find_index([3, 1, 4, 1, 5], 4);
+function find_index($values, $wanted) {
+    $found = -1;
+    foreach ($values as $key => $value) {
+        if ($value == $wanted) {
+            $found = $key;
+            break;
+        }
+    }
+    return $found;
+}
+
+// break 1 is the same as break
+$k = 10;
+while ($k > 0) {
+    $k--;
+    while (true) {
+        break 1;
+    }
+    if ($k == 7) {
+        break;
+    }
+}
+echo "k=" . $k;
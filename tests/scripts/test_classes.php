@@ -0,0 +1,185 @@
+<?php
+// Test classes with properties, methods, constructors and new
+echo "Testing classes:";
+
+class Point {
+    public int $x;
+    public int $y;
+
+    public function __construct($x, $y) {
+        $this->x = $x;
+        $this->y = $y;
+    }
+
+    public function add($other) {
+        return new Point($this->x + $other->x, $this->y + $other->y);
+    }
+
+    public function describe() {
+        return "(" . $this->x . ", " . $this->y . ")";
+    }
+}
+
+$a = new Point(1, 2);
+$b = new Point(10, 20);
+echo $a->add($b)->describe();
+
+// Properties take their default values before the constructor runs
+class Counter {
+    const STEP = 5;
+    const LIMIT = self::STEP * 4;
+
+    private $count = 0;
+    protected $history = [];
+    public $label;
+
+    public function increment() {
+        $this->count += self::STEP;
+        $this->history[] = $this->count;
+        return $this;
+    }
+
+    public function count() {
+        return $this->count;
+    }
+
+    public function history() {
+        return $this->history;
+    }
+
+    public function isFull() {
+        return $this->count >= static::LIMIT;
+    }
+
+    public static function create() {
+        return new self;
+    }
+}
+
+$counter = Counter::create();
+echo $counter->count();
+var_label($counter->label);
+$counter->increment()->increment();
+echo $counter->count();
+foreach ($counter->history() as $value) {
+    echo $value;
+}
+echo $counter->isFull() ? "full" : "not full";
+echo Counter::LIMIT;
+
+function var_label($label) {
+    echo $label === null ? "no label" : $label;
+}
+
+// Objects are handles, so every copy changes the same object
+$c = new Point(0, 0);
+$d = $c;
+$d->x = 7;
+$d->y++;
+echo $c->describe();
+echo $c === $d ? "same" : "different";
+echo $c == new Point(7, 1) ? "equal" : "not equal";
+echo $c === new Point(7, 1) ? "identical" : "not identical";
+
+// Static methods and self
+class MathUtil {
+    public static function square($n) {
+        return $n * $n;
+    }
+
+    public static function sumOfSquares($a, $b) {
+        return self::square($a) + self::square($b);
+    }
+}
+echo MathUtil::sumOfSquares(3, 4);
+echo MathUtil::class;
+
+// Closures in a method are bound to its object
+class Multiplier {
+    public $factor = 3;
+
+    public function scaleAll($items) {
+        $result = [];
+        foreach ($items as $item) {
+            $scale = fn($n) => $n * $this->factor;
+            $result[] = $scale($item);
+        }
+        return $result;
+    }
+}
+$m = new Multiplier();
+$m->factor = 4;
+foreach ($m->scaleAll([1, 2, 3]) as $value) {
+    echo $value;
+}
+
+// Typed properties convert the scalars assigned to them
+class Settings {
+    public int $size = 0;
+    public float $ratio = 1;
+    public string $title = "";
+    public bool $enabled = false;
+}
+$settings = new Settings();
+$inputs = ["42", "0.5", 3.5, "yes"];
+$settings->size = $inputs[0];
+$settings->ratio = $inputs[1];
+$settings->title = $inputs[2];
+$settings->enabled = $inputs[3];
+echo $settings->size + 1;
+echo $settings->ratio * 4;
+echo $settings->title . "!";
+echo $settings->enabled ? "enabled" : "disabled";
+
+// Typed properties without a default are uninitialized until they are assigned
+class Account {
+    public string $owner;
+    public array $log;
+}
+$account = new Account();
+echo $account->owner ?? "no owner";
+foreach ($account as $name => $value) {
+    echo "not reached";
+}
+$account->owner = "ann";
+$account->log[] = "opened";
+echo $account->owner . " " . $account->log[0];
+
+// Nullable types also take null
+class Profile {
+    public ?string $nickname = null;
+    public ?int $age;
+}
+$profile = new Profile();
+echo $profile->nickname ?? "no nickname";
+$profile->nickname = 7;
+$profile->age = "30";
+echo $profile->nickname . " is " . ($profile->age + 1);
+$profile->age = null;
+echo $profile->age ?? "age unknown";
+
+// A variable assigned in a branch may hold anything afterwards, so only the runtime checks it
+$sizes = [];
+if ($settings->enabled) {
+    $sizes = 5;
+}
+$settings->size = $sizes;
+echo $settings->size;
+$shape = new Settings();
+if ($settings->enabled) {
+    $shape = new Multiplier();
+}
+echo $shape->scaleAll([2])[0];
+
+// Assigning a property the class does not declare creates it, which is deprecated
+$point = new Point(1, 2);
+$point->label = "origin";
+$point->tags[] = "start";
+echo $point->label . " " . $point->tags[0];
+foreach ($point as $name => $value) {
+    echo $name;
+}
+
+// A method needs every argument
+$points = [$a, $b];
+echo $points[0]->add();
@@ -0,0 +1,77 @@
+<?php
+// Test anonymous functions, arrow functions and calls through variables
+echo "Testing closures:";
+
+// use captures by value when the closure is created
+$greeting = "Hello";
+$greet = function ($name) use ($greeting) {
+    return $greeting . ", " . $name;
+};
+$greeting = "Bye";
+echo $greet("World");
+
+// Arrow functions capture the variables they use automatically
+$factor = 3;
+$triple = fn($x) => $x * $factor;
+echo $triple(5);
+$factor = 10;
+echo $triple(5);
+
+// Nested arrow functions capture through the outer one
+$offset = 100;
+$adder = fn($x) => fn($y) => $x + $y + $offset;
+$add_one = $adder(1);
+echo $add_one(2);
+
+// Closures passed to and returned from functions
+function apply_twice($f, $value) {
+    return $f($f($value));
+}
+echo apply_twice(fn($n) => $n * $n, 3);
+
+function make_counter($start) {
+    return function ($step) use ($start) {
+        return $start + $step;
+    };
+}
+$counter = make_counter(40);
+echo $counter(2);
+
+// Immediately invoked closure
+echo (function () {
+    return "invoked";
+})();
+
+// A captured array is a copy, later changes on either side are not shared
+$list = [1, 2, 3];
+$sum = function () use ($list) {
+    $list[] = 4;
+    $total = 0;
+    foreach ($list as $item) {
+        $total += $item;
+    }
+    return $total;
+};
+$list[] = 100;
+echo $sum();
+echo count_of($list);
+
+function count_of($items) {
+    $n = 0;
+    foreach ($items as $item) {
+        $n++;
+    }
+    return $n;
+}
+
+// Closures in arrays, and comparisons
+$ops = ["double" => fn($x) => $x * 2, "negate" => fn($x) => -$x];
+echo $ops["double"](21);
+echo $ops["negate"](7);
+$same = $triple;
+echo $same === $triple ? "same" : "different";
+echo $triple == $greet ? "equal" : "not equal";
+
+// Extra arguments are ignored, too few are an error
+echo $triple(1, 2, 3);
+echo $greet();
@@ -0,0 +1,61 @@
+<?php
+// Test floating-point numbers
+echo "Testing floats:";
+
+// Float literals keep their fractional part
+$price = 3.7;
+echo $price;
+echo 2.5e3;
+echo 1.5e-7;
+echo 0.0001;
+
+// Arithmetic between floats and ints
+echo $price + 1;
+echo 10 - 0.25;
+echo 1.5 * 4;
+echo 1 / 3;
+echo 7.5 / 2.5;
+echo -$price;
+
+// PHP prints 14 significant digits
+echo 0.1 + 0.2;
+echo 2 / 3 * 1000000;
+echo 1e15;
+echo 123456789012345.0;
+echo -0.0;
+echo 1e308 * 10;
+echo -1e308 * 10;
+
+// Comparisons
+if ($price > 3) {
+    echo "3.7 > 3";
+}
+if (0.5 < 1) {
+    echo "0.5 < 1";
+}
+if (2.0 == 2) {
+    echo "2.0 == 2";
+}
+if (0.1 + 0.2 != 0.3) {
+    echo "0.1 + 0.2 != 0.3";
+}
+if (1.5 >= 1.5) {
+    echo "1.5 >= 1.5";
+}
+
+// Floats in strings
+echo "Total: " . 19.99 * 3;
+
+// A loop accumulating floats
+$sum = 0.0;
+$i = 0;
+while ($i < 10) {
+    $sum = $sum + 0.1;
+    $i = $i + 1;
+}
+echo $sum;
+
+// Modulo converts floats to ints, with a deprecation when that loses precision
+echo 7.0 % 2;
+echo 3 % 2.9;
+?>
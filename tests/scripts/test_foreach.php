@@ -0,0 +1,54 @@
+<?php
+// Test foreach loops
+echo "Testing foreach:";
+
+// Value-only form
+$numbers = [10, 20, 30];
+$sum = 0;
+foreach ($numbers as $n) {
+    $sum = $sum + $n;
+}
+echo "Sum:";
+echo $sum;
+
+// Key and value, in insertion order
+$person = ["name" => "John", "age" => 30, 7 => "seven", "city" => "New York"];
+foreach ($person as $key => $value) {
+    echo $key . ": " . $value;
+}
+
+// Nested loops
+$grid = [[1, 2], [3, 4]];
+foreach ($grid as $row) {
+    $line = "";
+    foreach ($row as $cell) {
+        $line .= $cell . " ";
+    }
+    echo $line;
+}
+
+// Empty arrays run the body zero times
+foreach ([] as $nothing) {
+    echo "never printed";
+}
+echo "after empty foreach";
+
+// Returning from inside a foreach
+function find_first_even($values) {
+    foreach ($values as $index => $v) {
+        if ($v % 2 == 0) {
+            return $index;
+        }
+    }
+    return -1;
+}
+echo "First even at:";
+echo find_first_even([3, 5, 8, 10]);
+
+// Iterating over something that is not an array warns and skips the loop
+$notArray = 42;
+foreach ($notArray as $x) {
+    echo "never printed";
+}
+echo "done";
+?>
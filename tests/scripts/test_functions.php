@@ -0,0 +1,57 @@
+<?php
+// Test user-defined functions
+echo "Testing functions:";
+
+// Functions can be called before they are declared
+echo "Square of 7:";
+echo square(7);
+
+function square($x) {
+    return $x * $x;
+}
+
+// Multiple parameters
+function add3($a, $b, $c) {
+    return $a + $b + $c;
+}
+echo "1 + 2 + 3 =";
+echo add3(1, 2, 3);
+
+// Recursion
+function fib($n) {
+    if ($n < 2) {
+        return $n;
+    }
+    return fib($n - 1) + fib($n - 2);
+}
+echo "fib(15) =";
+echo fib(15);
+
+// Deep recursion
+function depth($n) {
+    if ($n == 0) {
+        return 0;
+    }
+    return 1 + depth($n - 1);
+}
+echo "depth(50000) =";
+echo depth(50000);
+
+// Functions keep their own variables
+function countdown($n) {
+    $i = $n;
+    while ($i > 0) {
+        echo $i;
+        $i = $i - 1;
+    }
+}
+$i = 100;
+countdown(3);
+echo "i is still:";
+echo $i;
+
+// A call nested in an expression
+$total = 10 + square(3) * 2;
+echo "10 + square(3) * 2 =";
+echo $total;
+?>
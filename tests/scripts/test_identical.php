@@ -0,0 +1,63 @@
+<?php
+// Test strict comparison with === and !==
+echo "Testing identity comparison:";
+
+// Same value, different types are only loosely equal
+if (1 == "1") {
+    echo "1 == \"1\"";
+}
+if (1 !== "1") {
+    echo "1 !== \"1\"";
+}
+if (1 !== 1.0) {
+    echo "1 !== 1.0";
+}
+if (0 == false) {
+    echo "0 == false";
+}
+if (0 !== false) {
+    echo "0 !== false";
+}
+if (null !== false) {
+    echo "null !== false";
+}
+if ("abc" == 0) {
+    echo "not reached";
+}
+
+// Same type and value
+echo "[" . (5 === 5) . "]";
+echo "[" . (2.5 === 2.5) . "]";
+echo "[" . ("abc" === "ab" . "c") . "]";
+echo "[" . ("10" === "1e1") . "]";
+echo "[" . ("10" == "1e1") . "]";
+echo "[" . (null === null) . "]";
+echo "[" . (true === true) . "]";
+$x = 0.1 + 0.2;
+echo "[" . ($x === 0.3) . "]";
+
+// Arrays need the same keys, order and element types
+$a = [1, 2, 3];
+$b = [1, 2, 3];
+echo "[" . ($a === $b) . "]";
+echo "[" . ($a === [1, 2, "3"]) . "]";
+echo "[" . ($a == [1, 2, "3"]) . "]";
+$c = ["x" => 1, "y" => 2];
+$d = ["y" => 2, "x" => 1];
+echo "[" . ($c == $d) . "]";
+echo "[" . ($c === $d) . "]";
+$e = $c;
+echo "[" . ($c === $e) . "]";
+$e["x"] = 1;
+echo "[" . ($c === $e) . "]";
+echo "[" . ([[1], ["a" => null]] === [[1], ["a" => null]]) . "]";
+
+// Results from functions and variables
+function answer() {
+    return 42;
+}
+$n = 42;
+if (answer() === $n && answer() !== "42") {
+    echo "strict ok";
+}
+?>
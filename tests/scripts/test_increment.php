@@ -0,0 +1,89 @@
+<?php
+// Test prefix and postfix increment and decrement
+echo "Testing increment and decrement:";
+
+// Prefix yields the new value, postfix the old one
+$i = 5;
+echo $i++;
+echo $i;
+echo ++$i;
+echo $i--;
+echo --$i;
+echo $i;
+
+// Counting loops
+for ($n = 0; $n < 3; $n++) {
+    echo "n = " . $n;
+}
+$total = 0;
+$k = 3;
+while ($k-- > 0) {
+    $total += 10;
+}
+echo $total . " " . $k;
+
+// Floats, null and overflow
+$f = 1.5;
+$f++;
+echo $f;
+$null = null;
+$null++;
+echo $null;
+$stays = null;
+$stays--;
+echo "[" . $stays . "]";
+$big = 9223372036854775807;
+$big++;
+echo $big;
+$small = -9223372036854775807 - 1;
+$small--;
+echo $small;
+
+// Numeric strings become numbers, others count like a counter
+$s = "9";
+$s++;
+echo $s + 1;
+$s = "1.5";
+$s--;
+echo $s;
+$letters = ["a", "z", "Az", "zz", "a9", "Zz", "9z", "a-z", "", "5a"];
+foreach ($letters as $letter) {
+    $before = $letter;
+    $letter++;
+    echo $before . " -> " . $letter;
+}
+$word = "abc";
+$word--;
+echo $word;
+$empty = "";
+$empty--;
+echo $empty;
+
+// Bools are unchanged
+$flag = true;
+$flag++;
+echo $flag;
+
+// Array elements, with keys evaluated once
+$counts = ["a" => 1];
+$counts["a"]++;
+++$counts["a"];
+echo $counts["a"];
+echo $counts["a"]--;
+echo $counts["a"];
+$counts["b"] = 0;
+echo ++$counts["b"];
+$grid = [[0, 0], [0, 0]];
+$row = 0;
+$grid[$row++][1]++;
+echo $grid[0][1] . $grid[1][1] . $row;
+$letters[1]++;
+echo $letters[1];
+$old = $grid[1][0]++;
+echo $old . $grid[1][0];
+
+// Increments inside expressions
+$j = 1;
+$sum = $j++ + $j++;
+echo $sum . " " . $j;
+?>
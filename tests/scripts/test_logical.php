@@ -0,0 +1,58 @@
+<?php
+// Test short-circuit evaluation of &&, ||, and, or, and the keyword precedence of and, xor, or
+echo "Testing logical operators:";
+
+function noisy($label, $result) {
+    echo "evaluated " . $label;
+    return $result;
+}
+
+// The right operand only runs when the left one doesn't decide the result
+$x = 0;
+if ($x != 0 && 10 / $x > 1) {
+    echo "not reached";
+} else {
+    echo "no division by zero";
+}
+
+$a = noisy("a1", false) && noisy("a2", true);
+$b = noisy("b1", true) || noisy("b2", false);
+$c = noisy("c1", true) and noisy("c2", true);
+$d = noisy("d1", false) or noisy("d2", true);
+
+// The result is always a bool
+echo "[" . $a . "]";
+echo 5 && "x";
+echo "[" . (0 || 0.0) . "]";
+echo "[" . (null || "0") . "]";
+echo "[" . ("a" || "b") . "]";
+
+// Chains stop at the first operand that decides the result
+$n = 0;
+$found = $n > 1 || $n == 0 || noisy("never", true);
+echo $found;
+$all = $n == 0 && $n < 1 && noisy("last", false);
+if (!$all) {
+    echo "not all";
+}
+
+// The keyword forms bind more loosely than assignment
+$k = true and false;
+echo "[" . $k . "]";
+$k = false or true;
+echo "[" . $k . "]";
+$k = true xor true;
+echo "[" . $k . "]";
+echo "[" . (true xor false) . "]";
+echo "[" . (true xor true) . "]";
+echo "[" . (false or true and false) . "]";
+echo "[" . (true xor true or true) . "]";
+$done = false or noisy("fallback", true);
+
+// Mixed with comparisons and negation
+$i = 3;
+while ($i > 0 && !($i == 1)) {
+    echo $i;
+    $i = $i - 1;
+}
+?>
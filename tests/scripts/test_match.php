@@ -0,0 +1,77 @@
+<?php
+// Test match expressions with strict comparison and several conditions per arm
+echo "Testing match:";
+
+function http_status($code) {
+    return match ($code) {
+        200, 201, 204 => "success",
+        301, 302 => "redirect",
+        404 => "not found",
+        default => "error",
+    };
+}
+echo http_status(201);
+echo http_status(302);
+echo http_status(404);
+echo http_status(500);
+
+// Strict comparison: "1" and 1.0 do not match 1
+function kind($value) {
+    return match ($value) {
+        1 => "int one",
+        "1" => "string one",
+        1.0 => "float one",
+        true => "true",
+        null => "null",
+        default => "something else",
+    };
+}
+echo kind(1);
+echo kind("1");
+echo kind(1.0);
+echo kind(true);
+echo kind(null);
+echo kind(0);
+
+// match(true) with conditions that are evaluated in order
+$age = 34;
+echo match (true) {
+    $age < 18 => "minor",
+    $age < 65 => "adult",
+    default => "senior",
+};
+
+// Only the chosen arm is evaluated, and conditions stop at the first match
+function noisy($value) {
+    echo "checking " . $value;
+    return $value;
+}
+$x = 2;
+echo match ($x) {
+    noisy(1) => noisy("one"),
+    noisy(2) => noisy("two"),
+    noisy(3) => noisy("three"),
+};
+
+// match is an expression
+$sizes = ["s" => 1, "m" => 2];
+$total = 0;
+foreach ($sizes as $size => $count) {
+    $total += $count * match ($size) { "s" => 10, "m" => 20 };
+}
+echo $total;
+
+// With a literal subject only one arm can match, and the compiler warns about the rest
+echo match (2) {
+    1 => "one",
+    2 => "two",
+    2, 3 => "three",
+    default => "other",
+};
+
+// Without a matching arm, an UnhandledMatchError is thrown
+echo match ($x) {
+    1 => "one",
+    3 => "three",
+};
+echo "not reached";
@@ -0,0 +1,37 @@
+<?php
+// Test the exponent and spaceship operators
+echo "Testing ** and <=>:";
+
+echo 2 ** 10;
+echo 2 ** 0.5;
+echo 2 ** -1;
+echo 10 ** 20;
+echo 2.5 ** 2;
+echo "3" ** 2;
+
+// Right-associative and binding tighter than unary minus
+echo 2 ** 3 ** 2;
+echo -2 ** 2;
+echo (-2) ** 2;
+echo 2 * 3 ** 2;
+
+$x = 3;
+$x **= 3;
+echo $x;
+
+// Three-way comparison
+echo 1 <=> 2;
+echo 2 <=> 2;
+echo 3 <=> 2;
+echo 1.5 <=> 1.5;
+echo "a" <=> "b";
+echo "10" <=> "9";
+echo [1, 2] <=> [1, 3];
+echo null <=> false;
+echo 1 + 1 <=> 2;
+
+// In a comparator
+echo compare_desc(5, 9);
+function compare_desc($a, $b) {
+    return $b <=> $a;
+}
@@ -0,0 +1,51 @@
+<?php
+// Test building strings with concatenation
+echo "Testing string building:";
+
+// Concatenated strings outlive the expression that built them
+$greeting = "Hello" . ", " . "World";
+$message = $greeting . "!";
+echo $message;
+
+// Numbers are converted when concatenated
+$label = "Total: " . 42;
+echo $label;
+echo "Pi is about " . 3.14;
+
+// Building a long string with .=
+$line = "";
+$i = 0;
+while ($i < 1000) {
+    $line .= "ab";
+    $i = $i + 1;
+}
+echo "Length check:";
+echo $line[0] . $line[1999];
+
+// A copy is not changed by appending to the original
+$base = "base";
+$copy = $base . "-1";
+$other = $base . "-2";
+$copy .= "!";
+echo $copy;
+echo $other;
+echo $base;
+
+// A shared buffer outlives the string that created it
+$first = "shared" . "-";
+$second = $first . "tail";
+$first = "gone";
+$second .= "!";
+echo $second;
+echo $first;
+
+// A large output
+$out = "";
+$n = 0;
+while ($n < 100000) {
+    $out .= "x";
+    $n = $n + 1;
+}
+echo "Built 100000 characters:";
+echo $out[99999];
+?>
@@ -29,4 +29,10 @@ $prefix = "Value: ";
 $value = 42;
 $suffix = " (The answer)";
 echo $prefix, $value, $suffix;
+
+// Built-in string functions
+echo strlen($prefix . $value);
+echo substr("Hello, World", 7);
+echo substr("Hello, World", -5, 3);
+echo substr("Hello, World", 0, -7);
 ?>
@@ -0,0 +1,114 @@
+<?php
+// Test switch with loose matching, fallthrough, default and break
+echo "Testing switch:";
+
+// Dense integer cases, compiled to a jump table
+for ($day = 0; $day <= 8; $day++) {
+    switch ($day) {
+        case 1:
+            echo "Monday";
+            break;
+        case 2:
+            echo "Tuesday";
+            break;
+        case 3:
+        case 4:
+            echo "Midweek";
+            break;
+        case 5:
+            echo "Friday";
+            // Falls through
+        case 6:
+            echo "Weekend is near";
+            break;
+        default:
+            echo "No day " . $day;
+    }
+}
+
+// Non-integer subjects still match integer cases loosely
+$subjects = ["2", 3.0, true, null, "abc"];
+foreach ($subjects as $subject) {
+    switch ($subject) {
+        case 0:
+            echo "zero";
+            break;
+        case 1:
+            echo "one";
+            break;
+        case 2:
+            echo "two";
+            break;
+        case 3:
+            echo "three";
+            break;
+        default:
+            echo "other";
+            break;
+    }
+}
+
+// String cases, with the default in the middle
+function color_code($color) {
+    switch ($color) {
+        case "red":
+            return 1;
+        default:
+            return 0;
+        case "green":
+            return 2;
+        case "blue":
+            return 3;
+    }
+}
+echo color_code("green");
+echo color_code("purple");
+echo color_code("blue");
+
+// Sparse and negative cases, case expressions and duplicates
+function describe($n) {
+    $half = 50;
+    switch ($n) {
+        case -1:
+            return "minus one";
+        case 1000:
+            return "thousand";
+        case $half * 2:
+            return "hundred";
+        case 1000:
+            return "never reached";
+    }
+    return "unknown";
+}
+echo describe(-1);
+echo describe(100);
+echo describe(1000);
+echo describe(7);
+
+// No matching case and no default
+switch (42) {
+    case 1:
+        echo "not printed";
+}
+echo "after empty switch";
+
+// continue 2 and break 2 leave the switch and go on with the loop
+foreach ([1, 2, 3, 4] as $n) {
+    switch ($n % 2) {
+        case 0:
+            continue 2;
+        case 1:
+            if ($n == 3) {
+                break 2;
+            }
+            break;
+    }
+    echo "odd " . $n;
+}
+
+// A semicolon may end a case label
+switch ("x") {
+    case "x";
+        echo "semicolon case";
+        break;
+}
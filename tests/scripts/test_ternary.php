@@ -0,0 +1,68 @@
+<?php
+// Test the conditional (?:), short ternary and null coalescing operators
+echo "Testing conditional operators:";
+
+function noisy($label, $result) {
+    echo "evaluated " . $label;
+    return $result;
+}
+
+// Only the branch that is taken is evaluated
+$n = 5;
+echo $n > 3 ? "big" : "small";
+echo $n > 10 ? noisy("then", "big") : noisy("else", "small");
+$parity = $n % 2 == 0 ? "even" : "odd";
+echo $n . " is " . $parity;
+$x = 0;
+echo $x != 0 ? 10 / $x : "no division";
+
+// Branches may have different types
+$value = $n > 3 ? 1.5 : "none";
+echo $value * 2;
+
+// Nesting needs parentheses
+echo $n < 0 ? "negative" : ($n == 0 ? "zero" : "positive");
+echo ($n > 0 ? "a" : "b") . ($n > 9 ? "c" : "d");
+
+// The short form yields the condition when it is truthy
+echo "" ?: "default";
+echo "set" ?: "default";
+echo 0 ?: null ?: "last";
+echo noisy("once", 7) ?: "never";
+
+// Null coalescing reads missing variables and keys without warnings
+$config = ["name" => "oxiphant", "debug" => false, "level" => null];
+echo $config["name"] ?? "unnamed";
+echo $config["missing"] ?? "fallback";
+echo $config["level"] ?? "no level";
+echo "[" . ($config["debug"] ?? "not reached") . "]";
+echo $undefined ?? "undefined";
+echo $undefined["deep"]["er"] ?? "deep default";
+echo $config["name"]["x"] ?? "no offset";
+echo $config["name"][2] ?? "no char";
+echo $n[0] ?? "not an array";
+echo $config["missing"] ?? $undefined ?? "chained";
+echo $config["name"] ?? noisy("not evaluated", "x");
+
+// Null coalescing assignment only assigns when the target is null
+$count = null;
+$count ??= 10;
+$count ??= 20;
+echo $count;
+$config["level"] ??= 3;
+$config["name"] ??= "renamed";
+$config["new"] ??= "added";
+echo $config["level"] . " " . $config["name"] . " " . $config["new"];
+$cache = [];
+$cache["a"]["b"] ??= "nested";
+echo $cache["a"]["b"];
+echo $total ??= 100;
+
+// The keys of the target are evaluated once
+$slots = [];
+$next = 0;
+$slots[$next++] ??= "first";
+$slots[$next++] ??= "second";
+$slots[0] ??= "ignored";
+echo $next . " " . $slots[0] . " " . $slots[1];
+?>
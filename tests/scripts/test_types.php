@@ -0,0 +1,47 @@
+<?php
+// Test that values keep their type at runtime
+echo "Testing value types:";
+
+// Large integers are printed as numbers
+echo 250000;
+echo 9000000000;
+
+// Floats
+echo 1.5;
+echo 7 / 2;
+echo 0.1 + 0.2;
+
+// Integer overflow turns into a float
+echo 9223372036854775807 + 1;
+
+// Booleans and null
+echo true;
+echo "false and null print nothing:";
+echo false;
+echo null;
+
+// Strings stay strings
+$s = "12 apples";
+echo $s;
+echo "10" + 5;
+
+// Comparisons look at the real types
+if ("abc" == "abc") {
+    echo "abc == abc";
+}
+if ("10" == "1e1") {
+    echo "10 == 1e1";
+}
+if (100 == "1e2") {
+    echo "100 == 1e2";
+}
+if ("abc" != 0) {
+    echo "abc != 0";
+}
+if (1.5 > 1) {
+    echo "1.5 > 1";
+}
+if (null == false) {
+    echo "null == false";
+}
+?>